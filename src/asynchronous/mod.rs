@@ -0,0 +1,174 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Asynchronous removal for Tokio, enabled by the `async` crate feature.
+//!
+//! Removals run on Tokio's blocking thread pool using the same [`Monitor`] as background removals,
+//! which is cancelled when the returned future is dropped.
+//!
+//! [`Monitor`]: ../background/struct.Monitor.html
+
+#[cfg(test)]
+mod tests;
+
+use crate::{background::Monitor, remove, RemovalProgress, RemovalReport, RemoveOptions};
+use std::{
+    future::Future,
+    io,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{sync::watch, task::JoinHandle};
+
+/// Future resolving to the result of a removal running on Tokio's blocking thread pool, returned
+/// by [`remove_dir_all_with_async`](fn.remove_dir_all_with_async.html).
+///
+/// Dropping the future before it resolves cancels the removal, which stops before removing any
+/// further entries. Entries removed before then are not restored.
+#[derive(Debug)]
+#[must_use = "dropping a `RemovalFuture` cancels the removal"]
+pub struct RemovalFuture {
+    monitor: Arc<Monitor>,
+    task: JoinHandle<io::Result<RemovalReport>>,
+}
+
+impl RemovalFuture {
+    /// Starts removing the directory at `path` on the blocking thread pool.
+    fn spawn(path: &Path, options: &RemoveOptions, monitor: Monitor) -> Self {
+        let path = path.to_path_buf();
+        let options = options.clone();
+        let monitor = Arc::new(monitor);
+
+        let task_monitor = Arc::clone(&monitor);
+        let task = tokio::task::spawn_blocking(move || {
+            let result = remove::remove(&path, &options, Some(&task_monitor));
+            task_monitor.close_progress();
+            result
+        });
+
+        Self { monitor, task }
+    }
+
+    /// Returns the number of entries removed so far.
+    pub fn progress(&self) -> RemovalProgress {
+        self.monitor.progress()
+    }
+}
+
+impl Future for RemovalFuture {
+    type Output = io::Result<RemovalReport>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|result| match result {
+                Ok(result) => result,
+                Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+                Err(error) => Err(io::Error::new(io::ErrorKind::Other, error)),
+            })
+    }
+}
+
+impl Drop for RemovalFuture {
+    fn drop(&mut self) {
+        // This has no effect if the removal already finished.
+        self.monitor.cancel();
+    }
+}
+
+/// Removes a directory at this path, after removing all its contents, without blocking the
+/// calling task.
+///
+/// This behaves like [`remove_dir_all`](fn.remove_dir_all.html), running on Tokio's blocking
+/// thread pool. The removal starts immediately, even if the returned future is never polled, and
+/// is cancelled if the future is dropped before it resolves.
+///
+/// # Panics
+///
+/// Panics if called from outside of a Tokio runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tokio::runtime::Builder;
+/// use win32_remove_dir_all::remove_dir_all_async;
+///
+/// fn main() -> std::io::Result<()> {
+///     Builder::new_current_thread().build()?.block_on(async {
+///         remove_dir_all_async("/some/dir").await?;
+///         Ok(())
+///     })
+/// }
+/// ```
+pub fn remove_dir_all_async<P: AsRef<Path>>(path: P) -> impl Future<Output = io::Result<()>> {
+    let future = remove_dir_all_with_async(path, &RemoveOptions::new());
+    async move { future.await.map(|_| ()) }
+}
+
+/// Removes a directory at this path, after removing all its contents, using the specified options
+/// without blocking the calling task.
+///
+/// See [`remove_dir_all_async`](fn.remove_dir_all_async.html) and
+/// [`remove_dir_all_with`](fn.remove_dir_all_with.html).
+///
+/// # Panics
+///
+/// Panics if called from outside of a Tokio runtime.
+pub fn remove_dir_all_with_async<P: AsRef<Path>>(
+    path: P,
+    options: &RemoveOptions,
+) -> RemovalFuture {
+    RemovalFuture::spawn(path.as_ref(), options, Monitor::default())
+}
+
+/// Removes a directory at this path, after removing all its contents, using the specified options
+/// without blocking the calling task, streaming progress through a watch channel.
+///
+/// A [`RemovalProgress`](struct.RemovalProgress.html) snapshot is published after each entry is
+/// removed, and the channel is closed once the removal finishes. Since each snapshot contains
+/// running totals, a receiver that falls behind only sees the latest one, and the final snapshot
+/// can always be read from the receiver once the channel is closed. Progress granularity on
+/// Windows is the same as for [`remove_dir_all_background`](fn.remove_dir_all_background.html).
+///
+/// # Panics
+///
+/// Panics if called from outside of a Tokio runtime.
+///
+/// # Examples
+///
+/// ```no_run
+/// use tokio::runtime::Builder;
+/// use win32_remove_dir_all::{remove_dir_all_with_progress, RemoveOptions};
+///
+/// fn main() -> std::io::Result<()> {
+///     Builder::new_current_thread().build()?.block_on(async {
+///         let (removal, mut progress) =
+///             remove_dir_all_with_progress("/some/dir", &RemoveOptions::new());
+///         while progress.changed().await.is_ok() {
+///             println!("Removed {} files so far.", progress.borrow().files_removed());
+///         }
+///
+///         removal.await?;
+///         println!("Removed {} files in total.", progress.borrow().files_removed());
+///         Ok(())
+///     })
+/// }
+/// ```
+pub fn remove_dir_all_with_progress<P: AsRef<Path>>(
+    path: P,
+    options: &RemoveOptions,
+) -> (RemovalFuture, watch::Receiver<RemovalProgress>) {
+    let (sender, receiver) = watch::channel(RemovalProgress::default());
+    let monitor = Monitor::with_progress_sender(sender);
+    (
+        RemovalFuture::spawn(path.as_ref(), options, monitor),
+        receiver,
+    )
+}
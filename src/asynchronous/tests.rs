@@ -0,0 +1,104 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Asynchronous removal tests.
+
+use super::{remove_dir_all_async, remove_dir_all_with_async, remove_dir_all_with_progress};
+use crate::{engine::tests::populate_dir, lock, LockMode, RemovalProgress, RemoveOptions};
+use std::{fs, future::Future, io, path::Path};
+use tempfile::TempDir;
+use tokio::runtime::Builder;
+
+/// Runs `future` to completion on a current-thread runtime.
+fn block_on<F: Future>(future: F) -> F::Output {
+    Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Creates a directory at `path` containing a few entries.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)
+}
+
+/// Tests whether asynchronous removal works.
+#[test]
+fn async_removal_works() {
+    block_on(async {
+        let base_dir = TempDir::new().unwrap();
+        let dir_path = base_dir.path().join("target");
+        create_tree(&dir_path).unwrap();
+
+        remove_dir_all_async(&dir_path).await.unwrap();
+        assert!(!dir_path.exists());
+
+        assert_eq!(
+            remove_dir_all_async(&dir_path).await.unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    });
+}
+
+/// Tests whether progress snapshots are streamed until the removal finishes.
+#[test]
+fn progress_is_streamed() {
+    block_on(async {
+        let base_dir = TempDir::new().unwrap();
+        let dir_path = base_dir.path().join("target");
+        create_tree(&dir_path).unwrap();
+
+        let options = RemoveOptions::new();
+        let (removal, mut progress) = remove_dir_all_with_progress(&dir_path, &options);
+
+        let mut last = None;
+        while progress.changed().await.is_ok() {
+            let snapshot = *progress.borrow_and_update();
+            if let Some(last) = last {
+                assert!(snapshot.files_removed() >= last);
+            }
+            last = Some(snapshot.files_removed());
+        }
+
+        let report = removal.await.unwrap();
+        assert!(report.root_removed());
+        assert_eq!(progress.borrow().files_removed(), report.files_removed());
+        assert_eq!(progress.borrow().dirs_removed(), report.dirs_removed());
+    });
+}
+
+/// Tests whether dropping a removal future cancels the removal, leaving the target in place.
+#[test]
+fn dropping_cancels_removal() {
+    block_on(async {
+        let base_dir = TempDir::new().unwrap();
+        let dir_path = base_dir.path().join("target");
+        create_tree(&dir_path).unwrap();
+
+        // The removal waits for the lock held here, so it is dropped before removing anything.
+        let held_lock = lock::acquire(&dir_path, LockMode::FailFast, None).unwrap();
+        let mut options = RemoveOptions::new();
+        options.lock(LockMode::Wait);
+        let (removal, mut progress) = remove_dir_all_with_progress(&dir_path, &options);
+        drop(removal);
+        drop(held_lock);
+
+        // The channel closes once the blocking task stops.
+        while progress.changed().await.is_ok() {}
+        assert_eq!(*progress.borrow(), RemovalProgress::default());
+        assert!(dir_path.join("foo").is_file());
+        assert!(dir_path.join("baz").join("qux").is_file());
+
+        // A future that is not dropped runs to completion.
+        let report = remove_dir_all_with_async(&dir_path, &options)
+            .await
+            .unwrap();
+        assert!(report.root_removed());
+    });
+}
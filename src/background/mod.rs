@@ -0,0 +1,291 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal on a background thread, with progress reporting and cancellation.
+//!
+//! A [`Monitor`] is shared between the [`RemovalHandle`] and the removal engine running on the
+//! background thread. The engine publishes its running totals to the monitor after each entry and
+//! checks for cancellation before each one.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{remove, Error, RemovalReport, RemoveOptions};
+use std::{
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Progress and cancellation state shared with a removal engine.
+#[derive(Debug, Default)]
+pub(crate) struct Monitor {
+    cancelled: AtomicBool,
+    files_removed: AtomicU64,
+    dirs_removed: AtomicU64,
+    symlinks_removed: AtomicU64,
+
+    /// Channel to publish a snapshot of each progress update to, if any.
+    #[cfg(feature = "async")]
+    progress_sender: Mutex<Option<tokio::sync::watch::Sender<RemovalProgress>>>,
+}
+
+impl Monitor {
+    /// Creates a monitor publishing a snapshot of each progress update to `sender`.
+    #[cfg(feature = "async")]
+    pub fn with_progress_sender(sender: tokio::sync::watch::Sender<RemovalProgress>) -> Self {
+        Self {
+            progress_sender: Mutex::new(Some(sender)),
+            ..Self::default()
+        }
+    }
+
+    /// Requests that the removal stop before removing any further entries.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns an [`Error::Cancelled`] error if cancellation has been requested.
+    pub fn check_cancelled(&self) -> io::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(Error::Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Publishes the running totals of `report`.
+    pub fn update(&self, report: &RemovalReport) {
+        self.files_removed
+            .store(report.files_removed, Ordering::Relaxed);
+        self.dirs_removed
+            .store(report.dirs_removed, Ordering::Relaxed);
+        self.symlinks_removed
+            .store(report.symlinks_removed, Ordering::Relaxed);
+
+        // Snapshots are cumulative, so each one replaces any the receiver has not yet seen, and
+        // the last one always remains available once the channel is closed.
+        #[cfg(feature = "async")]
+        {
+            let sender = self
+                .progress_sender
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            if let Some(ref sender) = *sender {
+                sender.send_replace(self.progress());
+            }
+        }
+    }
+
+    /// Closes the progress channel, if any, signalling that the removal has finished.
+    #[cfg(feature = "async")]
+    pub fn close_progress(&self) {
+        self.progress_sender
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .take();
+    }
+
+    /// Returns a snapshot of the running totals.
+    pub fn progress(&self) -> RemovalProgress {
+        RemovalProgress {
+            files_removed: self.files_removed.load(Ordering::Relaxed),
+            dirs_removed: self.dirs_removed.load(Ordering::Relaxed),
+            symlinks_removed: self.symlinks_removed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Snapshot of the entries removed so far by a background or asynchronous removal.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct RemovalProgress {
+    files_removed: u64,
+    dirs_removed: u64,
+    symlinks_removed: u64,
+}
+
+impl RemovalProgress {
+    /// Returns the number of regular files removed so far.
+    pub fn files_removed(&self) -> u64 {
+        self.files_removed
+    }
+
+    /// Returns the number of directories removed so far.
+    pub fn dirs_removed(&self) -> u64 {
+        self.dirs_removed
+    }
+
+    /// Returns the number of symbolic links (and junctions on Windows) removed so far.
+    pub fn symlinks_removed(&self) -> u64 {
+        self.symlinks_removed
+    }
+}
+
+/// State shared between a [`RemovalHandle`] and its background thread.
+#[derive(Debug, Default)]
+struct Shared {
+    monitor: Arc<Monitor>,
+    finished: Mutex<bool>,
+    finished_changed: Condvar,
+}
+
+/// Marks the removal as finished when dropped, even if the background thread panics.
+struct FinishGuard(Arc<Shared>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        let mut finished = self
+            .0
+            .finished
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        *finished = true;
+        self.0.finished_changed.notify_all();
+    }
+}
+
+/// Handle to a removal running on a background thread, returned by
+/// [`remove_dir_all_background`](fn.remove_dir_all_background.html).
+///
+/// Dropping the handle detaches the background thread, which continues the removal unless
+/// [`cancel`](#method.cancel) was called first.
+#[derive(Debug)]
+pub struct RemovalHandle {
+    shared: Arc<Shared>,
+    thread: JoinHandle<io::Result<RemovalReport>>,
+}
+
+impl RemovalHandle {
+    /// Returns whether the removal has finished, whether successfully or not.
+    pub fn is_finished(&self) -> bool {
+        *self
+            .shared
+            .finished
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
+    /// Blocks until the removal finishes, returning its result.
+    ///
+    /// # Panics
+    ///
+    /// Resumes the panic of the background thread if it panicked.
+    pub fn wait(self) -> io::Result<RemovalReport> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    }
+
+    /// Blocks until the removal finishes or `timeout` elapses, returning whether it finished.
+    ///
+    /// The result of a finished removal can then be retrieved without blocking using
+    /// [`wait`](#method.wait).
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut finished = self
+            .shared
+            .finished
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        while !*finished {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+
+            finished = self
+                .shared
+                .finished_changed
+                .wait_timeout(finished, deadline - now)
+                .unwrap_or_else(|error| error.into_inner())
+                .0;
+        }
+
+        true
+    }
+
+    /// Returns the number of entries removed so far.
+    pub fn progress(&self) -> RemovalProgress {
+        self.shared.monitor.progress()
+    }
+
+    /// Requests that the removal stop as soon as possible, without waiting for it to do so.
+    ///
+    /// The removal checks for cancellation before removing each entry, and fails with an
+    /// [`Error::Cancelled`] error if it was cancelled before finishing. Entries removed before
+    /// then are not restored.
+    ///
+    /// [`Error::Cancelled`]: enum.Error.html#variant.Cancelled
+    pub fn cancel(&self) {
+        self.shared.monitor.cancel();
+    }
+}
+
+/// Starts removing a directory at this path, along with all its contents, on a background thread,
+/// returning immediately.
+///
+/// This behaves like [`remove_dir_all`](fn.remove_dir_all.html), but any errors (including
+/// protected path refusals) are reported by [`RemovalHandle::wait`] instead.
+///
+/// # Platform-specific behavior
+///
+/// On Windows, directory trees handed off to the Shell or Property System backends are removed as
+/// a single unit, so progress is only updated, and cancellation only checked, between them.
+/// Options requiring the portable engine to visit each entry, such as
+/// [`RemoveOptions::one_file_system`], report progress for every entry.
+///
+/// [`RemovalHandle::wait`]: struct.RemovalHandle.html#method.wait
+/// [`RemoveOptions::one_file_system`]: struct.RemoveOptions.html#method.one_file_system
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use win32_remove_dir_all::remove_dir_all_background;
+///
+/// fn main() -> std::io::Result<()> {
+///     let handle = remove_dir_all_background("/some/dir");
+///     while !handle.wait_timeout(Duration::from_millis(100)) {
+///         println!("Removed {} files so far.", handle.progress().files_removed());
+///     }
+///
+///     handle.wait()?;
+///     Ok(())
+/// }
+/// ```
+pub fn remove_dir_all_background<P: AsRef<Path>>(path: P) -> RemovalHandle {
+    remove_dir_all_background_with(path, &RemoveOptions::new())
+}
+
+/// Starts removing a directory at this path, along with all its contents, on a background thread
+/// using the specified options, returning immediately.
+///
+/// See [`remove_dir_all_background`](fn.remove_dir_all_background.html) and
+/// [`remove_dir_all_with`](fn.remove_dir_all_with.html).
+pub fn remove_dir_all_background_with<P: AsRef<Path>>(
+    path: P,
+    options: &RemoveOptions,
+) -> RemovalHandle {
+    let path = path.as_ref().to_path_buf();
+    let options = options.clone();
+    let shared = Arc::new(Shared::default());
+
+    let guard = FinishGuard(Arc::clone(&shared));
+    let thread = thread::spawn(move || {
+        let guard = guard;
+        remove::remove(&path, &options, Some(&guard.0.monitor))
+    });
+
+    RemovalHandle { shared, thread }
+}
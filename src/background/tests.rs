@@ -0,0 +1,100 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Background removal tests.
+
+use super::{remove_dir_all_background, remove_dir_all_background_with, Monitor};
+use crate::{
+    engine::{tests::populate_dir, Engine},
+    Error, RemoveOptions,
+};
+use std::{fs, io, path::Path, sync::Arc, time::Duration};
+use tempfile::TempDir;
+
+/// Creates a directory at `path` containing a few entries.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)
+}
+
+/// Tests whether a background removal can be waited on and reports its final progress.
+#[test]
+fn background_removal_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+
+    let handle = remove_dir_all_background(&dir_path);
+    assert!(handle.wait_timeout(Duration::from_secs(10)));
+    assert!(handle.is_finished());
+
+    let progress = handle.progress();
+    let report = handle.wait().unwrap();
+    assert!(report.root_removed());
+    assert_eq!(progress.files_removed(), report.files_removed());
+    assert_eq!(progress.dirs_removed(), report.dirs_removed());
+    assert!(!dir_path.exists());
+}
+
+/// Tests whether errors are reported when waiting rather than when starting the removal.
+#[test]
+fn errors_are_reported_by_wait() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("missing");
+
+    let handle = remove_dir_all_background(&dir_path);
+    assert_eq!(handle.wait().unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+/// Tests whether a cancelled engine stops before removing anything further.
+#[test]
+fn cancellation_stops_engine() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+
+    let monitor = Arc::new(Monitor::default());
+    monitor.cancel();
+
+    let options = RemoveOptions::new();
+    let mut engine = Engine::new(&options).with_monitor(Some(monitor));
+    let error = engine.remove_root(&dir_path).unwrap_err();
+    match Error::from_io_error(&error) {
+        Some(Error::Cancelled) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(dir_path.join("baz").join("qux").exists());
+}
+
+/// Tests whether cancelling a background removal either stops it with an `Error::Cancelled` error
+/// or lets it finish, depending on how far it got.
+#[test]
+fn cancellation_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    for i in 0..1000 {
+        fs::write(dir_path.join(i.to_string()), b"").unwrap();
+    }
+
+    let handle =
+        remove_dir_all_background_with(&dir_path, RemoveOptions::new().one_file_system(true));
+    handle.cancel();
+    let progress_before_wait = handle.progress();
+    match handle.wait() {
+        Ok(report) => assert!(report.root_removed()),
+        Err(error) => {
+            match Error::from_io_error(&error) {
+                Some(Error::Cancelled) => {}
+                _ => panic!("unexpected error: {}", error),
+            }
+            assert!(dir_path.exists());
+            assert!(progress_before_wait.files_removed() < 1000);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal of many directories in a single call.
+//!
+//! On Windows, directories handed off to the Shell or Property System backends are removed in a
+//! single `SHFileOperationW` call (with a double-nul-terminated source list) or a single
+//! `IFileOperation`, avoiding the setup cost of each. Directories removed by the portable
+//! engine are instead spread across a pool of threads.
+
+#[cfg(any(windows, test))]
+mod multi_string;
+#[cfg(test)]
+mod tests;
+
+#[cfg(windows)]
+pub(crate) use multi_string::MultiStringBuilder;
+
+use crate::{remove, RemovalReport, RemoveOptions};
+use std::{
+    io, mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Outcome of removing a single path passed to [`remove_all`](fn.remove_all.html).
+#[derive(Debug)]
+pub struct BatchOutcome {
+    path: PathBuf,
+    result: io::Result<RemovalReport>,
+}
+
+impl BatchOutcome {
+    /// Returns the path whose removal this outcome describes.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the result of removing the path.
+    pub fn result(&self) -> &io::Result<RemovalReport> {
+        &self.result
+    }
+
+    /// Consumes the outcome, returning the result of removing the path.
+    pub fn into_result(self) -> io::Result<RemovalReport> {
+        self.result
+    }
+}
+
+/// Removes each directory at the given paths, along with all their contents, using the specified
+/// options.
+///
+/// Each path is handled as if by [`remove_dir_all_with`](fn.remove_dir_all_with.html), and one
+/// [`BatchOutcome`](struct.BatchOutcome.html) is returned per path, in the order given. A failure
+/// to remove one path does not prevent the others from being removed.
+///
+/// # Platform-specific behavior
+///
+/// On Windows, when no options other than the protections are set, every directory is removed in a
+/// single `IFileOperation` or `SHFileOperationW` operation. Each such removal is reported as a
+/// single removed directory. If the batch operation fails, any directories it did not remove are
+/// retried individually to determine which of them failed.
+///
+/// Otherwise, the directories are removed in parallel by the portable engine, using up to one
+/// thread per available CPU.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_all, RemoveOptions};
+///
+/// fn main() {
+///     for outcome in remove_all(&["build/a", "build/b"], &RemoveOptions::new()) {
+///         if let Err(error) = outcome.result() {
+///             eprintln!("Failed to remove {}: {}", outcome.path().display(), error);
+///         }
+///     }
+/// }
+/// ```
+pub fn remove_all<I, P>(paths: I, options: &RemoveOptions) -> Vec<BatchOutcome>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+    let mut results: Vec<Option<io::Result<RemovalReport>>> = paths.iter().map(|_| None).collect();
+
+    #[cfg(windows)]
+    {
+        if options.allows_backend_batch() {
+            remove_with_backend(&paths, options, &mut results);
+        }
+    }
+
+    let pending: Vec<usize> = (0..paths.len())
+        .filter(|&index| results[index].is_none())
+        .collect();
+    for (index, result) in remove_in_parallel(&paths, &pending, options) {
+        results[index] = Some(result);
+    }
+
+    paths
+        .into_iter()
+        .zip(results)
+        .map(|(path, result)| BatchOutcome {
+            path,
+            result: result.expect("path was never removed"),
+        })
+        .collect()
+}
+
+/// Removes the directories at `paths` in a single operation using the Windows backends, storing
+/// the result of each in `results`.
+///
+/// Paths that are refused, are not directories, or remain after a failed batch operation are left
+/// without a result, to be removed individually.
+#[cfg(windows)]
+fn remove_with_backend(
+    paths: &[PathBuf],
+    options: &RemoveOptions,
+    results: &mut [Option<io::Result<RemovalReport>>],
+) {
+    use crate::engine::is_dir_symlink;
+    use std::fs;
+
+    let batch: Vec<usize> = (0..paths.len())
+        .filter(|&index| {
+            let path = &paths[index];
+            options.safeguards.check(path).is_ok()
+                && fs::symlink_metadata(path).ok().map_or(false, |metadata| {
+                    metadata.is_dir() || is_dir_symlink(path, metadata.file_type())
+                })
+        })
+        .collect();
+    if batch.is_empty() {
+        return;
+    }
+
+    let batch_paths: Vec<&Path> = batch.iter().map(|&index| paths[index].as_path()).collect();
+    let succeeded = crate::windows::remove_dirs_all(&batch_paths).is_ok();
+    for &index in &batch {
+        // Directories that are gone were removed by the batch operation, even if it failed.
+        if succeeded || fs::symlink_metadata(&paths[index]).is_err() {
+            results[index] = Some(Ok(RemovalReport {
+                dirs_removed: 1,
+                root_removed: true,
+                ..RemovalReport::default()
+            }));
+        }
+    }
+}
+
+/// Directories shared by the threads of `remove_in_parallel`, along with their results.
+struct ParallelRemoval {
+    paths: Vec<(usize, PathBuf)>,
+    options: RemoveOptions,
+    next: AtomicUsize,
+    results: Mutex<Vec<(usize, io::Result<RemovalReport>)>>,
+}
+
+impl ParallelRemoval {
+    /// Removes directories that no other thread has claimed yet until none are left.
+    fn remove_pending(&self) {
+        while let Some(&(index, ref path)) =
+            self.paths.get(self.next.fetch_add(1, Ordering::Relaxed))
+        {
+            let result = remove::remove(path, &self.options, None);
+            self.results
+                .lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .push((index, result));
+        }
+    }
+}
+
+/// Removes the directories at the given indices of `paths` individually, spreading them across a
+/// pool of threads, and returns the result for each index.
+///
+/// Every thread is joined before returning, and a panic in any of them is resumed afterward.
+fn remove_in_parallel(
+    paths: &[PathBuf],
+    indices: &[usize],
+    options: &RemoveOptions,
+) -> Vec<(usize, io::Result<RemovalReport>)> {
+    let thread_count = available_cpus().min(indices.len());
+    let removal = Arc::new(ParallelRemoval {
+        paths: indices
+            .iter()
+            .map(|&index| (index, paths[index].clone()))
+            .collect(),
+        options: options.clone(),
+        next: AtomicUsize::new(0),
+        results: Mutex::new(Vec::with_capacity(indices.len())),
+    });
+
+    if thread_count <= 1 {
+        removal.remove_pending();
+    } else {
+        let threads: Vec<_> = (0..thread_count)
+            .map(|_| {
+                let removal = Arc::clone(&removal);
+                thread::spawn(move || removal.remove_pending())
+            })
+            .collect();
+        let mut panic = None;
+        for thread in threads {
+            if let Err(payload) = thread.join() {
+                panic = panic.or(Some(payload));
+            }
+        }
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
+        }
+    }
+
+    let mut results = removal
+        .results
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    mem::replace(&mut *results, Vec::new())
+}
+
+/// Returns the number of CPUs available to the process, or 1 if it cannot be determined.
+#[cfg(unix)]
+fn available_cpus() -> usize {
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if count > 0 {
+        count as usize
+    } else {
+        1
+    }
+}
+
+/// Returns the number of CPUs available to the process, or 1 if it cannot be determined.
+#[cfg(windows)]
+fn available_cpus() -> usize {
+    use winapi::um::sysinfoapi::{GetSystemInfo, SYSTEM_INFO};
+
+    let mut info: SYSTEM_INFO = unsafe { mem::zeroed() };
+    unsafe { GetSystemInfo(&mut info) };
+    (info.dwNumberOfProcessors as usize).max(1)
+}
+
+/// Returns the number of CPUs available to the process, which cannot be determined on this
+/// platform, so a single thread is used.
+#[cfg(not(any(unix, windows)))]
+fn available_cpus() -> usize {
+    1
+}
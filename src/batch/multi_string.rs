@@ -0,0 +1,65 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Double-nul-terminated string lists, as accepted by the `pFrom` field of `SHFILEOPSTRUCTW`.
+
+use std::io;
+
+/// Builder for a list of nul-terminated strings followed by an additional nul terminator.
+///
+/// The builder is generic over the character type so that it can be tested with bytes on any
+/// platform, although only UTF-16 lists are built in practice.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MultiStringBuilder<T> {
+    buffer: Vec<T>,
+}
+
+impl<T: Copy + Default + PartialEq> MultiStringBuilder<T> {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    /// Appends a string, which must not include a nul terminator.
+    ///
+    /// Empty strings and strings containing nul characters are rejected with
+    /// `io::ErrorKind::InvalidInput`, as they would end the list early or split the string.
+    pub fn push(&mut self, string: &[T]) -> io::Result<()> {
+        let nul = T::default();
+        if string.is_empty() || string.contains(&nul) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Strings in a multi-string list must be non-empty and cannot contain nul characters.",
+            ));
+        }
+
+        self.buffer.extend_from_slice(string);
+        self.buffer.push(nul);
+        Ok(())
+    }
+
+    /// Returns the finished list, terminated by an additional nul character.
+    ///
+    /// An empty list consists of two nul characters, so it is still terminated correctly if read
+    /// as a single empty string.
+    pub fn build(mut self) -> Vec<T> {
+        if self.buffer.is_empty() {
+            self.buffer.push(T::default());
+        }
+        self.buffer.push(T::default());
+        self.buffer
+    }
+}
+
+/// Splits a double-nul-terminated list into its strings, excluding their terminators.
+#[cfg(test)]
+pub(crate) fn split_multi_string<T: Copy + Default + PartialEq>(list: &[T]) -> Vec<&[T]> {
+    list.split(|&c| c == T::default())
+        .take_while(|string| !string.is_empty())
+        .collect()
+}
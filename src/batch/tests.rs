@@ -0,0 +1,154 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Batch removal tests.
+
+use super::{
+    multi_string::{split_multi_string, MultiStringBuilder},
+    remove_all,
+};
+use crate::{engine::tests::populate_dir, lock, Error, LockMode, RemoveOptions};
+use std::{fs, io, path::Path};
+use tempfile::TempDir;
+
+/// Creates a directory at `path` containing a few entries.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)
+}
+
+/// Tests whether multi-string lists are built with each string nul-terminated and an additional
+/// terminator at the end.
+#[test]
+fn multi_string_builder_works() {
+    let mut builder = MultiStringBuilder::new();
+    builder.push(b"first").unwrap();
+    builder.push(b"second").unwrap();
+    let list = builder.build();
+    assert_eq!(list, b"first\0second\0\0");
+    assert_eq!(split_multi_string(&list), [&b"first"[..], &b"second"[..]]);
+
+    let wide: Vec<u16> = "path".encode_utf16().collect();
+    let mut builder = MultiStringBuilder::new();
+    builder.push(&wide).unwrap();
+    let list = builder.build();
+    assert_eq!(list.len(), wide.len() + 2);
+    assert_eq!(split_multi_string(&list), [&wide[..]]);
+}
+
+/// Tests whether an empty multi-string list is still double-nul-terminated.
+#[test]
+fn empty_multi_string_is_terminated() {
+    let list = MultiStringBuilder::<u16>::new().build();
+    assert_eq!(list, [0, 0]);
+    assert!(split_multi_string(&list).is_empty());
+}
+
+/// Tests whether strings that would corrupt a multi-string list are rejected.
+#[test]
+fn invalid_multi_strings_are_rejected() {
+    let mut builder = MultiStringBuilder::new();
+    assert_eq!(
+        builder.push(b"").unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        builder.push(b"a\0b").unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    builder.push(b"valid").unwrap();
+    assert_eq!(builder.build(), b"valid\0\0");
+}
+
+/// Tests whether many directories are removed, with an outcome returned for each path in the order
+/// given.
+#[test]
+fn batch_removal_works() {
+    let base_dir = TempDir::new().unwrap();
+    let mut paths: Vec<_> = (0..16)
+        .map(|i| base_dir.path().join(i.to_string()))
+        .collect();
+    for path in &paths {
+        create_tree(path).unwrap();
+    }
+    paths.insert(5, base_dir.path().join("missing"));
+
+    let outcomes = remove_all(&paths, &RemoveOptions::new());
+    assert_eq!(outcomes.len(), paths.len());
+    for (outcome, path) in outcomes.iter().zip(&paths) {
+        assert_eq!(outcome.path(), path);
+        assert!(!path.exists());
+    }
+
+    assert_eq!(
+        outcomes[5].result().as_ref().unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+    for outcome in outcomes
+        .into_iter()
+        .filter(|outcome| outcome.path().ends_with("3"))
+    {
+        assert!(outcome.into_result().unwrap().root_removed());
+    }
+}
+
+/// Tests whether a refused path is reported without preventing the other paths from being
+/// removed.
+#[test]
+fn batch_refusals_are_per_path() {
+    let base_dir = TempDir::new().unwrap();
+    let kept_path = base_dir.path().join("kept");
+    let removed_path = base_dir.path().join("removed");
+    create_tree(&kept_path).unwrap();
+    create_tree(&removed_path).unwrap();
+
+    let mut options = RemoveOptions::new();
+    options.protect_path(&kept_path);
+    let outcomes = remove_all(vec![&kept_path, &removed_path], &options);
+
+    let error = outcomes[0].result().as_ref().unwrap_err();
+    match Error::from_io_error(error) {
+        Some(Error::ProtectedPath { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(kept_path.exists());
+    assert!(outcomes[1].result().is_ok());
+    assert!(!removed_path.exists());
+}
+
+/// Tests whether options that the platform backends do not honour, such as locks, are applied to
+/// every path in a batch.
+#[test]
+fn batch_options_are_honoured() {
+    let base_dir = TempDir::new().unwrap();
+    let locked_path = base_dir.path().join("locked");
+    let removed_path = base_dir.path().join("removed");
+    create_tree(&locked_path).unwrap();
+    create_tree(&removed_path).unwrap();
+
+    let _lock = lock::acquire(&locked_path, LockMode::FailFast, None).unwrap();
+    let outcomes = remove_all(
+        vec![&locked_path, &removed_path],
+        RemoveOptions::new().lock(LockMode::FailFast),
+    );
+
+    let error = outcomes[0].result().as_ref().unwrap_err();
+    match Error::from_io_error(error) {
+        Some(Error::Locked { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(locked_path.join("baz").join("qux").exists());
+    assert!(outcomes[1].result().is_ok());
+    assert!(!removed_path.exists());
+}
+
+/// Tests whether an empty batch does nothing.
+#[test]
+fn empty_batch_works() {
+    assert!(remove_all(Vec::<&Path>::new(), &RemoveOptions::new()).is_empty());
+}
@@ -0,0 +1,168 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal of untrusted paths confined beneath a trusted root directory.
+//!
+//! Relative paths are first checked lexically, so absolute paths and `..` components climbing
+//! above the root are refused before anything is opened. On Unix, the target's parent directory is
+//! then opened relative to a file descriptor of the root, using `openat2` with `RESOLVE_BENEATH` on
+//! Linux where available, or a component-by-component walk that resolves symbolic links itself
+//! otherwise. Everything beneath the parent is then removed through file descriptors opened with
+//! `O_NOFOLLOW`, so a directory swapped for a symbolic link mid-removal cannot redirect it.
+//!
+//! Other platforms resolve the parent directory using canonical paths instead, which guards against
+//! `..` components and links pointing outside of the root but not against concurrent swaps.
+
+#[cfg(test)]
+mod tests;
+#[cfg(unix)]
+mod unix;
+
+use crate::{safeguard::Safeguards, Error, RemovalReport};
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+/// Removes the entry at `relative` beneath the directory `root`, after removing all its contents,
+/// guaranteeing that nothing outside of `root` is touched. Use carefully!
+///
+/// This is intended for paths from untrusted sources, such as the file lists of plugin manifests.
+/// `relative` must name an entry within `root`; `..` components are allowed as long as they never
+/// climb above `root`, and symbolic links in its intermediate components are followed only while
+/// they resolve within `root`. The target itself must be a directory, which is removed along with
+/// all its contents, or a symbolic link, which is removed without being followed. `root` is trusted
+/// and may itself be a symbolic link.
+///
+/// Default protections (see [`RemoveOptions`]) are checked against `root.join(relative)` before
+/// anything is removed.
+///
+/// [`RemoveOptions`]: struct.RemoveOptions.html
+///
+/// # Platform-specific behavior
+///
+/// On Linux, the parent of the target is resolved using `openat2` with `RESOLVE_BENEATH` where
+/// available, falling back to a verified walk over directory file descriptors on older kernels and
+/// other Unix platforms. The target is then removed using `openat`, `unlinkat`, and `O_NOFOLLOW`,
+/// so directories swapped for symbolic links during removal are never followed.
+///
+/// On Windows, the parent of the target is resolved and verified using canonical paths, which does
+/// not protect against directories being swapped for links while the removal is in progress.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::PermissionDenied` error carrying an [`Error::EscapesRoot`] if
+/// `relative` is absolute or would resolve outside of `root`, including through an absolute or
+/// escaping symbolic link. Paths that do not name an entry, such as an empty path or `a/..`, are
+/// rejected with `io::ErrorKind::InvalidInput`, and targets that are neither directories nor
+/// symbolic links are rejected with `io::ErrorKind::InvalidData`.
+///
+/// [`Error::EscapesRoot`]: enum.Error.html#variant.EscapesRoot
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_dir_all_beneath, Error};
+///
+/// fn main() -> std::io::Result<()> {
+///     match remove_dir_all_beneath("/opt/app/plugins", "../../../etc") {
+///         Err(ref error) if Error::from_io_error(error).is_some() => {
+///             eprintln!("Refusing to uninstall: {}", error);
+///         }
+///         result => {
+///             result?;
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn remove_dir_all_beneath<R: AsRef<Path>, P: AsRef<Path>>(
+    root: R,
+    relative: P,
+) -> io::Result<RemovalReport> {
+    let root = root.as_ref();
+    let relative = relative.as_ref();
+    check_relative(root, relative)?;
+    Safeguards::default().check(&root.join(relative))?;
+
+    remove_beneath(root, relative)
+}
+
+/// Checks lexically that `relative` names an entry beneath `root`.
+fn check_relative(root: &Path, relative: &Path) -> io::Result<()> {
+    let mut depth = 0usize;
+    for component in relative.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Err(escape_error(root, relative)),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| escape_error(root, relative))?;
+            }
+            Component::Normal(_) => depth += 1,
+        }
+    }
+
+    match relative.components().next_back() {
+        Some(Component::Normal(_)) => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Path does not name an entry beneath the root directory.",
+        )),
+    }
+}
+
+/// Returns the error reported when `relative` resolves outside of `root`.
+fn escape_error(root: &Path, relative: &Path) -> io::Error {
+    Error::EscapesRoot {
+        root: root.to_path_buf(),
+        path: relative.to_path_buf(),
+    }
+    .into()
+}
+
+/// Removes the entry at `relative` beneath `root` using file descriptors.
+#[cfg(unix)]
+fn remove_beneath(root: &Path, relative: &Path) -> io::Result<RemovalReport> {
+    unix::remove_beneath(root, relative, true)
+}
+
+/// Removes the entry at `relative` beneath `root`, verifying its parent directory using canonical
+/// paths.
+#[cfg(not(unix))]
+fn remove_beneath(root: &Path, relative: &Path) -> io::Result<RemovalReport> {
+    use crate::{engine::Engine, RemoveOptions};
+    use std::fs;
+
+    let canonical_root = fs::canonicalize(root)?;
+    let parent = match relative.parent() {
+        Some(parent) => fs::canonicalize(canonical_root.join(parent))?,
+        None => canonical_root.clone(),
+    };
+    if !parent.starts_with(&canonical_root) {
+        return Err(escape_error(root, relative));
+    }
+
+    // `check_relative` guarantees that the final component is a normal component.
+    let path = parent.join(relative.file_name().unwrap_or_default());
+    let file_type = fs::symlink_metadata(&path)?.file_type();
+    if !file_type.is_dir() && !file_type.is_symlink() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Target is not a directory or symbolic link.",
+        ));
+    }
+
+    let options = RemoveOptions::new();
+    let mut engine = Engine::new(&options);
+    let removed = engine.remove_entry(&path, file_type)?;
+    engine.report_mut().root_removed = removed;
+
+    engine.finish()
+}
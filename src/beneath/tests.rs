@@ -0,0 +1,166 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Confined removal tests.
+
+use crate::{engine::tests::populate_dir, remove_dir_all_beneath, Error};
+use std::{fs, io, path::Path};
+use tempfile::TempDir;
+
+/// Creates a directory at `path` populated with a few files and a subdirectory.
+fn create_tree(path: &Path) {
+    fs::create_dir_all(path).unwrap();
+    populate_dir(path).unwrap();
+}
+
+/// Asserts that `result` failed because the target would escape its root directory.
+fn assert_escapes<T: std::fmt::Debug>(result: io::Result<T>) {
+    let error = result.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    match Error::from_io_error(&error) {
+        Some(Error::EscapesRoot { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+}
+
+/// Removes `relative` beneath `root` using both the `openat2` resolver and the fallback walk,
+/// expecting the same result from each.
+#[cfg(unix)]
+fn remove_with_each_resolver(
+    root: &Path,
+    relative: &str,
+    setup: impl Fn(),
+    check: impl Fn(io::Result<crate::RemovalReport>),
+) {
+    for &use_openat2 in &[true, false] {
+        setup();
+        check(super::unix::remove_beneath(
+            root,
+            Path::new(relative),
+            use_openat2,
+        ));
+    }
+}
+
+/// Tests whether absolute paths and `..` components climbing above the root are refused before
+/// anything is removed, and whether paths not naming an entry are rejected.
+#[test]
+fn lexical_escapes_are_refused() {
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    let outside = base_dir.path().join("outside");
+    fs::create_dir_all(root.join("a")).unwrap();
+    create_tree(&outside);
+
+    assert_escapes(remove_dir_all_beneath(&root, "../outside"));
+    assert_escapes(remove_dir_all_beneath(&root, "a/../../outside"));
+    assert_escapes(remove_dir_all_beneath(&root, &outside));
+    assert!(outside.join("baz/qux").is_file());
+
+    for relative in &["", ".", "a/.."] {
+        let error = remove_dir_all_beneath(&root, relative).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert!(root.join("a").is_dir());
+
+    create_tree(&root.join("a/target"));
+    let error = remove_dir_all_beneath(&root, "a/./missing/../target").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    let report = remove_dir_all_beneath(&root, "a/../a/target").unwrap();
+    assert_eq!(report.files_removed(), 3);
+    assert_eq!(report.dirs_removed(), 2);
+    assert!(report.root_removed());
+    assert!(!root.join("a/target").exists());
+}
+
+/// Tests whether symbolic links in intermediate components are followed while they stay within
+/// the root, and refused once they would leave it.
+#[test]
+#[cfg(unix)]
+fn escaping_symlinks_are_refused() {
+    use std::os::unix::fs::symlink;
+
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    let outside = base_dir.path().join("outside");
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    create_tree(&outside.join("victim"));
+    symlink(&outside, root.join("absolute")).unwrap();
+    symlink("../../..", root.join("a/b/up")).unwrap();
+    symlink("a/b", root.join("inner")).unwrap();
+    symlink("../b", root.join("a/b/self")).unwrap();
+
+    let no_setup = || {};
+    for relative in &[
+        "absolute/victim",
+        "a/b/up/outside/victim",
+        "inner/up/outside/victim",
+    ] {
+        remove_with_each_resolver(&root, relative, no_setup, assert_escapes);
+    }
+    assert!(outside.join("victim/baz/qux").is_file());
+
+    let target = root.join("a/b/target");
+    remove_with_each_resolver(
+        &root,
+        "inner/self/self/target",
+        || create_tree(&target),
+        |result| {
+            assert_eq!(result.unwrap().files_removed(), 3);
+            assert!(!target.exists());
+        },
+    );
+}
+
+/// Tests whether symbolic links within the target, or as the target itself, are removed without
+/// following them.
+#[test]
+#[cfg(unix)]
+fn symlinks_are_not_followed() {
+    use std::os::unix::fs::symlink;
+
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    let outside = base_dir.path().join("outside");
+    fs::create_dir(&root).unwrap();
+    create_tree(&outside);
+
+    let link = root.join("link");
+    remove_with_each_resolver(
+        &root,
+        "link",
+        || symlink(&outside, &link).unwrap(),
+        |result| {
+            let report = result.unwrap();
+            assert_eq!(report.symlinks_removed(), 1);
+            assert_eq!(report.dirs_removed(), 0);
+            assert!(fs::symlink_metadata(&link).is_err());
+        },
+    );
+
+    let target = root.join("target");
+    remove_with_each_resolver(
+        &root,
+        "target",
+        || {
+            create_tree(&target);
+            symlink(&outside, target.join("baz/link")).unwrap();
+        },
+        |result| {
+            let report = result.unwrap();
+            assert_eq!(report.files_removed(), 3);
+            assert_eq!(report.symlinks_removed(), 1);
+            assert!(!target.exists());
+        },
+    );
+    assert!(outside.join("baz/qux").is_file());
+
+    fs::write(root.join("file"), b"").unwrap();
+    let error = remove_dir_all_beneath(&root, "file").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
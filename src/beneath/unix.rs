@@ -0,0 +1,365 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! File-descriptor-based confined removal for Unix platforms.
+
+use super::escape_error;
+use crate::RemovalReport;
+use std::{
+    ffi::{CStr, CString, OsStr, OsString},
+    io, mem,
+    os::{
+        raw::{c_char, c_int},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+    path::{Component, Path},
+};
+
+/// Maximum number of symbolic links followed while resolving a path, matching the Linux limit.
+const MAX_SYMLINKS: usize = 40;
+
+/// Argument of the `openat2` system call, declared here since `libc` only provides it for Rust 1.40
+/// and later.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct OpenHow {
+    flags: u64,
+    mode: u64,
+    resolve: u64,
+}
+
+/// Owned file descriptor, closed when dropped.
+struct Fd(c_int);
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+impl Fd {
+    /// Opens the directory at `path`, following symbolic links.
+    fn open_dir(path: &Path) -> io::Result<Self> {
+        let path = to_cstring(path.as_os_str())?;
+        let fd = cvt(unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        })?;
+        Ok(Fd(fd))
+    }
+
+    /// Opens the directory `name` within this directory without following symbolic links.
+    fn open_dir_at(&self, name: &CStr) -> io::Result<Self> {
+        let fd = cvt(unsafe {
+            libc::openat(
+                self.0,
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        })?;
+        Ok(Fd(fd))
+    }
+
+    /// Duplicates this file descriptor.
+    fn try_clone(&self) -> io::Result<Self> {
+        let fd = cvt(unsafe { libc::fcntl(self.0, libc::F_DUPFD_CLOEXEC, 0) })?;
+        Ok(Fd(fd))
+    }
+}
+
+/// Confined removal state for a single call.
+struct Confined<'a> {
+    /// Root directory, as provided by the caller.
+    root_path: &'a Path,
+
+    /// Path of the target relative to `root_path`, as provided by the caller.
+    relative: &'a Path,
+
+    /// Open file descriptor of the root directory.
+    root: Fd,
+
+    report: RemovalReport,
+}
+
+/// Removes the entry at `relative` beneath `root`, which must have been checked lexically.
+///
+/// `openat2` is only attempted if `use_openat2` is set, which allows tests to exercise the
+/// fallback walk on kernels supporting `openat2`.
+pub(super) fn remove_beneath(
+    root: &Path,
+    relative: &Path,
+    use_openat2: bool,
+) -> io::Result<RemovalReport> {
+    let mut confined = Confined {
+        root_path: root,
+        relative,
+        root: Fd::open_dir(root)?,
+        report: RemovalReport::default(),
+    };
+
+    let parent = match relative.parent() {
+        Some(parent) if parent != Path::new("") => confined.open_beneath(parent, use_openat2)?,
+        _ => confined.root.try_clone()?,
+    };
+
+    // The caller guarantees that the final component is a normal component.
+    let name = to_cstring(relative.file_name().unwrap_or_default())?;
+    let mode = stat_at(&parent, &name)?.st_mode & libc::S_IFMT;
+    if mode != libc::S_IFDIR && mode != libc::S_IFLNK {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Target is not a directory or symbolic link.",
+        ));
+    }
+
+    confined.remove_entry_at(&parent, &name)?;
+    confined.report.root_removed = true;
+
+    Ok(confined.report)
+}
+
+impl Confined<'_> {
+    /// Returns the error reported when the target resolves outside of the root.
+    fn escape(&self) -> io::Error {
+        escape_error(self.root_path, self.relative)
+    }
+
+    /// Opens the directory at `path` relative to the root, refusing to resolve outside of it.
+    fn open_beneath(&self, path: &Path, use_openat2: bool) -> io::Result<Fd> {
+        #[cfg(target_os = "linux")]
+        {
+            if use_openat2 {
+                match self.openat2_beneath(path) {
+                    Ok(fd) => return Ok(fd),
+                    Err(ref error) if error.raw_os_error() == Some(libc::EXDEV) => {
+                        return Err(self.escape())
+                    }
+                    // `openat2` is either unsupported by the kernel, blocked by a seccomp filter,
+                    // or failed due to a concurrent rename, so fall back to walking the path.
+                    Err(ref error)
+                        if [libc::ENOSYS, libc::EPERM, libc::EAGAIN]
+                            .contains(&error.raw_os_error().unwrap_or(0)) => {}
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = use_openat2;
+
+        self.walk_beneath(path)
+    }
+
+    /// Opens the directory at `path` relative to the root using `openat2` with `RESOLVE_BENEATH`,
+    /// which fails with `EXDEV` if resolution would leave the root.
+    #[cfg(target_os = "linux")]
+    fn openat2_beneath(&self, path: &Path) -> io::Result<Fd> {
+        let path = to_cstring(path.as_os_str())?;
+        let how = OpenHow {
+            flags: (libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) as u64,
+            mode: 0,
+            resolve: libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS,
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                self.root.0,
+                path.as_ptr(),
+                &how as *const OpenHow,
+                mem::size_of::<OpenHow>(),
+            )
+        };
+        if fd == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Fd(fd as c_int))
+        }
+    }
+
+    /// Opens the directory at `path` relative to the root one component at a time, never
+    /// following symbolic links implicitly.
+    ///
+    /// Each directory along the way is kept open, so `..` components are resolved against the
+    /// directories actually traversed rather than whatever their paths refer to now. Symbolic
+    /// links are read and their targets resolved the same way, with absolute targets refused
+    /// outright.
+    fn walk_beneath(&self, path: &Path) -> io::Result<Fd> {
+        let mut dirs: Vec<Fd> = Vec::new();
+        let mut pending = Vec::new();
+        self.push_components(&mut pending, path)?;
+
+        let mut links = 0;
+        while let Some(component) = pending.pop() {
+            let component = match component {
+                Some(component) => component,
+                None => {
+                    if dirs.pop().is_none() {
+                        return Err(self.escape());
+                    }
+                    continue;
+                }
+            };
+
+            let name = to_cstring(&component)?;
+            let dir = dirs.last().unwrap_or(&self.root);
+            match dir.open_dir_at(&name) {
+                Ok(fd) => dirs.push(fd),
+                Err(error) => {
+                    let target = match error.raw_os_error() {
+                        Some(libc::ELOOP) | Some(libc::EMLINK) | Some(libc::ENOTDIR) => {
+                            read_link_at(dir, &name).map_err(|_| error)?
+                        }
+                        _ => return Err(error),
+                    };
+
+                    links += 1;
+                    if links > MAX_SYMLINKS {
+                        return Err(io::Error::from_raw_os_error(libc::ELOOP));
+                    }
+                    self.push_components(&mut pending, Path::new(&target))?;
+                }
+            }
+        }
+
+        match dirs.pop() {
+            Some(fd) => Ok(fd),
+            None => self.root.try_clone(),
+        }
+    }
+
+    /// Pushes the components of `path` onto `pending` in reverse order, so that they are popped
+    /// in order, with `None` representing a `..` component.
+    fn push_components(&self, pending: &mut Vec<Option<OsString>>, path: &Path) -> io::Result<()> {
+        for component in path.components().rev() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => return Err(self.escape()),
+                Component::CurDir => {}
+                Component::ParentDir => pending.push(None),
+                Component::Normal(name) => pending.push(Some(name.to_os_string())),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the entry `name` within `dir`, recursing into it if it is a directory.
+    fn remove_entry_at(&mut self, dir: &Fd, name: &CStr) -> io::Result<()> {
+        let mode = stat_at(dir, name)?.st_mode & libc::S_IFMT;
+        if mode == libc::S_IFDIR {
+            match dir.open_dir_at(name) {
+                Ok(child) => {
+                    for child_name in read_dir_names(&child)? {
+                        self.remove_entry_at(&child, &child_name)?;
+                    }
+                    drop(child);
+
+                    cvt(unsafe { libc::unlinkat(dir.0, name.as_ptr(), libc::AT_REMOVEDIR) })?;
+                    self.report.dirs_removed += 1;
+                    return Ok(());
+                }
+                // The directory was replaced since it was inspected, so remove whatever took its
+                // place without following it.
+                Err(ref error)
+                    if [libc::ELOOP, libc::EMLINK, libc::ENOTDIR]
+                        .contains(&error.raw_os_error().unwrap_or(0)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        cvt(unsafe { libc::unlinkat(dir.0, name.as_ptr(), 0) })?;
+        if mode == libc::S_IFLNK {
+            self.report.symlinks_removed += 1;
+        } else {
+            self.report.files_removed += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts an `io::Result`-style return value of -1 into the last OS error.
+fn cvt(result: c_int) -> io::Result<c_int> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Converts a path or path component into a C string.
+fn to_cstring(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Path contains an interior nul byte.",
+        )
+    })
+}
+
+/// Returns the metadata of `name` within `dir` without following symbolic links.
+fn stat_at(dir: &Fd, name: &CStr) -> io::Result<libc::stat> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    cvt(unsafe { libc::fstatat(dir.0, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) })?;
+    Ok(stat)
+}
+
+/// Returns the target of the symbolic link `name` within `dir`.
+fn read_link_at(dir: &Fd, name: &CStr) -> io::Result<OsString> {
+    let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+    let len = unsafe {
+        libc::readlinkat(
+            dir.0,
+            name.as_ptr(),
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len(),
+        )
+    };
+    if len == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    buffer.truncate(len as usize);
+    Ok(OsString::from_vec(buffer))
+}
+
+/// Returns the names of the entries within `dir`, excluding `.` and `..`.
+///
+/// Read errors are indistinguishable from the end of the directory here, but any entries missed
+/// as a result cause the subsequent removal of the directory to fail.
+fn read_dir_names(dir: &Fd) -> io::Result<Vec<CString>> {
+    let fd = dir.try_clone()?;
+    let stream = unsafe { libc::fdopendir(fd.0) };
+    if stream.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    // The stream now owns the duplicated file descriptor.
+    mem::forget(fd);
+
+    let mut names = Vec::new();
+    unsafe {
+        libc::rewinddir(stream);
+        loop {
+            let entry = libc::readdir(stream);
+            if entry.is_null() {
+                break;
+            }
+
+            let name = CStr::from_ptr((*entry).d_name.as_ptr());
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                names.push(name.to_owned());
+            }
+        }
+        libc::closedir(stream);
+    }
+
+    Ok(names)
+}
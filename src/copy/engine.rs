@@ -0,0 +1,295 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Portable copy engine built on `std::fs`.
+
+use super::{CopyFailure, CopyOptions, CopyReport, CopySymlinkPolicy, OverwritePolicy};
+use crate::{engine::remove_link, symlinks::FollowedLinks, Error};
+use std::{
+    fs::{self, FileType},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// How a destination path is prepared before an entry is copied to it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Destination {
+    /// Nothing exists at the destination, so the entry can be created.
+    Create,
+
+    /// A directory exists at the destination, and the directory being copied is merged into it.
+    Merge,
+
+    /// An entry exists at the destination and is left in place.
+    Skip,
+}
+
+/// Stateful copy engine, accumulating a [`CopyReport`] as entries are copied.
+pub(super) struct CopyEngine<'a> {
+    options: &'a CopyOptions,
+    report: CopyReport,
+
+    /// Directories being copied, if links are followed.
+    links: Option<FollowedLinks>,
+}
+
+impl<'a> CopyEngine<'a> {
+    /// Creates an engine using the specified options.
+    pub fn new(options: &'a CopyOptions) -> Self {
+        Self {
+            options,
+            report: CopyReport::default(),
+            links: if options.symlinks == CopySymlinkPolicy::Follow {
+                Some(FollowedLinks::unconfined())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Returns the report of everything copied, skipped, or failed so far.
+    #[cfg(windows)]
+    pub fn report_mut(&mut self) -> &mut CopyReport {
+        &mut self.report
+    }
+
+    /// Consumes the engine, returning the final report.
+    pub fn into_report(self) -> CopyReport {
+        self.report
+    }
+
+    /// Copies the directory at `source` (following it if it is a link) and all of its contents to
+    /// `destination`.
+    ///
+    /// Failures to copy the root directory itself are always returned, regardless of whether the
+    /// copy continues past errors.
+    pub fn copy_root(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        self.copy_dir(source, destination, None)
+    }
+
+    /// Creates the directory at `destination`, unless a directory already exists there or an
+    /// existing entry is skipped. Returns whether the directory can be copied into.
+    #[cfg(windows)]
+    pub fn create_dir(&mut self, destination: &Path) -> io::Result<bool> {
+        match self.prepare(destination, true)? {
+            Destination::Create => fs::create_dir(destination)?,
+            Destination::Merge => {}
+            Destination::Skip => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Copies a single entry of the given (non-followed) type.
+    fn copy_entry(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        file_type: FileType,
+    ) -> io::Result<()> {
+        if file_type.is_symlink() {
+            self.copy_symlink(source, destination)
+        } else if file_type.is_dir() {
+            self.copy_dir(source, destination, None)
+        } else {
+            self.copy_file(source, destination, file_type)
+        }
+    }
+
+    /// Copies a directory and all of its contents.
+    ///
+    /// `canonical` is the canonical path of the directory, if it was reached by following a link.
+    fn copy_dir(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        canonical: Option<PathBuf>,
+    ) -> io::Result<()> {
+        let created = match self.prepare(destination, true)? {
+            Destination::Create => {
+                fs::create_dir(destination)?;
+                true
+            }
+            Destination::Merge => false,
+            Destination::Skip => return Ok(()),
+        };
+
+        if let Some(ref mut links) = self.links {
+            links.enter(source, canonical)?;
+        }
+        let result = self.copy_contents(source, destination);
+        if let Some(ref mut links) = self.links {
+            links.leave();
+        }
+        result?;
+
+        // Permissions are applied last, so that read-only directories can still be populated.
+        if created {
+            fs::set_permissions(destination, fs::metadata(source)?.permissions())?;
+        }
+
+        self.report.progress.dirs_copied += 1;
+        self.publish();
+        Ok(())
+    }
+
+    /// Copies every entry within the directory at `source` into the directory at `destination`.
+    fn copy_contents(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let entry_source = entry.path();
+            let result = entry.file_type().and_then(|file_type| {
+                self.copy_entry(
+                    &entry_source,
+                    &destination.join(entry.file_name()),
+                    file_type,
+                )
+            });
+            if let Err(error) = result {
+                self.fail(entry_source, error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies a symbolic link or junction according to the configured
+    /// [`CopySymlinkPolicy`].
+    fn copy_symlink(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        match self.options.symlinks {
+            CopySymlinkPolicy::CopyLink => self.copy_link(source, destination),
+            CopySymlinkPolicy::Refuse => Err(Error::SymlinkRefused {
+                path: source.to_path_buf(),
+            }
+            .into()),
+            CopySymlinkPolicy::Follow => match fs::metadata(source) {
+                Ok(ref metadata) if metadata.is_dir() => {
+                    let target = match self.links {
+                        Some(ref links) => links.resolve(source)?,
+                        None => None,
+                    };
+                    match target {
+                        Some(target) => self.copy_dir(&target, destination, Some(target.clone())),
+                        None => self.copy_link(source, destination),
+                    }
+                }
+                Ok(metadata) => self.copy_file(source, destination, metadata.file_type()),
+                // Dangling links cannot be followed.
+                Err(_) => self.copy_link(source, destination),
+            },
+        }
+    }
+
+    /// Recreates a symbolic link at `destination` with the same target as the one at `source`.
+    fn copy_link(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        if self.prepare(destination, false)? == Destination::Skip {
+            return Ok(());
+        }
+
+        create_link(source, destination)?;
+        self.report.progress.symlinks_copied += 1;
+        self.publish();
+        Ok(())
+    }
+
+    /// Copies the contents and permissions of a file, where `file_type` is the type of the file
+    /// itself (after following any link).
+    fn copy_file(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        file_type: FileType,
+    ) -> io::Result<()> {
+        // Opening special files such as FIFOs could block indefinitely.
+        if !file_type.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Source is not a regular file, directory, or symbolic link.",
+            ));
+        }
+
+        if self.prepare(destination, false)? == Destination::Skip {
+            return Ok(());
+        }
+
+        self.report.progress.bytes_copied += fs::copy(source, destination)?;
+        self.report.progress.files_copied += 1;
+        self.publish();
+        Ok(())
+    }
+
+    /// Prepares `destination` for an entry to be copied to it, applying the configured
+    /// [`OverwritePolicy`] to any entry that already exists there.
+    fn prepare(&mut self, destination: &Path, is_dir: bool) -> io::Result<Destination> {
+        let existing = match fs::symlink_metadata(destination) {
+            Ok(metadata) => metadata.file_type(),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Destination::Create)
+            }
+            Err(error) => return Err(error),
+        };
+
+        if existing.is_dir() && is_dir {
+            return Ok(Destination::Merge);
+        }
+
+        match self.options.overwrite {
+            OverwritePolicy::Skip => {
+                self.report.skipped.push(destination.to_path_buf());
+                Ok(Destination::Skip)
+            }
+            OverwritePolicy::Replace if !existing.is_dir() => {
+                remove_link(destination)?;
+                Ok(Destination::Create)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Destination `{}` already exists.", destination.display()),
+            )),
+        }
+    }
+
+    /// Records the failure to copy the entry at `path`, or returns the error if the copy should
+    /// not continue past it.
+    fn fail(&mut self, path: PathBuf, error: io::Error) -> io::Result<()> {
+        if !self.options.continue_on_error {
+            return Err(error);
+        }
+
+        self.report.failures.push(CopyFailure { path, error });
+        Ok(())
+    }
+
+    /// Passes the running totals of the report to the progress callback, if any.
+    fn publish(&self) {
+        if let Some(ref callback) = self.options.progress {
+            callback(self.report.progress);
+        }
+    }
+}
+
+/// Creates a symbolic link at `destination` with the same target as the one at `source`.
+#[cfg(unix)]
+pub(crate) fn create_link(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+}
+
+/// Creates a symbolic link at `destination` with the same target as the one at `source`.
+///
+/// Junctions are recreated as directory symbolic links, since `std` cannot create junctions.
+#[cfg(windows)]
+pub(crate) fn create_link(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+
+    let target = fs::read_link(source)?;
+    if crate::engine::is_dir_link(source)? {
+        symlink_dir(target, destination)
+    } else {
+        symlink_file(target, destination)
+    }
+}
@@ -0,0 +1,381 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Recursive directory copying, the counterpart to `remove_dir_all`.
+//!
+//! Like removal, copying uses the Windows Shell or Property System backends where the options
+//! allow an entire tree to be handed off to them, and a portable `std::fs`-based copy engine
+//! otherwise.
+
+mod engine;
+#[cfg(test)]
+mod tests;
+
+pub(crate) use engine::create_link;
+
+use crate::safeguard;
+use engine::CopyEngine;
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Callback receiving progress snapshots during a copy.
+type ProgressCallback = Arc<dyn Fn(CopyProgress) + Send + Sync>;
+
+/// How symbolic links (and junctions on Windows) within the source directory are copied.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CopySymlinkPolicy {
+    /// Links are recreated at the destination with the same target, without following them.
+    CopyLink,
+
+    /// The copy fails with an [`Error::SymlinkRefused`](enum.Error.html#variant.SymlinkRefused)
+    /// if any entry within the source is a link.
+    Refuse,
+
+    /// Links are followed, copying the files and directories they refer to in their place.
+    ///
+    /// Dangling links, and links leading back into a directory that is already being copied, are
+    /// recreated as links instead.
+    Follow,
+}
+
+impl Default for CopySymlinkPolicy {
+    fn default() -> Self {
+        CopySymlinkPolicy::CopyLink
+    }
+}
+
+/// What happens when a file or link being copied already exists at the destination.
+///
+/// Existing directories are always merged with the directories being copied into them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OverwritePolicy {
+    /// The copy fails with `io::ErrorKind::AlreadyExists`.
+    Fail,
+
+    /// The existing entry is left in place and listed by
+    /// [`CopyReport::skipped`](struct.CopyReport.html#method.skipped).
+    Skip,
+
+    /// The existing entry is removed and replaced. Existing directories are never replaced by
+    /// files or links, which fails with `io::ErrorKind::AlreadyExists` instead.
+    Replace,
+}
+
+impl Default for OverwritePolicy {
+    fn default() -> Self {
+        OverwritePolicy::Fail
+    }
+}
+
+/// Options and flags which can be used to configure how a directory tree is copied.
+///
+/// This follows the same builder conventions as [`RemoveOptions`](struct.RemoveOptions.html).
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{copy_dir_all, CopyOptions, OverwritePolicy};
+///
+/// fn main() -> std::io::Result<()> {
+///     let report = copy_dir_all(
+///         "build/output",
+///         "dist",
+///         CopyOptions::new().overwrite(OverwritePolicy::Replace),
+///     )?;
+///     println!("Copied {} bytes.", report.bytes_copied());
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct CopyOptions {
+    pub(crate) symlinks: CopySymlinkPolicy,
+    pub(crate) overwrite: OverwritePolicy,
+    pub(crate) continue_on_error: bool,
+    pub(crate) progress: Option<ProgressCallback>,
+}
+
+impl CopyOptions {
+    /// Creates a blank set of options, which copies links as links and fails if any file already
+    /// exists at the destination.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how symbolic links (and junctions on Windows) within the source are copied, which
+    /// defaults to [`CopySymlinkPolicy::CopyLink`](enum.CopySymlinkPolicy.html#variant.CopyLink).
+    ///
+    /// The source directory itself may be a link to a directory, which is always followed unless
+    /// links are refused.
+    pub fn symlinks(&mut self, policy: CopySymlinkPolicy) -> &mut Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sets what happens when a file or link already exists at the destination, which defaults to
+    /// [`OverwritePolicy::Fail`](enum.OverwritePolicy.html#variant.Fail).
+    pub fn overwrite(&mut self, policy: OverwritePolicy) -> &mut Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Sets whether the copy continues past entries that could not be copied, recording each
+    /// failure in the report (see [`CopyReport::failures`]) instead of returning the first error.
+    ///
+    /// Failures to read the source directory itself or to create the destination are still
+    /// returned as errors.
+    ///
+    /// [`CopyReport::failures`]: struct.CopyReport.html#method.failures
+    pub fn continue_on_error(&mut self, continue_on_error: bool) -> &mut Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Calls `callback` with the running totals after each entry is copied.
+    ///
+    /// The callback is run on the copying thread, so it should return quickly.
+    pub fn on_progress<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(CopyProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Returns whether each entry in a directory tree must be visited individually by the portable
+    /// copy engine, rather than handing the entire tree off to the platform backend.
+    #[cfg(windows)]
+    pub(crate) fn needs_entry_walk(&self) -> bool {
+        self.symlinks != CopySymlinkPolicy::Follow
+            || self.overwrite != OverwritePolicy::Replace
+            || self.continue_on_error
+            || self.progress.is_some()
+    }
+}
+
+impl fmt::Debug for CopyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CopyOptions")
+            .field("symlinks", &self.symlinks)
+            .field("overwrite", &self.overwrite)
+            .field("continue_on_error", &self.continue_on_error)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// Running totals of a copy in progress, passed to the callback set using
+/// [`CopyOptions::on_progress`](struct.CopyOptions.html#method.on_progress).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CopyProgress {
+    files_copied: u64,
+    dirs_copied: u64,
+    symlinks_copied: u64,
+    bytes_copied: u64,
+}
+
+impl CopyProgress {
+    /// Returns the number of files copied so far.
+    pub fn files_copied(&self) -> u64 {
+        self.files_copied
+    }
+
+    /// Returns the number of directories copied so far.
+    pub fn dirs_copied(&self) -> u64 {
+        self.dirs_copied
+    }
+
+    /// Returns the number of symbolic links recreated so far.
+    pub fn symlinks_copied(&self) -> u64 {
+        self.symlinks_copied
+    }
+
+    /// Returns the number of bytes of file contents copied so far.
+    pub fn bytes_copied(&self) -> u64 {
+        self.bytes_copied
+    }
+}
+
+/// Summary of the entries copied, skipped, and failed by [`copy_dir_all`](fn.copy_dir_all.html).
+///
+/// Entries copied through the Windows Shell or Property System APIs cannot be counted
+/// individually, so a tree handed off to one of those backends is counted as a single copied
+/// directory.
+#[derive(Debug, Default)]
+pub struct CopyReport {
+    pub(crate) progress: CopyProgress,
+    pub(crate) skipped: Vec<PathBuf>,
+    pub(crate) failures: Vec<CopyFailure>,
+}
+
+impl CopyReport {
+    /// Returns the number of files copied.
+    pub fn files_copied(&self) -> u64 {
+        self.progress.files_copied
+    }
+
+    /// Returns the number of directories copied, including the destination directory itself.
+    pub fn dirs_copied(&self) -> u64 {
+        self.progress.dirs_copied
+    }
+
+    /// Returns the number of symbolic links recreated at the destination.
+    pub fn symlinks_copied(&self) -> u64 {
+        self.progress.symlinks_copied
+    }
+
+    /// Returns the number of bytes of file contents copied.
+    pub fn bytes_copied(&self) -> u64 {
+        self.progress.bytes_copied
+    }
+
+    /// Returns the destination paths that already existed and were left in place, if
+    /// [`OverwritePolicy::Skip`](enum.OverwritePolicy.html#variant.Skip) was used.
+    pub fn skipped(&self) -> &[PathBuf] {
+        &self.skipped
+    }
+
+    /// Returns the entries that could not be copied, if
+    /// [`CopyOptions::continue_on_error`](struct.CopyOptions.html#method.continue_on_error) was
+    /// enabled.
+    pub fn failures(&self) -> &[CopyFailure] {
+        &self.failures
+    }
+
+    /// Returns whether every entry was copied, with none skipped or failed.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty() && self.failures.is_empty()
+    }
+}
+
+/// Entry that could not be copied, recorded when
+/// [`CopyOptions::continue_on_error`](struct.CopyOptions.html#method.continue_on_error) is enabled.
+#[derive(Debug)]
+pub struct CopyFailure {
+    path: PathBuf,
+    error: io::Error,
+}
+
+impl CopyFailure {
+    /// Returns the source path of the entry that could not be copied.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the error encountered while copying the entry.
+    pub fn error(&self) -> &io::Error {
+        &self.error
+    }
+}
+
+/// Copies the directory at `source`, along with all its contents, to `destination` using the
+/// specified options.
+///
+/// The destination directory is created if it does not exist, along with any missing parents.
+/// Existing directories at the destination are merged with those being copied, while existing
+/// files and links are handled according to the [`OverwritePolicy`](enum.OverwritePolicy.html).
+/// File permissions are copied along with file contents, and directory permissions are applied
+/// to newly created directories once their contents have been copied.
+///
+/// # Platform-specific behavior
+///
+/// On Windows, the whole tree is copied in a single `IFileOperation` or `SHFileOperationW`
+/// operation when links are followed ([`CopySymlinkPolicy::Follow`]), existing files are replaced
+/// ([`OverwritePolicy::Replace`]), and neither progress callbacks nor
+/// [`continue_on_error`](struct.CopyOptions.html#method.continue_on_error) are used, as the Shell
+/// always copies the targets of links and replaces existing files. Otherwise, and on all other
+/// platforms, the portable engine visits and copies each entry individually.
+///
+/// [`CopySymlinkPolicy::Follow`]: enum.CopySymlinkPolicy.html#variant.Follow
+/// [`OverwritePolicy::Replace`]: enum.OverwritePolicy.html#variant.Replace
+///
+/// # Errors
+///
+/// Returns an error if `source` is not a directory (or a link to one), if `destination` is
+/// located within `source`, or if any entry could not be copied and
+/// [`continue_on_error`](struct.CopyOptions.html#method.continue_on_error) is disabled. Entries
+/// copied before the failure are left in place.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{copy_dir_all, remove_dir_all, CopyOptions};
+///
+/// fn main() -> std::io::Result<()> {
+///     remove_dir_all("dist")?;
+///     copy_dir_all("build/output", "dist", &CopyOptions::new())?;
+///     Ok(())
+/// }
+/// ```
+pub fn copy_dir_all<P, Q>(
+    source: P,
+    destination: Q,
+    options: &CopyOptions,
+) -> io::Result<CopyReport>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let source = source.as_ref();
+    let destination = destination.as_ref();
+
+    if options.symlinks == CopySymlinkPolicy::Refuse
+        && fs::symlink_metadata(source)?.file_type().is_symlink()
+    {
+        return Err(crate::Error::SymlinkRefused {
+            path: source.to_path_buf(),
+        }
+        .into());
+    }
+
+    if !fs::metadata(source)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Source is not a directory or directory symlink.",
+        ));
+    }
+
+    let canonical_source = safeguard::comparison_key(&fs::canonicalize(source)?);
+    if safeguard::comparison_key(&safeguard::resolve(destination)).starts_with(&canonical_source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot copy a directory into itself.",
+        ));
+    }
+
+    if let Some(parent) = destination.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let mut engine = CopyEngine::new(options);
+
+    #[cfg(windows)]
+    {
+        if !options.needs_entry_walk() {
+            if engine.create_dir(destination)? {
+                let entries = fs::read_dir(source)?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<io::Result<Vec<_>>>()?;
+                if !entries.is_empty() {
+                    let entries: Vec<&Path> = entries.iter().map(PathBuf::as_path).collect();
+                    crate::windows::copy_items(&entries, destination)?;
+                }
+                engine.report_mut().progress.dirs_copied += 1;
+            }
+
+            return Ok(engine.into_report());
+        }
+    }
+
+    engine.copy_root(source, destination)?;
+    Ok(engine.into_report())
+}
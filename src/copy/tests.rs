@@ -0,0 +1,258 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Directory copy tests.
+
+use super::{copy_dir_all, CopyOptions, OverwritePolicy};
+use crate::engine::tests::populate_dir;
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tempfile::TempDir;
+
+#[cfg(unix)]
+use super::CopySymlinkPolicy;
+#[cfg(unix)]
+use crate::Error;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+
+/// Creates a directory at `path` containing a few entries, with some file contents.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)?;
+    fs::write(path.join("baz").join("data"), b"contents")
+}
+
+/// Tests whether a tree is copied with its contents, and every entry is counted.
+#[test]
+fn copy_works() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    let destination = base_dir.path().join("nested").join("destination");
+    create_tree(&source).unwrap();
+
+    let report = copy_dir_all(&source, &destination, &CopyOptions::new()).unwrap();
+    assert_eq!(report.files_copied(), 4);
+    assert_eq!(report.dirs_copied(), 2);
+    assert_eq!(report.bytes_copied(), 8);
+    assert!(report.is_complete());
+
+    assert!(destination.join("foo").is_file());
+    assert!(destination.join("baz").join("qux").is_file());
+    assert_eq!(
+        fs::read(destination.join("baz").join("data")).unwrap(),
+        b"contents"
+    );
+    assert!(source.join("baz").join("data").is_file());
+}
+
+/// Tests whether existing destination files are handled according to the overwrite policy, while
+/// existing directories are merged.
+#[test]
+fn overwrite_policies_work() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    let destination = base_dir.path().join("destination");
+    create_tree(&source).unwrap();
+    fs::create_dir_all(destination.join("baz")).unwrap();
+    fs::write(destination.join("baz").join("data"), b"old").unwrap();
+    fs::write(destination.join("extra"), b"").unwrap();
+
+    let error = copy_dir_all(&source, &destination, &CopyOptions::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+    let report = copy_dir_all(
+        &source,
+        &destination,
+        CopyOptions::new().overwrite(OverwritePolicy::Skip),
+    )
+    .unwrap();
+    assert!(report
+        .skipped()
+        .contains(&destination.join("baz").join("data")));
+    assert_eq!(
+        fs::read(destination.join("baz").join("data")).unwrap(),
+        b"old"
+    );
+
+    let report = copy_dir_all(
+        &source,
+        &destination,
+        CopyOptions::new().overwrite(OverwritePolicy::Replace),
+    )
+    .unwrap();
+    assert!(report.is_complete());
+    assert_eq!(
+        fs::read(destination.join("baz").join("data")).unwrap(),
+        b"contents"
+    );
+    assert!(destination.join("extra").is_file());
+}
+
+/// Tests whether links are copied, refused, or followed according to the link policy.
+#[test]
+#[cfg(unix)]
+fn symlink_policies_work() {
+    let base_dir = TempDir::new().unwrap();
+    let outside = base_dir.path().join("outside");
+    create_tree(&outside).unwrap();
+    let source = base_dir.path().join("source");
+    create_tree(&source).unwrap();
+    symlink(&outside, source.join("dir-link")).unwrap();
+    symlink(outside.join("baz").join("data"), source.join("file-link")).unwrap();
+    symlink(base_dir.path().join("missing"), source.join("dangling")).unwrap();
+    symlink(&source, source.join("baz").join("loop")).unwrap();
+
+    let destination = base_dir.path().join("links");
+    let report = copy_dir_all(&source, &destination, &CopyOptions::new()).unwrap();
+    assert_eq!(report.symlinks_copied(), 4);
+    assert_eq!(
+        fs::read_link(destination.join("dir-link")).unwrap(),
+        outside
+    );
+    assert!(fs::symlink_metadata(destination.join("dangling"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+
+    let error = copy_dir_all(
+        &source,
+        base_dir.path().join("refused"),
+        CopyOptions::new().symlinks(CopySymlinkPolicy::Refuse),
+    )
+    .unwrap_err();
+    match Error::from_io_error(&error) {
+        Some(Error::SymlinkRefused { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+
+    let destination = base_dir.path().join("followed");
+    let report = copy_dir_all(
+        &source,
+        &destination,
+        CopyOptions::new().symlinks(CopySymlinkPolicy::Follow),
+    )
+    .unwrap();
+    assert_eq!(report.symlinks_copied(), 2);
+    for path in &["dir-link", "file-link"] {
+        assert!(!fs::symlink_metadata(destination.join(path))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+    assert_eq!(
+        fs::read(destination.join("dir-link").join("baz").join("data")).unwrap(),
+        b"contents"
+    );
+    assert_eq!(
+        fs::read(destination.join("file-link")).unwrap(),
+        b"contents"
+    );
+    assert_eq!(
+        fs::read_link(destination.join("baz").join("loop")).unwrap(),
+        source
+    );
+}
+
+/// Tests whether failures are collected per entry when continuing past errors, and returned
+/// otherwise.
+#[test]
+#[cfg(unix)]
+fn failures_are_reported_per_entry() {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    create_tree(&source).unwrap();
+    let fifo = source.join("baz").join("fifo");
+    let fifo_path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o644) }, 0);
+
+    let error =
+        copy_dir_all(&source, base_dir.path().join("failed"), &CopyOptions::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    let destination = base_dir.path().join("continued");
+    let report = copy_dir_all(
+        &source,
+        &destination,
+        CopyOptions::new().continue_on_error(true),
+    )
+    .unwrap();
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(report.failures()[0].path(), fifo);
+    assert_eq!(
+        report.failures()[0].error().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    assert!(!report.is_complete());
+    assert_eq!(report.files_copied(), 4);
+    assert!(destination.join("baz").join("data").is_file());
+}
+
+/// Tests whether progress is reported after each entry, ending with the final totals.
+#[test]
+fn progress_is_reported() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    create_tree(&source).unwrap();
+
+    let snapshots = Arc::new(Mutex::new(Vec::new()));
+    let mut options = CopyOptions::new();
+    {
+        let snapshots = Arc::clone(&snapshots);
+        options.on_progress(move |progress| snapshots.lock().unwrap().push(progress));
+    }
+
+    let report = copy_dir_all(&source, base_dir.path().join("destination"), &options).unwrap();
+    let snapshots = snapshots.lock().unwrap();
+    assert_eq!(snapshots.len(), 6);
+    assert!(snapshots
+        .windows(2)
+        .all(|pair| pair[0].files_copied() <= pair[1].files_copied()));
+    let last = snapshots.last().unwrap();
+    assert_eq!(last.files_copied(), report.files_copied());
+    assert_eq!(last.bytes_copied(), report.bytes_copied());
+}
+
+/// Tests whether copying a directory into itself, or copying something other than a directory,
+/// is refused.
+#[test]
+fn invalid_copies_fail() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    create_tree(&source).unwrap();
+
+    let error = copy_dir_all(
+        &source,
+        source.join("baz").join("copy"),
+        &CopyOptions::new(),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(!source.join("baz").join("copy").exists());
+
+    let error = copy_dir_all(
+        source.join("foo"),
+        base_dir.path().join("file"),
+        &CopyOptions::new(),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let error = copy_dir_all(
+        base_dir.path().join("missing"),
+        base_dir.path().join("copy"),
+        &CopyOptions::new(),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
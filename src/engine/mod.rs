@@ -0,0 +1,535 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Portable directory removal engine built on `std::fs`.
+//!
+//! The engine visits each entry in a directory tree individually, which allows options such as
+//! Git ignore filtering, filesystem boundary checks, and shredding to be applied per entry and
+//! every removal to be counted in a [`RemovalReport`](../struct.RemovalReport.html). On Windows,
+//! entire subtrees are handed off to the Shell or Property System backends whenever no option
+//! requires visiting their entries.
+//!
+//! Other processes may create or remove entries within the tree during removal. Entries that
+//! vanish before the engine removes them are treated as removed (without being counted), and a
+//! directory found to be non-empty after its contents were removed is re-scanned, up to
+//! [`MAX_PASSES`] times in total, before failing with
+//! [`Error::KeptChanging`](../enum.Error.html#variant.KeptChanging).
+
+#[cfg(test)]
+pub(crate) mod tests;
+
+use crate::{
+    background::Monitor,
+    journal::Journal,
+    mounts::{Boundary, MountPoints},
+    permissions, shred,
+    symlinks::FollowedLinks,
+    Error, RemovalReport, RemoveOptions, SkipReason, SymlinkPolicy,
+};
+use std::{
+    collections::BTreeSet,
+    fs::{self, FileType},
+    io,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Most times the contents of a directory are removed before giving up on a directory that keeps
+/// being refilled by other processes.
+pub(crate) const MAX_PASSES: u32 = 5;
+
+/// Stateful removal engine, accumulating a [`RemovalReport`] as entries are removed.
+pub(crate) struct Engine<'a> {
+    options: &'a RemoveOptions,
+    report: RemovalReport,
+
+    /// Mount points to use for boundary detection instead of those of the current process.
+    mount_points: Option<MountPoints>,
+
+    /// Filesystem boundary of the current target, if `one_file_system` is enabled.
+    boundary: Option<Boundary>,
+
+    /// Destination for progress updates and source of cancellation requests, if any.
+    monitor: Option<Arc<Monitor>>,
+
+    /// Directories being emptied, if links are followed.
+    links: Option<FollowedLinks>,
+
+    /// Remaining directories that entries were removed from, to be synced if removals are durable.
+    /// Paths are ordered by component, so the descendants of a directory follow it contiguously.
+    dirty: BTreeSet<PathBuf>,
+
+    /// Journal recording the progress of the removal, if any.
+    journal: Option<Journal>,
+}
+
+impl<'a> Engine<'a> {
+    /// Creates an engine using the specified options.
+    pub fn new(options: &'a RemoveOptions) -> Self {
+        Self {
+            options,
+            report: RemovalReport::default(),
+            mount_points: None,
+            boundary: None,
+            monitor: None,
+            links: None,
+            dirty: BTreeSet::new(),
+            journal: None,
+        }
+    }
+
+    /// Publishes progress to, and checks for cancellation using, the specified monitor.
+    pub fn with_monitor(mut self, monitor: Option<Arc<Monitor>>) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    /// Records progress in the specified journal, which is removed once the removal completes.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Overrides the mount points used to detect filesystem boundaries.
+    #[cfg(test)]
+    pub fn with_mount_points(mut self, mount_points: MountPoints) -> Self {
+        self.mount_points = Some(mount_points);
+        self
+    }
+
+    /// Returns the report of everything removed or skipped so far.
+    pub fn report_mut(&mut self) -> &mut RemovalReport {
+        &mut self.report
+    }
+
+    /// Writes the progress of the removal so far to the journal, if any.
+    pub fn flush_journal(&mut self) -> io::Result<()> {
+        match self.journal {
+            Some(ref mut journal) => journal.flush(&self.report),
+            None => Ok(()),
+        }
+    }
+
+    /// Syncs every remaining directory that entries were removed from if removals are durable,
+    /// and removes the journal if any, then returns the final report.
+    pub fn finish(mut self) -> io::Result<RemovalReport> {
+        for dir in &self.dirty {
+            sync_dir(dir)?;
+        }
+        if let Some(journal) = self.journal.take() {
+            journal.complete(&mut self.report)?;
+        }
+
+        Ok(self.report)
+    }
+
+    /// Prepares the engine to remove entries within the directory at `root`.
+    pub fn begin(&mut self, root: &Path) -> io::Result<()> {
+        self.boundary = if self.options.one_file_system {
+            let mount_points = match self.mount_points {
+                Some(ref mount_points) => mount_points.clone(),
+                None => MountPoints::load()?,
+            };
+            Some(Boundary::new(root, mount_points)?)
+        } else {
+            None
+        };
+        self.links = if self.options.symlinks == SymlinkPolicy::DeleteTargetContents {
+            Some(FollowedLinks::new(root)?)
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
+    /// Removes the directory at `path` and all of its contents.
+    ///
+    /// Symbolic links to directories are handled according to the configured
+    /// [`SymlinkPolicy`]. Any other type of file is rejected with `io::ErrorKind::InvalidData`,
+    /// matching the Windows backends.
+    pub fn remove_root(&mut self, path: &Path) -> io::Result<()> {
+        let file_type = fs::symlink_metadata(path)?.file_type();
+        if !file_type.is_dir() && !is_dir_symlink(path, file_type) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Target is not a directory or directory symlink.",
+            ));
+        }
+
+        self.begin(path)?;
+        self.report.root_removed = self.remove_entry(path, file_type)?;
+
+        Ok(())
+    }
+
+    /// Removes a single entry of the given type, recursing into it if it is a directory.
+    ///
+    /// Returns whether the entry was removed, as opposed to being left in place (along with any of
+    /// its ancestors) due to the configured options.
+    pub fn remove_entry(&mut self, path: &Path, file_type: FileType) -> io::Result<bool> {
+        if let Some(ref monitor) = self.monitor {
+            monitor.check_cancelled()?;
+        }
+
+        match self.skip_boundary(path, file_type) {
+            Ok(true) => return Ok(false),
+            Ok(false) => {}
+            Err(ref error) if is_vanished(error) => return Ok(true),
+            Err(error) => return Err(error),
+        }
+
+        if file_type.is_symlink() {
+            if !self.remove_symlink(path)? {
+                return Ok(false);
+            }
+        } else if file_type.is_dir() {
+            let removed = self.remove_tree(path)?;
+            self.publish();
+            return Ok(removed);
+        } else {
+            let shred = self.options.shred.as_ref().filter(|_| file_type.is_file());
+            let result = match shred {
+                Some(shred) => shred::shred_file(path, shred),
+                None => fs::remove_file(path)
+                    .map(|()| true)
+                    .map_err(|error| permissions::explain(path, error)),
+            };
+            match result {
+                Ok(true) if shred.is_some() => self.report.shredded.push(path.to_path_buf()),
+                Ok(true) => {}
+                Ok(false) => {
+                    self.report.skip(path.to_path_buf(), SkipReason::HardLinked);
+                    return Ok(false);
+                }
+                Err(ref error) if is_vanished(error) => return Ok(true),
+                Err(error) => return Err(error),
+            }
+            self.removed(path);
+            self.report.files_removed += 1;
+        }
+
+        self.publish();
+        Ok(true)
+    }
+
+    /// Checks whether the entry at `path` resides on a different filesystem than the target when
+    /// `one_file_system` is enabled, recording it as skipped if so.
+    pub fn skip_boundary(&mut self, path: &Path, file_type: FileType) -> io::Result<bool> {
+        let crossed = match self.boundary {
+            Some(ref boundary) if file_type.is_symlink() => boundary.is_crossed_by_link(path),
+            Some(ref boundary) if file_type.is_dir() => boundary.is_crossed_by_dir(path)?,
+            _ => false,
+        };
+
+        if crossed {
+            self.report
+                .skip(path.to_path_buf(), SkipReason::FileSystemBoundary);
+        }
+
+        Ok(crossed)
+    }
+
+    /// Removes a directory whose contents have already been removed.
+    ///
+    /// A directory that was already removed by another process counts as success, but is not
+    /// counted as removed.
+    pub fn remove_empty_dir(&mut self, path: &Path) -> io::Result<()> {
+        match fs::remove_dir(path) {
+            Ok(()) => self.report.dirs_removed += 1,
+            Err(ref error) if is_vanished(error) => {}
+            Err(error) => return Err(permissions::explain(path, error)),
+        }
+        self.removed(path);
+        self.publish();
+        Ok(())
+    }
+
+    /// Records that the entry at `path` was removed, so that its parent directory is synced if
+    /// removals are durable.
+    ///
+    /// Directories are only synced once, after every removal, and not at all if they (or any of
+    /// their ancestors) are removed themselves.
+    fn removed(&mut self, path: &Path) {
+        if !self.options.durable {
+            return;
+        }
+
+        let removed: Vec<_> = self
+            .dirty
+            .range::<Path, _>((Bound::Included(path), Bound::Unbounded))
+            .take_while(|dir| dir.starts_with(path))
+            .cloned()
+            .collect();
+        for dir in removed {
+            self.dirty.remove(&dir);
+        }
+
+        let parent = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        if !self.dirty.contains(parent) {
+            self.dirty.insert(parent.to_path_buf());
+        }
+    }
+
+    /// Publishes the running totals of the report to the monitor and journal, if any.
+    fn publish(&mut self) {
+        if let Some(ref monitor) = self.monitor {
+            monitor.update(&self.report);
+        }
+        if let Some(ref mut journal) = self.journal {
+            journal.record(&self.report);
+        }
+    }
+
+    /// Removes a directory (not a directory symlink) and all of its contents, returning whether
+    /// the directory itself could be removed.
+    fn remove_tree(&mut self, path: &Path) -> io::Result<bool> {
+        #[cfg(windows)]
+        {
+            if !self.options.needs_entry_walk() {
+                crate::windows::remove_dir_all(path)?;
+                self.removed(path);
+                self.report.dirs_removed += 1;
+                return Ok(true);
+            }
+        }
+
+        remove_in_passes(
+            self,
+            path,
+            |engine, path| engine.remove_contents(path, None),
+            Engine::remove_empty_dir,
+        )
+    }
+
+    /// Removes all entries within the directory at `path`, returning whether it was emptied.
+    ///
+    /// `canonical` is the canonical path of the directory, if it was reached by following a link.
+    ///
+    /// Directories and entries removed by other processes in the meantime are skipped.
+    fn remove_contents(&mut self, path: &Path, canonical: Option<PathBuf>) -> io::Result<bool> {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(ref error) if is_vanished(error) => return Ok(true),
+            Err(error) => return Err(error),
+        };
+
+        if let Some(ref mut links) = self.links {
+            links.enter(path, canonical)?;
+        }
+
+        let mut emptied = true;
+        for entry in entries {
+            let (entry_path, file_type) = match entry.and_then(|entry| {
+                let file_type = entry.file_type()?;
+                Ok((entry.path(), file_type))
+            }) {
+                Ok(entry) => entry,
+                Err(ref error) if is_vanished(error) => continue,
+                Err(error) => return Err(error),
+            };
+            emptied &= self.remove_entry(&entry_path, file_type)?;
+        }
+
+        if let Some(ref mut links) = self.links {
+            links.leave();
+        }
+
+        Ok(emptied)
+    }
+
+    /// Removes a symbolic link or junction according to the configured [`SymlinkPolicy`],
+    /// removing the contents of its target first if it is followed.
+    ///
+    /// Returns whether the link was removed, as opposed to being left in place because its target
+    /// resides on a different filesystem and `one_file_system` is enabled.
+    fn remove_symlink(&mut self, path: &Path) -> io::Result<bool> {
+        if self.options.symlinks == SymlinkPolicy::Refuse {
+            return Err(Error::SymlinkRefused {
+                path: path.to_path_buf(),
+            }
+            .into());
+        }
+
+        let target = match self.links {
+            Some(ref links) => links.resolve(path)?,
+            None => None,
+        };
+        if let Some(target) = target {
+            // Entries within the target are checked as they are reached, but the target itself may
+            // be a mount point within the removal target.
+            let crossed = match self.boundary {
+                Some(ref boundary) => boundary.is_crossed_by_dir(&target)?,
+                None => false,
+            };
+            if crossed {
+                self.report
+                    .skip(path.to_path_buf(), SkipReason::FileSystemBoundary);
+                return Ok(false);
+            }
+
+            self.remove_contents(&target, Some(target.clone()))?;
+        }
+
+        match remove_link(path) {
+            Ok(()) => {}
+            Err(ref error) if is_vanished(error) => return Ok(true),
+            Err(error) => return Err(permissions::explain(path, error)),
+        }
+        self.removed(path);
+        self.report.symlinks_removed += 1;
+        Ok(true)
+    }
+}
+
+/// Removes the directory at `path` by emptying it with `empty`, then removing it with `remove`,
+/// emptying it again each time another process refills it in between, up to [`MAX_PASSES`] times
+/// in total.
+///
+/// Returns whether the directory was removed, as opposed to being left in place because `empty`
+/// could not empty it.
+fn remove_in_passes<T, E, R>(
+    state: &mut T,
+    path: &Path,
+    mut empty: E,
+    mut remove: R,
+) -> io::Result<bool>
+where
+    E: FnMut(&mut T, &Path) -> io::Result<bool>,
+    R: FnMut(&mut T, &Path) -> io::Result<()>,
+{
+    let mut passes = 1;
+    loop {
+        if !empty(state, path)? {
+            return Ok(false);
+        }
+
+        // Other processes may have created entries since the contents were listed.
+        match remove(state, path) {
+            Ok(()) => return Ok(true),
+            Err(ref error) if is_not_empty(error) && passes < MAX_PASSES => passes += 1,
+            Err(error) => {
+                if !is_not_empty(&error) {
+                    return Err(error);
+                }
+
+                return Err(Error::KeptChanging {
+                    path: path.to_path_buf(),
+                    passes,
+                    source: error,
+                }
+                .into());
+            }
+        }
+    }
+}
+
+/// Returns whether `error` indicates that an entry no longer exists, having been removed by
+/// another process.
+pub(crate) fn is_vanished(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::NotFound
+}
+
+/// Returns whether `error` indicates that a directory could not be removed as it is not empty.
+#[cfg(unix)]
+fn is_not_empty(error: &io::Error) -> bool {
+    // POSIX allows `EEXIST` in place of `ENOTEMPTY`.
+    [libc::ENOTEMPTY, libc::EEXIST].contains(&error.raw_os_error().unwrap_or(0))
+}
+
+/// Returns whether `error` indicates that a directory could not be removed as it is not empty.
+#[cfg(windows)]
+fn is_not_empty(error: &io::Error) -> bool {
+    use winapi::shared::winerror::ERROR_DIR_NOT_EMPTY;
+
+    error.raw_os_error() == Some(ERROR_DIR_NOT_EMPTY as i32)
+}
+
+/// Returns whether `error` indicates that a directory could not be removed as it is not empty,
+/// which cannot be determined on this platform.
+#[cfg(not(any(unix, windows)))]
+fn is_not_empty(_error: &io::Error) -> bool {
+    false
+}
+
+/// Returns whether the entry at `path` with the given (non-followed) file type is a symbolic link
+/// that resolves to a directory.
+pub(crate) fn is_dir_symlink(path: &Path, file_type: FileType) -> bool {
+    file_type.is_symlink()
+        && fs::metadata(path)
+            .ok()
+            .map_or(false, |metadata| metadata.is_dir())
+}
+
+/// Removes a symbolic link without touching its target.
+#[cfg(not(windows))]
+pub(crate) fn remove_link(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+}
+
+/// Removes a symbolic link or junction without touching its target.
+///
+/// Windows distinguishes between file and directory links, with the latter requiring
+/// `RemoveDirectoryW` instead of `DeleteFileW`.
+#[cfg(windows)]
+pub(crate) fn remove_link(path: &Path) -> io::Result<()> {
+    if is_dir_link(path)? {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Returns whether the link or junction at `path` is a directory link, as recorded in the link
+/// itself rather than by resolving its target.
+#[cfg(windows)]
+pub(crate) fn is_dir_link(path: &Path) -> io::Result<bool> {
+    use std::os::windows::fs::MetadataExt;
+    use winapi::um::winnt::FILE_ATTRIBUTE_DIRECTORY;
+
+    Ok(fs::symlink_metadata(path)?.file_attributes() & FILE_ATTRIBUTE_DIRECTORY != 0)
+}
+
+/// Flushes the entries of the directory at `path` to the storage device, so that removals within
+/// it survive a crash or power loss.
+///
+/// Directories that no longer exist are ignored, as there is nothing left to flush.
+#[cfg(not(windows))]
+pub(crate) fn sync_dir(path: &Path) -> io::Result<()> {
+    match fs::File::open(path) {
+        Ok(dir) => dir.sync_all(),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Flushes the entries of the directory at `path` to the storage device, so that removals within
+/// it survive a crash or power loss.
+///
+/// Directories that no longer exist are ignored, as there is nothing left to flush. Directory
+/// handles must be opened for writing with `FILE_FLAG_BACKUP_SEMANTICS` for `FlushFileBuffers`
+/// (which `sync_all` calls) to accept them.
+#[cfg(windows)]
+pub(crate) fn sync_dir(path: &Path) -> io::Result<()> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use winapi::um::winbase::FILE_FLAG_BACKUP_SEMANTICS;
+
+    let dir = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path);
+    match dir {
+        Ok(dir) => dir.sync_all(),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
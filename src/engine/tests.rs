@@ -0,0 +1,411 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Portable removal engine tests.
+
+use super::{remove_in_passes, sync_dir, Engine, MAX_PASSES};
+use crate::{mounts::MountPoints, Error, RemoveOptions, SkipReason};
+use std::{fs, io, path::Path};
+use tempfile::TempDir;
+
+/// Creates an empty file at the specified path.
+pub(crate) fn create_empty_file(path: &Path) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)
+        .map(|_| ())
+}
+
+/// Populates `dir_path` with a couple of files and a non-empty subdirectory.
+pub(crate) fn populate_dir(dir_path: &Path) -> io::Result<()> {
+    create_empty_file(&dir_path.join("foo"))?;
+    create_empty_file(&dir_path.join("bar"))?;
+
+    let baz_path = dir_path.join("baz");
+    fs::create_dir(&baz_path)?;
+    create_empty_file(&baz_path.join("qux"))
+}
+
+/// Tests whether the engine removes a non-empty directory and counts every entry.
+#[test]
+fn non_empty_directory_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let options = RemoveOptions::new();
+    let mut engine = Engine::new(&options);
+    engine.remove_root(&dir_path).unwrap();
+    assert_eq!(
+        fs::metadata(&dir_path).err().map(|error| error.kind()),
+        Some(io::ErrorKind::NotFound)
+    );
+
+    let report = engine.finish().unwrap();
+    assert!(report.root_removed());
+    if cfg!(not(windows)) {
+        assert_eq!(report.files_removed(), 3);
+        assert_eq!(report.dirs_removed(), 2);
+    }
+}
+
+/// Tests whether the engine rejects regular files.
+#[test]
+fn file_fails() {
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    create_empty_file(&file_path).unwrap();
+
+    assert_eq!(
+        Engine::new(&RemoveOptions::new())
+            .remove_root(&file_path)
+            .err()
+            .map(|error| error.kind()),
+        Some(io::ErrorKind::InvalidData)
+    );
+    assert!(fs::metadata(&file_path).unwrap().is_file());
+}
+
+/// Tests whether the engine rejects targets that don't exist.
+#[test]
+fn missing_target_fails() {
+    let base_dir = TempDir::new().unwrap();
+
+    assert_eq!(
+        Engine::new(&RemoveOptions::new())
+            .remove_root(&base_dir.path().join("missing"))
+            .err()
+            .map(|error| error.kind()),
+        Some(io::ErrorKind::NotFound)
+    );
+}
+
+/// Tests whether the engine removes directory symlinks, both as the target and within the tree,
+/// without deleting what they point to.
+#[test]
+#[cfg(unix)]
+fn directory_symlinks_are_not_followed() {
+    use std::os::unix::fs::symlink;
+
+    let base_dir = TempDir::new().unwrap();
+    let outside_path = base_dir.path().join("outside");
+    fs::create_dir(&outside_path).unwrap();
+    populate_dir(&outside_path).unwrap();
+
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    symlink(&outside_path, dir_path.join("link")).unwrap();
+
+    let root_link_path = base_dir.path().join("root_link");
+    symlink(&outside_path, &root_link_path).unwrap();
+
+    let options = RemoveOptions::new();
+    let mut engine = Engine::new(&options);
+    engine.remove_root(&dir_path).unwrap();
+    engine.remove_root(&root_link_path).unwrap();
+    assert_eq!(engine.finish().unwrap().symlinks_removed(), 2);
+
+    assert!(fs::symlink_metadata(&dir_path).is_err());
+    assert!(fs::symlink_metadata(&root_link_path).is_err());
+    assert!(outside_path.join("baz").join("qux").is_file());
+}
+
+/// Tests whether `one_file_system` skips directories listed as mount points, such as bind mounts
+/// sharing the target's device, along with their ancestors.
+#[test]
+fn one_file_system_skips_mount_points() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+    let mount_path = dir_path.join("baz");
+
+    let canonical = fs::canonicalize(&mount_path).unwrap();
+    let mountinfo = format!(
+        "36 35 98:0 / {} rw,noatime master:1 - ext3 /dev/root rw\n",
+        canonical.to_string_lossy().replace(' ', "\\040")
+    );
+
+    let options = RemoveOptions::new().one_file_system(true).clone();
+    let mut engine = Engine::new(&options).with_mount_points(MountPoints::parse(&mountinfo));
+    engine.remove_root(&dir_path).unwrap();
+
+    let report = engine.finish().unwrap();
+    assert!(!report.root_removed());
+    assert_eq!(report.skipped().len(), 1);
+    assert_eq!(report.skipped()[0].path(), mount_path.as_path());
+    assert_eq!(report.skipped()[0].reason(), SkipReason::FileSystemBoundary);
+    assert!(!dir_path.join("foo").exists());
+    assert!(mount_path.join("qux").is_file());
+}
+
+/// Tests whether `one_file_system` skips a tmpfs mounted within the target. This requires
+/// permission to mount filesystems, so the test passes trivially if mounting fails.
+#[test]
+#[cfg(target_os = "linux")]
+fn one_file_system_skips_tmpfs() {
+    use std::process::{Command, Stdio};
+
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    let mount_path = dir_path.join("mnt");
+    fs::create_dir_all(&mount_path).unwrap();
+    create_empty_file(&dir_path.join("foo")).unwrap();
+
+    let mounted = Command::new("mount")
+        .args(&["-t", "tmpfs", "none"])
+        .arg(&mount_path)
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+        .map_or(false, |status| status.success());
+    if !mounted {
+        return;
+    }
+
+    create_empty_file(&mount_path.join("bar")).unwrap();
+    let result = crate::remove_dir_all_with(&dir_path, RemoveOptions::new().one_file_system(true));
+    let bar_exists = mount_path.join("bar").is_file();
+    Command::new("umount").arg(&mount_path).status().unwrap();
+
+    let report = result.unwrap();
+    assert!(bar_exists);
+    assert!(!report.root_removed());
+    assert_eq!(report.files_removed(), 1);
+    assert_eq!(report.skipped()[0].reason(), SkipReason::FileSystemBoundary);
+}
+
+/// Tests whether durable removals sync each remaining directory entries were removed from, once,
+/// and skip directories that were removed themselves.
+#[test]
+fn durable_removal_syncs_remaining_dirs() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+    create_empty_file(&dir_path.join("baz").join("quux")).unwrap();
+
+    let mut options = RemoveOptions::new();
+    options.durable(true);
+    let mut engine = Engine::new(&options);
+    for name in ["foo", "bar"].iter() {
+        let path = dir_path.join(name);
+        engine
+            .remove_entry(&path, fs::symlink_metadata(&path).unwrap().file_type())
+            .unwrap();
+    }
+    assert!(engine.dirty.iter().eq([dir_path.clone()].iter()));
+
+    let nested = dir_path.join("baz").join("quux");
+    let file_type = fs::symlink_metadata(&nested).unwrap().file_type();
+    engine.remove_entry(&nested, file_type).unwrap();
+    assert!(engine
+        .dirty
+        .iter()
+        .eq([dir_path.clone(), dir_path.join("baz")].iter()));
+
+    engine.remove_root(&dir_path).unwrap();
+    assert!(engine
+        .dirty
+        .iter()
+        .eq([base_dir.path().to_path_buf()].iter()));
+    assert!(engine.finish().unwrap().root_removed());
+}
+
+/// Tests whether errors syncing directories are returned, while missing directories are ignored.
+#[test]
+fn sync_dir_works() {
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    create_empty_file(&file_path).unwrap();
+
+    sync_dir(base_dir.path()).unwrap();
+    sync_dir(&base_dir.path().join("missing")).unwrap();
+    assert!(sync_dir(&file_path.join("child")).is_err());
+}
+
+/// Tests whether entries removed by another process before the engine reaches them count as
+/// removed, without being counted in the report.
+#[test]
+fn vanished_entries_are_tolerated() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let entries: Vec<_> = fs::read_dir(&dir_path)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.path(), entry.file_type().unwrap())
+        })
+        .collect();
+    fs::remove_dir_all(&dir_path).unwrap();
+
+    let options = RemoveOptions::new();
+    let mut engine = Engine::new(&options);
+    for (path, file_type) in &entries {
+        assert!(engine.remove_entry(path, *file_type).unwrap());
+    }
+    engine.remove_empty_dir(&dir_path).unwrap();
+
+    let report = engine.finish().unwrap();
+    assert_eq!(report.files_removed(), 0);
+    assert_eq!(report.dirs_removed(), 0);
+}
+
+/// Removes every file in the directory at `path`, then recreates the file `refill` in it if
+/// `refills` is nonzero, decrementing it. Returns whether the directory was emptied.
+fn empty_and_refill(refills: &mut u32, path: &Path) -> io::Result<bool> {
+    for entry in fs::read_dir(path)? {
+        fs::remove_file(entry?.path())?;
+    }
+
+    if *refills > 0 {
+        *refills -= 1;
+        create_empty_file(&path.join("refill"))?;
+    }
+
+    Ok(true)
+}
+
+/// Tests whether a directory refilled after its contents were removed is emptied again on a
+/// second pass.
+#[test]
+fn refilled_dir_is_rescanned() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    create_empty_file(&dir_path.join("foo")).unwrap();
+
+    let mut passes = 0;
+    let mut refills = 1;
+    let removed = remove_in_passes(
+        &mut refills,
+        &dir_path,
+        |refills, path| {
+            passes += 1;
+            empty_and_refill(refills, path)
+        },
+        |_, path| fs::remove_dir(path),
+    )
+    .unwrap();
+
+    assert!(removed);
+    assert_eq!(passes, 2);
+    assert!(!dir_path.exists());
+}
+
+/// Tests whether a directory refilled after every pass fails with `Error::KeptChanging` once the
+/// maximum number of passes is reached.
+#[test]
+fn refilling_dir_fails() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+
+    let mut refills = std::u32::MAX;
+    let error = remove_in_passes(&mut refills, &dir_path, empty_and_refill, |_, path| {
+        fs::remove_dir(path)
+    })
+    .unwrap_err();
+
+    match Error::from_io_error(&error) {
+        Some(Error::KeptChanging { path, passes, .. }) => {
+            assert_eq!(*path, dir_path);
+            assert_eq!(*passes, MAX_PASSES);
+        }
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert_eq!(refills, std::u32::MAX - MAX_PASSES);
+    assert!(dir_path.join("refill").exists());
+}
+
+/// Tests whether a directory that cannot be emptied is left in place after a single pass.
+#[test]
+fn unemptied_dir_is_kept() {
+    let base_dir = TempDir::new().unwrap();
+    let mut passes = 0;
+    let removed = remove_in_passes(
+        &mut passes,
+        base_dir.path(),
+        |passes, _| {
+            *passes += 1;
+            Ok(false)
+        },
+        |_, _| panic!("directory removed"),
+    )
+    .unwrap();
+
+    assert!(!removed);
+    assert_eq!(passes, 1);
+    assert!(base_dir.path().exists());
+}
+
+/// Tests whether removals racing a thread that creates and removes files throughout the tree
+/// either succeed or fail with `Error::KeptChanging`, never with spurious not-found or not-empty
+/// errors.
+#[test]
+#[cfg(target_os = "linux")]
+fn concurrent_writer_is_tolerated() {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    const FILES: usize = 250;
+
+    let base_dir = TempDir::new().unwrap();
+    for round in 0..5 {
+        let dir_path = base_dir.path().join(format!("target{}", round));
+        let subdirs: Vec<_> = (0..4)
+            .map(|index| dir_path.join(format!("sub{}", index)))
+            .collect();
+        for subdir in &subdirs {
+            fs::create_dir_all(subdir).unwrap();
+            for index in 0..FILES {
+                create_empty_file(&subdir.join(format!("file{}", index))).unwrap();
+            }
+        }
+
+        let done = Arc::new(AtomicBool::new(false));
+        let writer_done = Arc::clone(&done);
+        let writer = thread::spawn(move || {
+            let mut index = 0;
+            while !writer_done.load(Ordering::Relaxed) {
+                let subdir = &subdirs[index % subdirs.len()];
+                if index % 2 == 0 {
+                    let _ = create_empty_file(&subdir.join(format!("new{}", index)));
+                }
+                let _ = fs::remove_file(subdir.join(format!("file{}", index / 4 % FILES)));
+                index += 1;
+            }
+        });
+
+        let result = crate::remove_dir_all_with(&dir_path, &RemoveOptions::new());
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+
+        match result {
+            Ok(report) => {
+                assert!(report.root_removed());
+                assert!(!dir_path.exists());
+            }
+            Err(error) => match Error::from_io_error(&error) {
+                Some(Error::KeptChanging { passes, .. }) => assert_eq!(*passes, super::MAX_PASSES),
+                _ => panic!("unexpected error: {}", error),
+            },
+        }
+    }
+}
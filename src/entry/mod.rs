@@ -0,0 +1,155 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal of any kind of filesystem entry, for [`remove_path_all`].
+//!
+//! The target is inspected once without following links. Directories are removed as by
+//! [`remove_dir_all_with`], while every other kind of entry is unlinked directly by the portable
+//! engine, so links are removed without touching their targets.
+//!
+//! [`remove_path_all`]: ../fn.remove_path_all.html
+//! [`remove_dir_all_with`]: ../fn.remove_dir_all_with.html
+
+#[cfg(test)]
+mod tests;
+
+use crate::{engine::Engine, remove, symlinks, EntryKind, RemovalReport, RemoveOptions};
+use std::{
+    fs::{self, FileType},
+    io,
+    path::Path,
+};
+
+/// Removes whatever is at this path: a file, a symbolic link (or junction on Windows), or a
+/// directory along with all its contents. Use carefully!
+///
+/// Links are removed without following them, regardless of whether their targets are files or
+/// directories. The returned [`RemovalReport`](struct.RemovalReport.html) records the
+/// [`EntryKind`](enum.EntryKind.html) found at the path, so callers don't need to inspect it
+/// beforehand.
+///
+/// # Errors
+///
+/// Returns an error if nothing exists at the path, if the path is protected (see
+/// [`remove_dir_all`](fn.remove_dir_all.html)), or if any entry could not be removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_path_all, EntryKind};
+///
+/// fn main() -> std::io::Result<()> {
+///     let report = remove_path_all("output")?;
+///     if report.entry_kind() == Some(EntryKind::Directory) {
+///         println!("Removed {} files.", report.files_removed());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn remove_path_all<P: AsRef<Path>>(path: P) -> io::Result<RemovalReport> {
+    remove_path_all_with(path, &RemoveOptions::new())
+}
+
+/// Removes whatever is at this path, using the specified options.
+///
+/// Directories are removed as by [`remove_dir_all_with`](fn.remove_dir_all_with.html). Files and
+/// links are removed directly, honoring every option that applies to them; tombstones are only
+/// used for directories, since unlinking any other entry is already atomic.
+///
+/// # Errors
+///
+/// In addition to the errors returned by [`remove_path_all`](fn.remove_path_all.html), returns an
+/// `io::ErrorKind::InvalidInput` error if the path is not a directory and either
+/// [`RemoveOptions::gitignored_only`](struct.RemoveOptions.html#method.gitignored_only) is enabled,
+/// as only the contents of a directory can be filtered, or
+/// [`RemoveOptions::journal`](struct.RemoveOptions.html#method.journal) is set, as only directories
+/// can be resumed.
+pub fn remove_path_all_with<P: AsRef<Path>>(
+    path: P,
+    options: &RemoveOptions,
+) -> io::Result<RemovalReport> {
+    let path = path.as_ref();
+    options.safeguards.check(path)?;
+
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    let kind = entry_kind(path, file_type);
+    let mut report = if kind == EntryKind::Directory {
+        remove::remove_checked(path, options, None)?
+    } else {
+        if options.gitignored_only {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Only directories can be filtered by Git ignore rules.",
+            ));
+        }
+        if options.journal.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Only directories can be journaled.",
+            ));
+        }
+        if kind == EntryKind::DirSymlink {
+            // Links to directories may be followed, depending on the options.
+            symlinks::check_root(path, options)?;
+        }
+
+        remove::run_removal(path, options, || {
+            (remove_non_directory(path, file_type, kind, options), None)
+        })?
+    };
+
+    report.entry_kind = Some(kind);
+    Ok(report)
+}
+
+/// Removes the file or link at `path`, of the given kind and (non-followed) file type, using the
+/// portable engine.
+fn remove_non_directory(
+    path: &Path,
+    file_type: FileType,
+    kind: EntryKind,
+    options: &RemoveOptions,
+) -> io::Result<RemovalReport> {
+    let mut engine = Engine::new(options);
+    if kind == EntryKind::DirSymlink {
+        engine.begin(path)?;
+    }
+    let removed = engine.remove_entry(path, file_type)?;
+    engine.report_mut().root_removed = removed;
+    engine.finish()
+}
+
+/// Classifies the entry at `path` with the given (non-followed) file type.
+fn entry_kind(path: &Path, file_type: FileType) -> EntryKind {
+    if file_type.is_dir() {
+        EntryKind::Directory
+    } else if file_type.is_symlink() {
+        if is_dir_link(path, file_type) {
+            EntryKind::DirSymlink
+        } else {
+            EntryKind::FileSymlink
+        }
+    } else {
+        EntryKind::File
+    }
+}
+
+/// Returns whether a symbolic link refers to a directory.
+///
+/// Windows records whether a link refers to a directory in the link itself, which also covers
+/// junctions and links whose targets no longer exist.
+#[cfg(windows)]
+fn is_dir_link(path: &Path, _file_type: FileType) -> bool {
+    crate::engine::is_dir_link(path).unwrap_or(false)
+}
+
+/// Returns whether a symbolic link currently resolves to a directory.
+#[cfg(not(windows))]
+fn is_dir_link(path: &Path, file_type: FileType) -> bool {
+    crate::engine::is_dir_symlink(path, file_type)
+}
@@ -0,0 +1,143 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Path removal tests.
+
+use super::{remove_path_all, remove_path_all_with};
+use crate::{
+    engine::tests::{create_empty_file, populate_dir},
+    lock, EntryKind, Error, LockMode, RemoveOptions,
+};
+use std::{fs, io, time::Duration};
+use tempfile::TempDir;
+
+/// Tests whether regular files are removed and reported as such.
+#[test]
+fn file_works() {
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    create_empty_file(&file_path).unwrap();
+
+    let report = remove_path_all(&file_path).unwrap();
+    assert_eq!(report.entry_kind(), Some(EntryKind::File));
+    assert_eq!(report.files_removed(), 1);
+    assert!(report.root_removed());
+    assert!(fs::symlink_metadata(&file_path).is_err());
+}
+
+/// Tests whether directories are removed along with their contents.
+#[test]
+fn directory_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let report = remove_path_all(&dir_path).unwrap();
+    assert_eq!(report.entry_kind(), Some(EntryKind::Directory));
+    assert!(report.root_removed());
+    assert!(fs::symlink_metadata(&dir_path).is_err());
+}
+
+/// Tests whether file and directory symlinks, including dangling ones, are removed without
+/// touching their targets.
+#[test]
+#[cfg(unix)]
+fn symlinks_are_not_followed() {
+    use std::os::unix::fs::symlink;
+
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    let dir_path = base_dir.path().join("dir");
+    create_empty_file(&file_path).unwrap();
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let cases = [
+        ("file-link", file_path.clone(), EntryKind::FileSymlink),
+        ("dir-link", dir_path.clone(), EntryKind::DirSymlink),
+        (
+            "dangling-link",
+            base_dir.path().join("missing"),
+            EntryKind::FileSymlink,
+        ),
+    ];
+    for (name, target, kind) in cases.iter() {
+        let link_path = base_dir.path().join(name);
+        symlink(target, &link_path).unwrap();
+
+        let report = remove_path_all(&link_path).unwrap();
+        assert_eq!(report.entry_kind(), Some(*kind));
+        assert_eq!(report.symlinks_removed(), 1);
+        assert!(fs::symlink_metadata(&link_path).is_err());
+    }
+
+    assert!(file_path.is_file());
+    assert!(dir_path.join("baz").join("qux").is_file());
+}
+
+/// Tests whether missing paths are reported as not found.
+#[test]
+fn missing_path_fails() {
+    let base_dir = TempDir::new().unwrap();
+    assert_eq!(
+        remove_path_all(base_dir.path().join("missing"))
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::NotFound
+    );
+}
+
+/// Tests whether Git ignore filtering and journals are refused for files, leaving them in place.
+#[test]
+fn gitignored_only_file_fails() {
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    create_empty_file(&file_path).unwrap();
+
+    let error =
+        remove_path_all_with(&file_path, RemoveOptions::new().gitignored_only(true)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(file_path.is_file());
+
+    let error = remove_path_all_with(
+        &file_path,
+        RemoveOptions::new().journal(base_dir.path().join("journals")),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(file_path.is_file());
+}
+
+/// Tests whether locks and waiting for removals to complete apply to files as well.
+#[test]
+fn file_options_are_honoured() {
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    create_empty_file(&file_path).unwrap();
+
+    let lock = lock::acquire(&file_path, LockMode::FailFast, None).unwrap();
+    let error = remove_path_all_with(&file_path, RemoveOptions::new().lock(LockMode::FailFast))
+        .unwrap_err();
+    match Error::from_io_error(&error) {
+        Some(Error::Locked { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(file_path.is_file());
+    drop(lock);
+
+    let report = remove_path_all_with(
+        &file_path,
+        RemoveOptions::new()
+            .lock(LockMode::FailFast)
+            .wait_until_gone(Duration::from_secs(1)),
+    )
+    .unwrap();
+    assert!(report.root_removed());
+    assert!(fs::symlink_metadata(&file_path).is_err());
+}
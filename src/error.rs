@@ -0,0 +1,401 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Crate-specific error details.
+
+use crate::Holder;
+use std::{error, fmt, io, path::PathBuf, time::Duration};
+
+/// Details of a failure specific to this crate.
+///
+/// All functions in this crate return [`io::Error`] so that they remain drop-in replacements for
+/// their `std::fs` counterparts. Failures that have no natural `io::Error` equivalent carry an
+/// `Error` as their inner error, which can be retrieved using
+/// [`Error::from_io_error`](#method.from_io_error).
+///
+/// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_dir_all, Error};
+///
+/// if let Err(error) = remove_dir_all("/") {
+///     if let Some(Error::ProtectedPath { protected, .. }) = Error::from_io_error(&error) {
+///         eprintln!("Refused to remove {}.", protected.display());
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    /// The target is, or contains, a protected or in-use path.
+    ProtectedPath {
+        /// Path whose removal was refused.
+        path: PathBuf,
+
+        /// Protected path equal to or contained within `path`.
+        protected: PathBuf,
+
+        /// Why `protected` is protected.
+        reason: ProtectionReason,
+    },
+
+    /// A path passed to a confined removal function resolves to a location outside of its root
+    /// directory, whether through `..` components, an absolute path, or a symbolic link. This is
+    /// also used for links that would be followed outside of the removal target under
+    /// [`SymlinkPolicy::DeleteTargetContents`](enum.SymlinkPolicy.html#variant.DeleteTargetContents).
+    EscapesRoot {
+        /// Root directory the removal was confined to.
+        root: PathBuf,
+
+        /// Path relative to `root` that would have escaped it.
+        path: PathBuf,
+    },
+
+    /// A symbolic link or junction was found while links are refused using
+    /// [`SymlinkPolicy::Refuse`](enum.SymlinkPolicy.html#variant.Refuse).
+    SymlinkRefused {
+        /// Path of the link.
+        path: PathBuf,
+    },
+
+    /// A file to be shredded has other hard links, so its contents are still in use elsewhere.
+    HardLinked {
+        /// Path of the file.
+        path: PathBuf,
+
+        /// Number of hard links to the file, including `path`.
+        links: u64,
+    },
+
+    /// Removal failed because entries within the target are in use, and the processes holding
+    /// them were identified using
+    /// [`RemoveOptions::diagnose_holders`](struct.RemoveOptions.html#method.diagnose_holders).
+    InUse {
+        /// Path whose removal failed.
+        path: PathBuf,
+
+        /// Processes holding entries within `path`.
+        holders: Vec<Holder>,
+
+        /// Error the removal failed with.
+        source: io::Error,
+    },
+
+    /// An entry could not be removed due to a lack of permission, and the cause was determined.
+    AccessDenied {
+        /// Path of the entry that could not be removed.
+        path: PathBuf,
+
+        /// Why `path` could not be removed.
+        reason: DenialReason,
+
+        /// Error the removal failed with.
+        source: io::Error,
+    },
+
+    /// A directory was still not empty after its contents were removed the given number of times,
+    /// as other processes kept creating entries within it.
+    KeptChanging {
+        /// Path of the directory.
+        path: PathBuf,
+
+        /// Number of times the contents of the directory were removed.
+        passes: u32,
+
+        /// Error the final attempt to remove the directory failed with.
+        source: io::Error,
+    },
+
+    /// A removed path was still present once the timeout passed to
+    /// [`wait_until_gone`](fn.wait_until_gone.html) elapsed, such as a file or directory on
+    /// Windows left pending deletion by another process holding it open.
+    StillExists {
+        /// Path that was still present.
+        path: PathBuf,
+
+        /// How long the path was waited on.
+        timeout: Duration,
+    },
+
+    /// The target was locked by another removal using
+    /// [`RemoveOptions::lock`](struct.RemoveOptions.html#method.lock), and the
+    /// [`LockMode`](enum.LockMode.html) did not allow waiting for it to be released, or the lock
+    /// was still held once the timeout elapsed.
+    Locked {
+        /// Path of the target.
+        path: PathBuf,
+
+        /// How long the lock was waited on, or `None` if the lock was not waited on.
+        timeout: Option<Duration>,
+    },
+
+    /// The target of a journaled removal was replaced by a different directory since the journal
+    /// was written, so [`resume_pending`](fn.resume_pending.html) did not resume it.
+    TargetReplaced {
+        /// Path of the target.
+        path: PathBuf,
+
+        /// Path of the journal, which is left in place.
+        journal: PathBuf,
+    },
+
+    /// A background removal was cancelled using
+    /// [`RemovalHandle::cancel`](struct.RemovalHandle.html#method.cancel) before it finished.
+    Cancelled,
+}
+
+impl Error {
+    /// Returns the crate-specific details of an `io::Error` returned by this crate, if any.
+    pub fn from_io_error(error: &io::Error) -> Option<&Self> {
+        error.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+
+    /// Returns the `io::ErrorKind` used when converting this error into an `io::Error`.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::ProtectedPath { .. } => io::ErrorKind::PermissionDenied,
+            Self::EscapesRoot { .. } => io::ErrorKind::PermissionDenied,
+            Self::SymlinkRefused { .. } => io::ErrorKind::PermissionDenied,
+            Self::HardLinked { .. } => io::ErrorKind::PermissionDenied,
+            Self::InUse { source, .. } => source.kind(),
+            Self::AccessDenied { source, .. } => source.kind(),
+            Self::KeptChanging { source, .. } => source.kind(),
+            Self::StillExists { .. } => io::ErrorKind::TimedOut,
+            Self::Locked { timeout: None, .. } => io::ErrorKind::WouldBlock,
+            Self::Locked { .. } => io::ErrorKind::TimedOut,
+            Self::TargetReplaced { .. } => io::ErrorKind::InvalidData,
+            Self::Cancelled => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ProtectedPath {
+                path,
+                protected,
+                reason,
+            } => {
+                if path == protected {
+                    write!(f, "Refusing to remove {} `{}`.", reason, path.display())
+                } else {
+                    write!(
+                        f,
+                        "Refusing to remove `{}`, as it contains {} `{}`.",
+                        path.display(),
+                        reason,
+                        protected.display()
+                    )
+                }
+            }
+            Self::EscapesRoot { root, path } => write!(
+                f,
+                "Refusing to remove `{}`, as it resolves outside of `{}`.",
+                path.display(),
+                root.display()
+            ),
+            Self::SymlinkRefused { path } => write!(
+                f,
+                "Refusing to remove `{}`, as it is a symbolic link.",
+                path.display()
+            ),
+            Self::HardLinked { path, links } => write!(
+                f,
+                "Refusing to shred `{}`, as it has {} hard links.",
+                path.display(),
+                links
+            ),
+            Self::InUse {
+                path,
+                holders,
+                source,
+            } => {
+                write!(
+                    f,
+                    "Failed to remove `{}` ({}), as it is in use by",
+                    path.display(),
+                    source
+                )?;
+                let mut pids = Vec::new();
+                for holder in holders {
+                    if pids.contains(&holder.pid) {
+                        continue;
+                    }
+                    f.write_str(if pids.is_empty() { " " } else { ", " })?;
+                    match &holder.name {
+                        Some(name) => write!(f, "`{}` (PID {})", name, holder.pid)?,
+                        None => write!(f, "PID {}", holder.pid)?,
+                    }
+                    pids.push(holder.pid);
+                }
+                f.write_str(".")
+            }
+            Self::AccessDenied {
+                path,
+                reason,
+                source,
+            } => write!(
+                f,
+                "Failed to remove `{}` ({}), as {}.",
+                path.display(),
+                source,
+                reason
+            ),
+            Self::KeptChanging {
+                path,
+                passes,
+                source,
+            } => write!(
+                f,
+                "Failed to remove `{}` ({}), as the tree kept changing after {} passes.",
+                path.display(),
+                source,
+                passes
+            ),
+            Self::StillExists { path, timeout } => write!(
+                f,
+                "`{}` still exists after waiting {:?} for it to be removed.",
+                path.display(),
+                timeout
+            ),
+            Self::Locked {
+                path,
+                timeout: None,
+            } => write!(f, "`{}` is locked by another removal.", path.display()),
+            Self::Locked {
+                path,
+                timeout: Some(timeout),
+            } => write!(
+                f,
+                "`{}` is still locked by another removal after waiting {:?}.",
+                path.display(),
+                timeout
+            ),
+            Self::TargetReplaced { path, journal } => write!(
+                f,
+                "`{}` was replaced by a different directory since its removal was journaled in `{}`.",
+                path.display(),
+                journal.display()
+            ),
+            Self::Cancelled => f.write_str("Removal was cancelled before it finished."),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::InUse { source, .. } => Some(source),
+            Self::AccessDenied { source, .. } => Some(source),
+            Self::KeptChanging { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        io::Error::new(error.kind(), error)
+    }
+}
+
+/// Reason a path is protected from removal.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProtectionReason {
+    /// The path is a filesystem root, such as `/` or `C:\`.
+    Root,
+
+    /// The path is a user profile or home directory.
+    HomeDirectory,
+
+    /// The path is a well-known operating system directory.
+    SystemDirectory,
+
+    /// The path was protected using
+    /// [`RemoveOptions::protect_path`](struct.RemoveOptions.html#method.protect_path).
+    Denylisted,
+
+    /// The path is the current working directory of the process.
+    CurrentDirectory,
+
+    /// The path is the executable of the running process.
+    CurrentExecutable,
+
+    /// The path was registered as in use with
+    /// [`RemoveOptions::guard_path`](struct.RemoveOptions.html#method.guard_path).
+    InUse,
+}
+
+impl fmt::Display for ProtectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Root => "filesystem root",
+            Self::HomeDirectory => "home directory",
+            Self::SystemDirectory => "system directory",
+            Self::Denylisted => "protected path",
+            Self::CurrentDirectory => "current working directory",
+            Self::CurrentExecutable => "running executable",
+            Self::InUse => "in-use path",
+        })
+    }
+}
+
+/// Cause of a failure to remove an entry due to a lack of permission.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DenialReason {
+    /// The parent directory of the entry is not writable by the current user.
+    ParentNotWritable,
+
+    /// The parent directory of the entry is not searchable (lacks execute permission) by the
+    /// current user.
+    ParentNotSearchable,
+
+    /// The parent directory of the entry has the sticky bit set, and neither it nor the entry is
+    /// owned by the current user.
+    StickyDirectory,
+
+    /// The entry or its parent directory has the immutable attribute set.
+    Immutable {
+        /// Path of the entry with the attribute set.
+        path: PathBuf,
+    },
+
+    /// The entry or its parent directory has the append-only attribute set.
+    AppendOnly {
+        /// Path of the entry with the attribute set.
+        path: PathBuf,
+    },
+
+    /// The entry resides on a read-only filesystem.
+    ReadOnlyFilesystem,
+
+    /// The entry has the Windows read-only attribute set.
+    ReadOnlyAttribute,
+}
+
+impl fmt::Display for DenialReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ParentNotWritable => f.write_str("its parent directory is not writable"),
+            Self::ParentNotSearchable => f.write_str("its parent directory is not searchable"),
+            Self::StickyDirectory => f.write_str(
+                "its parent directory is sticky and neither is owned by the current user",
+            ),
+            Self::Immutable { path } => {
+                write!(f, "`{}` has the immutable attribute set", path.display())
+            }
+            Self::AppendOnly { path } => {
+                write!(f, "`{}` has the append-only attribute set", path.display())
+            }
+            Self::ReadOnlyFilesystem => f.write_str("it resides on a read-only filesystem"),
+            Self::ReadOnlyAttribute => f.write_str("it has the read-only attribute set"),
+        }
+    }
+}
@@ -0,0 +1,622 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal of Git-ignored entries, mirroring the semantics of `git clean -fdX`.
+//!
+//! Ignore rules follow the format described at https://git-scm.com/docs/gitignore, and tracked
+//! files are read directly from the Git index (versions 2 through 4, as described at
+//! https://git-scm.com/docs/index-format), so no `git` binary is required.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    engine::{is_vanished, Engine},
+    SkipReason,
+};
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    env,
+    fs::{self, FileType},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Name of the per-directory ignore file.
+const IGNORE_FILE_NAME: &str = ".gitignore";
+
+/// Name of the Git repository directory (or `gitdir:` file) within a work tree.
+const GIT_DIR_NAME: &str = ".git";
+
+/// Prefix of the repository path within a `.git` file.
+const GITDIR_PREFIX: &str = "gitdir:";
+
+/// Single rule parsed from an ignore file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Pattern {
+    /// Glob to match, stripped of any leading `!`, leading `/`, and trailing `/`.
+    glob: Vec<u8>,
+
+    /// Directory containing the ignore file, relative to the work tree root (empty for the root).
+    base: String,
+
+    /// Whether the rule re-includes entries matched by an earlier rule (`!` prefix).
+    negated: bool,
+
+    /// Whether the rule only matches directories (`/` suffix).
+    dir_only: bool,
+
+    /// Whether the rule is matched against the full path relative to `base` rather than only the
+    /// entry name, which is the case if it contains any `/` other than a trailing one.
+    anchored: bool,
+}
+
+impl Pattern {
+    /// Parses a single line from an ignore file, returning `None` for blank lines and comments.
+    fn parse(line: &[u8], base: &str) -> Option<Self> {
+        let mut line = line;
+        if line.last() == Some(&b'\r') {
+            line = &line[..line.len() - 1];
+        }
+
+        // Trailing spaces are ignored unless escaped with a backslash.
+        while line.last() == Some(&b' ') && !line[..line.len() - 1].ends_with(b"\\") {
+            line = &line[..line.len() - 1];
+        }
+
+        if line.is_empty() || line[0] == b'#' {
+            return None;
+        }
+
+        let negated = line[0] == b'!';
+        if negated {
+            line = &line[1..];
+        }
+
+        let dir_only = line.last() == Some(&b'/');
+        if dir_only {
+            line = &line[..line.len() - 1];
+        }
+
+        let anchored = line.contains(&b'/');
+        if line.first() == Some(&b'/') {
+            line = &line[1..];
+        }
+
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            glob: line.to_vec(),
+            base: base.to_owned(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Returns whether this rule matches the entry at `path` (relative to the work tree root).
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let relative = if self.base.is_empty() {
+            path
+        } else if path.starts_with(self.base.as_str()) && path[self.base.len()..].starts_with('/') {
+            &path[self.base.len() + 1..]
+        } else {
+            return false;
+        };
+
+        if self.anchored {
+            wildmatch(&self.glob, relative.as_bytes())
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            wildmatch(&self.glob, name.as_bytes())
+        }
+    }
+}
+
+/// Stack of ignore rules applicable to the directory currently being visited.
+///
+/// Rules are stored in order of increasing precedence: `info/exclude` first, followed by the
+/// `.gitignore` files from the work tree root downward. The last matching rule wins.
+#[derive(Debug, Default)]
+struct Matcher {
+    patterns: Vec<Pattern>,
+}
+
+impl Matcher {
+    /// Appends the rules from an ignore file, returning the previous rule count for use with
+    /// [`truncate`](#method.truncate).
+    fn push_file(&mut self, path: &Path, base: &str) -> io::Result<usize> {
+        let len = self.patterns.len();
+        match fs::read(path) {
+            Ok(contents) => self.patterns.extend(
+                contents
+                    .split(|&byte| byte == b'\n')
+                    .filter_map(|line| Pattern::parse(line, base)),
+            ),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        Ok(len)
+    }
+
+    /// Drops all rules appended after the stack contained `len` rules.
+    fn truncate(&mut self, len: usize) {
+        self.patterns.truncate(len);
+    }
+
+    /// Returns whether the entry at `path` (relative to the work tree root) is ignored.
+    fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map_or(false, |pattern| !pattern.negated)
+    }
+}
+
+/// Matches `text` against a Git wildcard pattern, with `/` treated as a path separator.
+///
+/// `*`, `?`, and bracket expressions never match `/`, while `**` matches across directories when
+/// it forms an entire path component (`**/`, `/**/`, or `/**`).
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    wildmatch_from(pattern, 0, text)
+}
+
+/// Matches `text` against the part of `pattern` beginning at index `p`.
+fn wildmatch_from(pattern: &[u8], mut p: usize, text: &[u8]) -> bool {
+    let mut t = 0;
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                let star_start = p;
+                while p < pattern.len() && pattern[p] == b'*' {
+                    p += 1;
+                }
+
+                let double_star = p - star_start >= 2
+                    && (star_start == 0 || pattern[star_start - 1] == b'/')
+                    && (p == pattern.len() || pattern[p] == b'/');
+                if double_star {
+                    if p == pattern.len() {
+                        return true;
+                    }
+
+                    // Skip the `/` following `**` so that `**/` can match zero directories.
+                    let rest = p + 1;
+                    return wildmatch_from(pattern, rest, &text[t..])
+                        || (t..text.len()).any(|i| {
+                            text[i] == b'/' && wildmatch_from(pattern, rest, &text[i + 1..])
+                        });
+                }
+
+                if p == pattern.len() {
+                    return !text[t..].contains(&b'/');
+                }
+
+                for i in t..=text.len() {
+                    if wildmatch_from(pattern, p, &text[i..]) {
+                        return true;
+                    }
+
+                    if i < text.len() && text[i] == b'/' {
+                        break;
+                    }
+                }
+
+                return false;
+            }
+            b'?' => {
+                if t >= text.len() || text[t] == b'/' {
+                    return false;
+                }
+
+                p += 1;
+                t += 1;
+            }
+            b'[' => {
+                let c = match text.get(t) {
+                    Some(&c) if c != b'/' => c,
+                    _ => return false,
+                };
+
+                match match_bracket(pattern, p, c) {
+                    Some((true, next)) => p = next,
+                    Some((false, _)) => return false,
+                    None if c == b'[' => p += 1,
+                    None => return false,
+                }
+
+                t += 1;
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                if text.get(t) != Some(&pattern[p + 1]) {
+                    return false;
+                }
+
+                p += 2;
+                t += 1;
+            }
+            c => {
+                if text.get(t) != Some(&c) {
+                    return false;
+                }
+
+                p += 1;
+                t += 1;
+            }
+        }
+    }
+
+    t == text.len()
+}
+
+/// Matches `c` against the bracket expression starting at `pattern[start]`.
+///
+/// Returns whether the character matched and the index following the closing `]`, or `None` if the
+/// expression is unterminated (in which case the `[` is treated as a literal).
+fn match_bracket(pattern: &[u8], start: usize, c: u8) -> Option<(bool, usize)> {
+    let mut p = start + 1;
+    let negated = match pattern.get(p) {
+        Some(b'!') | Some(b'^') => {
+            p += 1;
+            true
+        }
+        _ => false,
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut low = *pattern.get(p)?;
+        if low == b']' && !first {
+            break;
+        }
+
+        first = false;
+        if low == b'\\' {
+            p += 1;
+            low = *pattern.get(p)?;
+        }
+        p += 1;
+
+        let mut high = low;
+        if pattern.get(p) == Some(&b'-') && pattern.get(p + 1).map_or(false, |&b| b != b']') {
+            high = pattern[p + 1];
+            p += 2;
+            if high == b'\\' {
+                high = *pattern.get(p)?;
+                p += 1;
+            }
+        }
+
+        if low <= c && c <= high {
+            matched = true;
+        }
+    }
+
+    Some((matched != negated, p + 1))
+}
+
+/// Paths of the files tracked in a Git index.
+#[derive(Debug, Default)]
+struct Index {
+    files: HashSet<String>,
+}
+
+impl Index {
+    /// Loads the index file at `path`, returning an empty index if it does not exist.
+    fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Self::parse(&data).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Malformed Git index file.")
+            }),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Parses the contents of an index file, returning `None` if it is malformed or uses an
+    /// unsupported version.
+    fn parse(data: &[u8]) -> Option<Self> {
+        /// Size of the fixed-length portion of each entry, up to and including the flags field.
+        const ENTRY_HEADER_LEN: usize = 62;
+
+        const FLAG_EXTENDED: u16 = 0x4000;
+        const NAME_MASK: u16 = 0x0fff;
+
+        fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+            Some(u32::from_be_bytes(
+                data.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        }
+
+        if data.get(..4)? != b"DIRC" {
+            return None;
+        }
+
+        let version = read_u32(data, 4)?;
+        if !(2..=4).contains(&version) {
+            return None;
+        }
+
+        let count = read_u32(data, 8)?;
+        let mut index = Self::default();
+        let mut offset = 12;
+        let mut name = Vec::new();
+        for _ in 0..count {
+            let entry_start = offset;
+            let flags = u16::from_be_bytes(data.get(offset + 60..offset + 62)?.try_into().ok()?);
+            offset += ENTRY_HEADER_LEN;
+            if version >= 3 && flags & FLAG_EXTENDED != 0 {
+                offset += 2;
+            }
+
+            if version == 4 {
+                // Version 4 prefix-compresses each name against the previous entry's name.
+                let mut byte = *data.get(offset)?;
+                offset += 1;
+                let mut strip = usize::from(byte & 0x7f);
+                while byte & 0x80 != 0 {
+                    byte = *data.get(offset)?;
+                    offset += 1;
+                    strip = ((strip + 1) << 7) | usize::from(byte & 0x7f);
+                }
+
+                let suffix_len = data.get(offset..)?.iter().position(|&b| b == 0)?;
+                name.truncate(name.len().checked_sub(strip)?);
+                name.extend_from_slice(&data[offset..offset + suffix_len]);
+                offset += suffix_len + 1;
+            } else {
+                let name_len = match flags & NAME_MASK {
+                    NAME_MASK => data.get(offset..)?.iter().position(|&b| b == 0)?,
+                    len => usize::from(len),
+                };
+
+                name.clear();
+                name.extend_from_slice(data.get(offset..offset + name_len)?);
+
+                // Entries are nul-padded to a multiple of eight bytes, with at least one nul.
+                offset = entry_start + ((offset + name_len - entry_start + 8) & !7);
+            }
+
+            index
+                .files
+                .insert(String::from_utf8_lossy(&name).into_owned());
+        }
+
+        Some(index)
+    }
+}
+
+/// Locates the Git work tree enclosing `path`, returning the work tree root and repository
+/// directory, or `None` if `path` is not inside a work tree.
+fn find_repository(path: &Path) -> io::Result<Option<(PathBuf, PathBuf)>> {
+    for dir in path.ancestors() {
+        let dot_git = dir.join(GIT_DIR_NAME);
+        let metadata = match fs::metadata(&dot_git) {
+            Ok(metadata) => metadata,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+
+        if metadata.is_dir() {
+            return Ok(Some((dir.to_path_buf(), dot_git)));
+        }
+
+        // Linked work trees and submodules use a `.git` file pointing at the repository instead.
+        let contents = fs::read_to_string(&dot_git)?;
+        let contents = contents.trim_end();
+        if contents.starts_with(GITDIR_PREFIX) {
+            let git_dir = contents[GITDIR_PREFIX.len()..].trim();
+            return Ok(Some((dir.to_path_buf(), dir.join(git_dir))));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the directory holding the files shared by every work tree of the repository whose
+/// directory is `git_dir`, such as `info/exclude`.
+///
+/// The directory of a linked work tree (within `.git/worktrees`) names the main repository
+/// directory in its `commondir` file, relative to itself if not absolute.
+fn common_dir(git_dir: &Path) -> io::Result<PathBuf> {
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(contents) => Ok(git_dir.join(contents.trim_end())),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(git_dir.to_path_buf()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns whether `path` contains its own `.git` entry, marking it as a separate repository.
+fn is_nested_repository(path: &Path) -> bool {
+    fs::symlink_metadata(path.join(GIT_DIR_NAME)).is_ok()
+}
+
+/// Joins a relative path string and an entry name using `/` as the separator.
+fn join_relative(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+/// Walker state for a single `git clean -X`-style removal.
+struct Cleaner<'a, 'b> {
+    engine: &'a mut Engine<'b>,
+    matcher: Matcher,
+    index: Index,
+}
+
+impl Cleaner<'_, '_> {
+    /// Removes the ignored entries within the directory at `path`, whose path relative to the work
+    /// tree root is `relative`. If `ignored` is set, the directory itself is ignored and all of its
+    /// untracked contents are removed.
+    ///
+    /// Returns whether every entry in the directory was removed.
+    fn clean_dir(&mut self, path: &Path, relative: &str, ignored: bool) -> io::Result<bool> {
+        let len = self
+            .matcher
+            .push_file(&path.join(IGNORE_FILE_NAME), relative)?;
+        // Entries removed by other processes are treated as removed, as the engine does.
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(ref error) if is_vanished(error) => {
+                self.matcher.truncate(len);
+                return Ok(true);
+            }
+            Err(error) => return Err(error),
+        };
+
+        let mut emptied = true;
+        for entry in entries {
+            let (name, file_type) = match entry.and_then(|entry| {
+                let file_type = entry.file_type()?;
+                Ok((entry.file_name(), file_type))
+            }) {
+                Ok(entry) => entry,
+                Err(ref error) if is_vanished(error) => continue,
+                Err(error) => return Err(error),
+            };
+            if name == GIT_DIR_NAME {
+                emptied = false;
+                continue;
+            }
+
+            let entry_relative = join_relative(relative, &name.to_string_lossy());
+            emptied &= self.clean_entry(&path.join(&name), &entry_relative, file_type, ignored)?;
+        }
+
+        self.matcher.truncate(len);
+        Ok(emptied)
+    }
+
+    /// Removes the entry at `path` if it is ignored and untracked, descending into directories as
+    /// needed.
+    ///
+    /// Ignored directories are descended into rather than removed outright so that any tracked
+    /// files or nested repositories within them are preserved, as `git clean` does.
+    ///
+    /// Returns whether the entry was removed.
+    fn clean_entry(
+        &mut self,
+        path: &Path,
+        relative: &str,
+        file_type: FileType,
+        parent_ignored: bool,
+    ) -> io::Result<bool> {
+        let is_dir = file_type.is_dir();
+        let ignored = parent_ignored || self.matcher.is_ignored(relative, is_dir);
+        if self.index.files.contains(relative) {
+            if ignored {
+                self.engine
+                    .report_mut()
+                    .skip(path.to_path_buf(), SkipReason::Tracked);
+            }
+
+            return Ok(false);
+        }
+
+        if !is_dir {
+            return if ignored {
+                self.engine.remove_entry(path, file_type)
+            } else {
+                Ok(false)
+            };
+        }
+
+        match self.engine.skip_boundary(path, file_type) {
+            Ok(true) => return Ok(false),
+            Ok(false) => {}
+            Err(ref error) if is_vanished(error) => return Ok(true),
+            Err(error) => return Err(error),
+        }
+
+        if is_nested_repository(path) {
+            if ignored {
+                self.engine
+                    .report_mut()
+                    .skip(path.to_path_buf(), SkipReason::NestedRepository);
+            }
+
+            return Ok(false);
+        }
+
+        if self.clean_dir(path, relative, ignored)? && ignored {
+            self.engine.remove_empty_dir(path)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+/// Removes all untracked entries within the directory at `path` that are ignored by Git.
+///
+/// The directory itself is always kept, even if it is ignored.
+pub(crate) fn clean(path: &Path, engine: &mut Engine) -> io::Result<()> {
+    if !fs::symlink_metadata(path)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Target is not a directory.",
+        ));
+    }
+
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    let path: PathBuf = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+
+    engine.begin(&path)?;
+
+    let mut matcher = Matcher::default();
+    let mut index = Index::default();
+    let mut root = path.clone();
+    if let Some((work_tree, git_dir)) = find_repository(&path)? {
+        matcher.push_file(&common_dir(&git_dir)?.join("info").join("exclude"), "")?;
+        index = Index::load(&git_dir.join("index"))?;
+        root = work_tree;
+    }
+
+    // Load the ignore files from each directory between the work tree root and the target,
+    // checking along the way whether any of those directories are ignored themselves.
+    let mut relative = String::new();
+    let mut ignored = false;
+    let mut dir = root.clone();
+    if let Ok(components) = path.strip_prefix(&root) {
+        for component in components.components() {
+            matcher.push_file(&dir.join(IGNORE_FILE_NAME), &relative)?;
+            let name = component.as_os_str();
+            dir.push(name);
+            relative = join_relative(&relative, &name.to_string_lossy());
+            ignored = ignored || matcher.is_ignored(&relative, true);
+        }
+    }
+
+    Cleaner {
+        engine,
+        matcher,
+        index,
+    }
+    .clean_dir(&path, &relative, ignored)
+    .map(|_| ())
+}
@@ -0,0 +1,279 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Git ignore matching and cleanup tests.
+
+use super::{wildmatch, Cleaner, Index, Matcher, Pattern};
+use crate::{
+    engine::{tests::create_empty_file, Engine},
+    remove_dir_all_with, RemoveOptions, SkipReason,
+};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+
+/// Writes a version 2 Git index listing the specified paths.
+fn write_index(path: &Path, entries: &[&str]) {
+    let mut data = b"DIRC".to_vec();
+    data.extend_from_slice(&2u32.to_be_bytes());
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for name in entries {
+        let entry_start = data.len();
+        data.extend_from_slice(&[0; 60]);
+        data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        data.extend_from_slice(name.as_bytes());
+        let padded_len = (data.len() - entry_start + 8) & !7;
+        data.resize(entry_start + padded_len, 0);
+    }
+
+    fs::write(path, data).unwrap();
+}
+
+/// Creates a file at `path` relative to `root`, along with any missing parent directories.
+fn create_file(root: &Path, path: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    create_empty_file(&path).unwrap();
+}
+
+/// Creates a fixture repository with a `.git` directory and the specified ignore rules at its
+/// root.
+fn create_repository(gitignore: &str) -> TempDir {
+    let repo = TempDir::new().unwrap();
+    fs::create_dir_all(repo.path().join(".git").join("info")).unwrap();
+    fs::write(repo.path().join(".gitignore"), gitignore).unwrap();
+    repo
+}
+
+/// Tests basic wildcard matching, including `/` handling.
+#[test]
+fn wildmatch_works() {
+    assert!(wildmatch(b"*.o", b"main.o"));
+    assert!(!wildmatch(b"*.o", b"src/main.o"));
+    assert!(wildmatch(b"src/*.o", b"src/main.o"));
+    assert!(wildmatch(b"ma?n.[a-z]", b"main.o"));
+    assert!(!wildmatch(b"ma?n.[!a-z]", b"main.o"));
+    assert!(wildmatch(b"\\*literal", b"*literal"));
+    assert!(!wildmatch(b"\\*literal", b"xliteral"));
+    assert!(wildmatch(b"[unterminated", b"[unterminated"));
+}
+
+/// Tests `**` matching across directory boundaries.
+#[test]
+fn wildmatch_double_star_works() {
+    assert!(wildmatch(b"**/foo", b"foo"));
+    assert!(wildmatch(b"**/foo", b"a/b/foo"));
+    assert!(wildmatch(b"a/**/b", b"a/b"));
+    assert!(wildmatch(b"a/**/b", b"a/x/y/b"));
+    assert!(wildmatch(b"a/**", b"a/x/y"));
+    assert!(!wildmatch(b"a/**", b"b/x"));
+    assert!(!wildmatch(b"a**b", b"a/b"));
+}
+
+/// Tests ignore rule parsing, negation, and precedence.
+#[test]
+fn matcher_works() {
+    let mut matcher = Matcher::default();
+    for line in &[
+        "# comment",
+        "",
+        "*.log",
+        "!keep.log",
+        "build/",
+        "/root-only",
+        "docs/*.html  ",
+    ] {
+        matcher.patterns.extend(Pattern::parse(line.as_bytes(), ""));
+    }
+    matcher
+        .patterns
+        .extend(Pattern::parse(b"nested.txt", "sub"));
+
+    assert_eq!(matcher.patterns.len(), 6);
+    assert!(matcher.is_ignored("debug.log", false));
+    assert!(matcher.is_ignored("deep/debug.log", false));
+    assert!(!matcher.is_ignored("keep.log", false));
+    assert!(matcher.is_ignored("build", true));
+    assert!(!matcher.is_ignored("build", false));
+    assert!(matcher.is_ignored("root-only", false));
+    assert!(!matcher.is_ignored("sub/root-only", false));
+    assert!(matcher.is_ignored("docs/index.html", false));
+    assert!(!matcher.is_ignored("docs/api/index.html", false));
+    assert!(matcher.is_ignored("sub/a/nested.txt", false));
+    assert!(!matcher.is_ignored("nested.txt", false));
+}
+
+/// Tests parsing of an index file listing tracked files.
+#[test]
+fn index_parsing_works() {
+    let dir = TempDir::new().unwrap();
+    let index_path = dir.path().join("index");
+    write_index(
+        &index_path,
+        &["a.txt", "src/lib/mod.rs", "verylongname_exactly"],
+    );
+
+    let index = Index::load(&index_path).unwrap();
+    assert!(index.files.contains("a.txt"));
+    assert!(index.files.contains("src/lib/mod.rs"));
+    assert!(index.files.contains("verylongname_exactly"));
+
+    fs::write(&index_path, b"DIRC\0\0\0\x02\0\0\0\x01").unwrap();
+    assert!(Index::load(&index_path).is_err());
+}
+
+/// Tests whether only ignored, untracked entries are removed from a fixture repository.
+#[test]
+fn only_ignored_entries_are_removed() {
+    let repo = create_repository("target/\n*.log\n!important.log\ncache/\n");
+    let root = repo.path();
+    fs::write(root.join(".git").join("info").join("exclude"), "*.tmp\n").unwrap();
+    create_file(root, "src/main.rs");
+    create_file(root, "target/debug/app");
+    create_file(root, "target/release/app");
+    create_file(root, "debug.log");
+    create_file(root, "important.log");
+    create_file(root, "scratch.tmp");
+    create_file(root, "notes.txt");
+    create_file(root, "sub/generated.out");
+    fs::write(root.join("sub").join(".gitignore"), "*.out\n!kept.out\n").unwrap();
+    create_file(root, "sub/kept.out");
+    create_file(root, "tracked.log");
+    create_file(root, "cache/tracked.dat");
+    create_file(root, "cache/scratch.dat");
+    write_index(
+        &root.join(".git").join("index"),
+        &["tracked.log", "cache/tracked.dat"],
+    );
+
+    let report = remove_dir_all_with(root, RemoveOptions::new().gitignored_only(true)).unwrap();
+    assert!(!report.root_removed());
+    let mut skipped: Vec<_> = report
+        .skipped()
+        .iter()
+        .map(|entry| (entry.path().to_path_buf(), entry.reason()))
+        .collect();
+    skipped.sort();
+    assert_eq!(
+        skipped,
+        vec![
+            (root.join("cache").join("tracked.dat"), SkipReason::Tracked),
+            (root.join("tracked.log"), SkipReason::Tracked),
+        ]
+    );
+
+    for removed in &[
+        "target",
+        "debug.log",
+        "scratch.tmp",
+        "sub/generated.out",
+        "cache/scratch.dat",
+    ] {
+        assert!(
+            fs::symlink_metadata(root.join(removed)).is_err(),
+            "{}",
+            removed
+        );
+    }
+
+    for kept in &[
+        ".git/info/exclude",
+        ".gitignore",
+        "src/main.rs",
+        "important.log",
+        "notes.txt",
+        "sub/kept.out",
+        "tracked.log",
+        "cache/tracked.dat",
+    ] {
+        assert!(root.join(kept).is_file(), "{}", kept);
+    }
+}
+
+/// Tests whether ignore rules from ancestor directories apply when cleaning a subdirectory, and
+/// whether nested repositories are left alone.
+#[test]
+fn subdirectory_and_nested_repository_work() {
+    let repo = create_repository("*.o\nvendor/\n");
+    let root = repo.path();
+    create_file(root, "top.o");
+    create_file(root, "crate/lib.o");
+    create_file(root, "crate/lib.c");
+    create_file(root, "crate/vendor/dep/.git/HEAD");
+    create_file(root, "crate/vendor/dep/dep.o");
+
+    let report = remove_dir_all_with(
+        root.join("crate"),
+        RemoveOptions::new().gitignored_only(true),
+    )
+    .unwrap();
+    assert_eq!(report.files_removed(), 1);
+    assert_eq!(report.skipped().len(), 1);
+    assert_eq!(report.skipped()[0].reason(), SkipReason::NestedRepository);
+
+    assert!(root.join("top.o").is_file());
+    assert!(!root.join("crate").join("lib.o").exists());
+    assert!(root.join("crate").join("lib.c").is_file());
+    assert!(root.join("crate/vendor/dep/dep.o").is_file());
+}
+
+/// Tests whether the exclude rules of the main repository apply within a linked work tree, whose
+/// `.git` file points at a per-work-tree directory naming the shared repository directory.
+#[test]
+fn linked_work_tree_uses_common_excludes() {
+    let repo = create_repository("");
+    let git_dir = repo.path().join(".git");
+    fs::write(git_dir.join("info").join("exclude"), "*.o\n").unwrap();
+
+    // Rules in the per-work-tree directory are not used by Git.
+    let work_tree_git_dir = git_dir.join("worktrees").join("linked");
+    fs::create_dir_all(work_tree_git_dir.join("info")).unwrap();
+    fs::write(work_tree_git_dir.join("commondir"), "../..\n").unwrap();
+    fs::write(work_tree_git_dir.join("info").join("exclude"), "*.c\n").unwrap();
+
+    let work_tree = TempDir::new().unwrap();
+    let root = work_tree.path();
+    fs::write(
+        root.join(".git"),
+        format!("gitdir: {}\n", work_tree_git_dir.display()),
+    )
+    .unwrap();
+    create_file(root, "main.o");
+    create_file(root, "main.c");
+
+    let report = remove_dir_all_with(root, RemoveOptions::new().gitignored_only(true)).unwrap();
+    assert_eq!(report.files_removed(), 1);
+    assert!(!root.join("main.o").exists());
+    assert!(root.join("main.c").is_file());
+}
+
+/// Tests whether directories and entries removed by another process while cleaning count as
+/// removed rather than failing.
+#[test]
+fn vanished_entries_are_tolerated() {
+    let repo = create_repository("build/\n");
+    let root = repo.path();
+    create_file(root, "build/out/main.o");
+    let build_path = root.join("build");
+    let out_type = fs::symlink_metadata(build_path.join("out"))
+        .unwrap()
+        .file_type();
+    fs::remove_dir_all(&build_path).unwrap();
+
+    let options = RemoveOptions::new();
+    let mut engine = Engine::new(&options);
+    let mut cleaner = Cleaner {
+        engine: &mut engine,
+        matcher: Matcher::default(),
+        index: Index::default(),
+    };
+    assert!(cleaner.clean_dir(&build_path, "build", true).unwrap());
+    assert!(cleaner
+        .clean_entry(&build_path.join("out"), "build/out", out_type, true)
+        .unwrap());
+    assert_eq!(engine.finish().unwrap().dirs_removed(), 0);
+}
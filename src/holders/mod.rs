@@ -0,0 +1,159 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Identification of processes holding entries within a directory tree, for
+//! [`RemoveOptions::diagnose_holders`].
+//!
+//! On Linux, every process's open file descriptors, working directory, and memory-mapped files
+//! are read from `/proc`. On Windows, the files within the tree are registered with a Restart
+//! Manager session, which reports the processes using any of them. Nothing is ever signalled or
+//! closed; the holders are only reported.
+//!
+//! [`RemoveOptions::diagnose_holders`]: ../struct.RemoveOptions.html#method.diagnose_holders
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod procfs;
+#[cfg(test)]
+mod tests;
+
+use crate::Error;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// How a process holds an entry within a directory tree.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HoldKind {
+    /// The process has the entry open.
+    OpenFile,
+
+    /// The entry is the working directory of the process.
+    WorkingDirectory,
+
+    /// The entry is mapped into the memory of the process, such as an executable or shared
+    /// library.
+    MappedFile,
+}
+
+/// Process found holding an entry within a directory tree that could not be removed.
+///
+/// Holders are attached to removal errors as [`Error::InUse`](enum.Error.html#variant.InUse) when
+/// [`RemoveOptions::diagnose_holders`](struct.RemoveOptions.html#method.diagnose_holders) is
+/// enabled.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Holder {
+    pub(crate) pid: u32,
+    pub(crate) name: Option<String>,
+    pub(crate) kind: HoldKind,
+    pub(crate) path: Option<PathBuf>,
+}
+
+impl Holder {
+    /// Returns the ID of the process.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns the name of the process, if it could be determined.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    /// Returns how the process holds the entry.
+    pub fn kind(&self) -> HoldKind {
+        self.kind
+    }
+
+    /// Returns the path of the held entry, if known.
+    ///
+    /// Restart Manager does not report which of the files registered with it each process is
+    /// using, so this is always `None` on Windows.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(PathBuf::as_path)
+    }
+}
+
+/// Attaches the processes holding entries within `path` to `error` if it indicates that something
+/// is in use, returning the resulting error.
+///
+/// `error` is returned unchanged if it has another cause, if the holders could not be determined,
+/// or if none were found.
+pub(crate) fn diagnose(path: &Path, error: io::Error) -> io::Error {
+    if !is_in_use(&error) {
+        return error;
+    }
+
+    match find_holders(path) {
+        Ok(ref holders) if holders.is_empty() => error,
+        Ok(holders) => Error::InUse {
+            path: path.to_path_buf(),
+            holders,
+            source: error,
+        }
+        .into(),
+        Err(_) => error,
+    }
+}
+
+/// Returns the processes holding entries within the directory tree at `path`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn find_holders(path: &Path) -> io::Result<Vec<Holder>> {
+    procfs::find_holders(path)
+}
+
+/// Returns the processes holding files within the directory tree at `path`.
+#[cfg(windows)]
+pub(crate) fn find_holders(path: &Path) -> io::Result<Vec<Holder>> {
+    crate::windows::find_holders(path)
+}
+
+/// Returns the processes holding entries within the directory tree at `path`, which cannot be
+/// determined on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+pub(crate) fn find_holders(_path: &Path) -> io::Result<Vec<Holder>> {
+    Ok(Vec::new())
+}
+
+/// Returns whether `error` indicates that an entry is busy or in use by another process.
+///
+/// `ENOTEMPTY` is included since NFS clients rename files that are removed while open to hidden
+/// `.nfs` files, which then keep their directory from being removed.
+#[cfg(unix)]
+fn is_in_use(error: &io::Error) -> bool {
+    [libc::EBUSY, libc::ETXTBSY, libc::ENOTEMPTY].contains(&error.raw_os_error().unwrap_or(0))
+}
+
+/// Returns whether `error` indicates that an entry is busy or in use by another process.
+///
+/// Open files without `FILE_SHARE_DELETE` cause sharing violations, while open files with it are
+/// only removed once closed, so their directories cannot be removed in the meantime.
+#[cfg(windows)]
+fn is_in_use(error: &io::Error) -> bool {
+    use winapi::shared::winerror::{
+        ERROR_ACCESS_DENIED, ERROR_DIR_NOT_EMPTY, ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION,
+        ERROR_USER_MAPPED_FILE,
+    };
+
+    [
+        ERROR_SHARING_VIOLATION,
+        ERROR_LOCK_VIOLATION,
+        ERROR_ACCESS_DENIED,
+        ERROR_DIR_NOT_EMPTY,
+        ERROR_USER_MAPPED_FILE,
+    ]
+    .iter()
+    .any(|&code| error.raw_os_error() == Some(code as i32))
+}
+
+/// Returns whether `error` indicates that an entry is busy or in use by another process, which
+/// cannot be determined on this platform.
+#[cfg(not(any(unix, windows)))]
+fn is_in_use(_error: &io::Error) -> bool {
+    false
+}
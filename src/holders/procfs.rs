@@ -0,0 +1,106 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Holder identification using `/proc`.
+
+use super::{HoldKind, Holder};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Suffix appended by the kernel to the paths of open files that have since been removed.
+const DELETED_SUFFIX: &str = " (deleted)";
+
+/// Returns the processes with open file descriptors, working directories, or memory mappings
+/// within the directory tree at `path`.
+///
+/// Processes whose details cannot be read, such as those belonging to other users, are skipped.
+pub(super) fn find_holders(path: &Path) -> io::Result<Vec<Holder>> {
+    let root = fs::canonicalize(path)?;
+    let mut holders = Vec::new();
+
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let process_dir = entry.path();
+        let name = fs::read_to_string(process_dir.join("comm"))
+            .ok()
+            .map(|comm| comm.trim_end().to_owned());
+        let mut add = |kind, path: PathBuf| {
+            let holder = Holder {
+                pid,
+                name: name.clone(),
+                kind,
+                path: Some(path),
+            };
+            if !holders.contains(&holder) {
+                holders.push(holder);
+            }
+        };
+
+        if let Some(cwd) = read_link(&process_dir.join("cwd")) {
+            if cwd.starts_with(&root) {
+                add(HoldKind::WorkingDirectory, cwd);
+            }
+        }
+
+        if let Ok(fds) = fs::read_dir(process_dir.join("fd")) {
+            for fd in fds.filter_map(Result::ok) {
+                if let Some(target) = read_link(&fd.path()) {
+                    if target.starts_with(&root) {
+                        add(HoldKind::OpenFile, target);
+                    }
+                }
+            }
+        }
+
+        if let Ok(maps) = fs::read_to_string(process_dir.join("maps")) {
+            for target in maps.lines().filter_map(mapped_path) {
+                if target.starts_with(&root) {
+                    add(HoldKind::MappedFile, target);
+                }
+            }
+        }
+    }
+
+    Ok(holders)
+}
+
+/// Reads the target of a `/proc` link, without any suffix marking it as removed.
+fn read_link(path: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(path).ok()?;
+    match target
+        .to_str()
+        .filter(|target| target.ends_with(DELETED_SUFFIX))
+        .map(|target| &target[..target.len() - DELETED_SUFFIX.len()])
+    {
+        Some(stripped) => Some(PathBuf::from(stripped)),
+        None => Some(target),
+    }
+}
+
+/// Returns the path of the file mapped by a line of `/proc/<pid>/maps`, if any.
+///
+/// The path is the last field of the line, and may contain spaces. None of the preceding fields
+/// (the address range, permissions, offset, device, and inode) contain slashes.
+pub(super) fn mapped_path(line: &str) -> Option<PathBuf> {
+    let path = &line[line.find('/')?..];
+    if path.ends_with(DELETED_SUFFIX) {
+        Some(PathBuf::from(&path[..path.len() - DELETED_SUFFIX.len()]))
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Holder identification tests.
+
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use super::{diagnose, find_holders, procfs::mapped_path, HoldKind};
+use crate::{engine::tests::populate_dir, Error};
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process::{self, Child, Command},
+    ptr, thread,
+    time::Duration,
+};
+use tempfile::TempDir;
+
+/// Child process that is killed when dropped.
+struct ChildGuard(Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Creates a directory at `path` containing a few entries, along with a subdirectory and a file to
+/// be held by other processes.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path.join("held"))?;
+    populate_dir(path)?;
+    fs::write(path.join("held").join("data"), b"contents")
+}
+
+/// Spawns a process with its working directory set to `dir` and `file` open as its standard input.
+///
+/// The child may not have executed `sleep` yet when `spawn` returns, so this waits until its name
+/// changes from that of the test thread.
+fn spawn_holder(dir: &Path, file: &Path) -> ChildGuard {
+    let child = ChildGuard(
+        Command::new("sleep")
+            .arg("30")
+            .current_dir(dir)
+            .stdin(File::open(file).unwrap())
+            .spawn()
+            .unwrap(),
+    );
+
+    let comm = Path::new("/proc")
+        .join(child.0.id().to_string())
+        .join("comm");
+    for _ in 0..500 {
+        if fs::read_to_string(&comm)
+            .ok()
+            .map_or(false, |name| name.trim_end() == "sleep")
+        {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    child
+}
+
+/// Tests whether a child process's working directory and open file within the tree are found.
+#[test]
+fn find_holders_works() {
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    create_tree(&root).unwrap();
+    let held_dir = fs::canonicalize(root.join("held")).unwrap();
+    let held_file = held_dir.join("data");
+    let child = spawn_holder(&held_dir, &held_file);
+    let pid = child.0.id();
+
+    let holders = find_holders(&root).unwrap();
+    let child_holders: Vec<_> = holders
+        .iter()
+        .filter(|holder| holder.pid() == pid)
+        .collect();
+    assert!(child_holders
+        .iter()
+        .all(|holder| holder.name() == Some("sleep")));
+    assert!(child_holders.iter().any(|holder| {
+        holder.kind() == HoldKind::WorkingDirectory && holder.path() == Some(held_dir.as_path())
+    }));
+    assert!(child_holders.iter().any(|holder| {
+        holder.kind() == HoldKind::OpenFile && holder.path() == Some(held_file.as_path())
+    }));
+
+    let other_dir = base_dir.path().join("other");
+    fs::create_dir(&other_dir).unwrap();
+    assert!(find_holders(&other_dir).unwrap().is_empty());
+}
+
+/// Tests whether files mapped into memory within the tree are found, even after being removed.
+#[test]
+fn mapped_files_are_found() {
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    create_tree(&root).unwrap();
+    let held_file = fs::canonicalize(root.join("held").join("data")).unwrap();
+
+    let file = File::open(&held_file).unwrap();
+    let mapping = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            8,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    assert_ne!(mapping, libc::MAP_FAILED);
+    drop(file);
+    fs::remove_file(&held_file).unwrap();
+
+    let holders = find_holders(&root);
+    unsafe {
+        libc::munmap(mapping, 8);
+    }
+    assert!(holders.unwrap().iter().any(|holder| {
+        holder.pid() == process::id()
+            && holder.kind() == HoldKind::MappedFile
+            && holder.path() == Some(held_file.as_path())
+    }));
+}
+
+/// Tests whether mapped paths are parsed from lines of `/proc/<pid>/maps`.
+#[test]
+fn mapped_path_works() {
+    assert_eq!(
+        mapped_path("7f2c4a000000-7f2c4a021000 r--p 00000000 08:01 1234   /usr/lib/libc.so.6"),
+        Some(PathBuf::from("/usr/lib/libc.so.6"))
+    );
+    assert_eq!(
+        mapped_path("7f2c4a000000-7f2c4a021000 r--p 00000000 08:01 1234   /tmp/a b (deleted)"),
+        Some(PathBuf::from("/tmp/a b"))
+    );
+    assert_eq!(
+        mapped_path("7ffd5e1c9000-7ffd5e1ea000 rw-p 00000000 00:00 0      [stack]"),
+        None
+    );
+}
+
+/// Tests whether holders are attached to busy errors, and other errors are left unchanged.
+#[test]
+fn diagnose_works() {
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    create_tree(&root).unwrap();
+    let held_dir = fs::canonicalize(root.join("held")).unwrap();
+    let child = spawn_holder(&held_dir, &held_dir.join("data"));
+    let pid = child.0.id();
+
+    let busy = io::Error::from_raw_os_error(libc::EBUSY);
+    let kind = busy.kind();
+    let error = diagnose(&root, busy);
+    assert_eq!(error.kind(), kind);
+    match Error::from_io_error(&error) {
+        Some(Error::InUse { path, holders, .. }) => {
+            assert_eq!(path, &root);
+            assert!(holders.iter().any(|holder| holder.pid() == pid));
+        }
+        other => panic!("unexpected error details: {:?}", other),
+    }
+    assert!(error
+        .to_string()
+        .contains(&format!("`sleep` (PID {})", pid)));
+
+    let error = diagnose(&root, io::Error::from(io::ErrorKind::NotFound));
+    assert!(Error::from_io_error(&error).is_none());
+}
+
+/// Tests whether removals through a tombstone diagnose the tombstone, where the remaining entries
+/// are held, rather than the original path.
+#[test]
+fn tombstones_are_diagnosed() {
+    use crate::{remove_dir_all_with, RemoveOptions, TombstoneMode};
+    use std::process::Stdio;
+
+    let base_dir = TempDir::new().unwrap();
+    let base_path = fs::canonicalize(base_dir.path()).unwrap();
+    let root = base_path.join("root");
+    create_tree(&root).unwrap();
+    let mount_path = root.join("held");
+
+    // Removing the mount point fails with `EBUSY`, which requires being able to mount a filesystem.
+    let mounted = Command::new("mount")
+        .args(&["-t", "tmpfs", "none"])
+        .arg(&mount_path)
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+        .map_or(false, |status| status.success());
+    if !mounted {
+        return;
+    }
+    fs::write(mount_path.join("data"), b"contents").unwrap();
+    let child = spawn_holder(&mount_path, &mount_path.join("data"));
+
+    let mut options = RemoveOptions::new();
+    options
+        .tombstone(TombstoneMode::Synchronous)
+        .diagnose_holders(true);
+    let error = remove_dir_all_with(&root, &options).unwrap_err();
+    drop(child);
+
+    let tombstone = fs::read_dir(&base_path)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    Command::new("umount")
+        .arg(tombstone.join("held"))
+        .status()
+        .unwrap();
+
+    match Error::from_io_error(&error) {
+        Some(Error::InUse { path, holders, .. }) => {
+            assert_eq!(path, &tombstone);
+            assert!(!holders.is_empty());
+        }
+        other => panic!("unexpected error details: {:?}", other),
+    }
+}
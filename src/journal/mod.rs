@@ -0,0 +1,617 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Journals recording the intent and progress of removals, so that removals interrupted by a
+//! crash or reboot can be finished later.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    engine::sync_dir, lock, remove, tombstone, Error, RemovalReport, RemoveOptions, ShredOptions,
+};
+use std::{
+    env,
+    ffi::OsStr,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+/// Header line preceding the version number in every journal.
+const HEADER: &str = "win32-remove-dir-all journal";
+
+/// Version of the journal format written.
+const VERSION: u32 = 2;
+
+/// Extension of journal files.
+const EXTENSION: &str = "journal";
+
+/// Extension of journals being rewritten.
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Shortest time between progress updates written to a journal.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Outcome of resuming an interrupted removal found by
+/// [`resume_pending`](fn.resume_pending.html).
+#[derive(Debug)]
+pub struct ResumedRemoval {
+    journal: PathBuf,
+    target: Option<PathBuf>,
+    result: io::Result<RemovalReport>,
+}
+
+impl ResumedRemoval {
+    /// Returns the path of the journal recording the interrupted removal.
+    pub fn journal(&self) -> &Path {
+        &self.journal
+    }
+
+    /// Returns the path of the directory being removed, or `None` if the journal could not be
+    /// read.
+    pub fn target(&self) -> Option<&Path> {
+        self.target.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Returns the result of finishing the removal. Entries removed before the interruption are
+    /// included in the counts of the report.
+    pub fn result(&self) -> &io::Result<RemovalReport> {
+        &self.result
+    }
+
+    /// Consumes the outcome, returning the result of finishing the removal.
+    pub fn into_result(self) -> io::Result<RemovalReport> {
+        self.result
+    }
+}
+
+/// Finishes every removal journaled in `journal_dir` by a process that is no longer running,
+/// using the specified options.
+///
+/// Journals are written by removals using
+/// [`RemoveOptions::journal`](struct.RemoveOptions.html#method.journal), and are left behind
+/// when a removal fails or its process exits (or the system crashes) before it completes. Each
+/// one is resumed as if by [`remove_dir_all_with`](fn.remove_dir_all_with.html), with a new
+/// journal written to the same directory in case the resumed removal is interrupted as well. Any
+/// of [`gitignored_only`], [`one_file_system`], and [`shred`] that were enabled for the original
+/// removal are enabled again (shredding with default [`ShredOptions`] if `options` does not
+/// shred), so resuming never removes more than the original removal would have. Tombstones are
+/// not used when resuming.
+///
+/// A removal is only resumed if its target is still the directory it was journaled for, as
+/// recorded by its identity. If the target has since been replaced by a different directory,
+/// the removal fails with an `io::ErrorKind::InvalidData` error carrying an
+/// [`Error::TargetReplaced`], and the journal is left in place for the caller to remove.
+///
+/// **Every journal in `journal_dir` is executed as a deletion of the directory it names**, so the
+/// journal directory must not be writable by untrusted users. Anyone able to create files in it
+/// can cause any directory they can name to be removed with the privileges of the caller.
+///
+/// One [`ResumedRemoval`](struct.ResumedRemoval.html) is returned per journal resumed, and a
+/// failure to resume one does not prevent the others from being resumed. Journals that cannot be
+/// read are reported with their error and left in place. Nothing is resumed if `journal_dir` does
+/// not exist.
+///
+/// [`gitignored_only`]: struct.RemoveOptions.html#method.gitignored_only
+/// [`one_file_system`]: struct.RemoveOptions.html#method.one_file_system
+/// [`shred`]: struct.RemoveOptions.html#method.shred
+/// [`ShredOptions`]: struct.ShredOptions.html
+/// [`Error::TargetReplaced`]: enum.Error.html#variant.TargetReplaced
+///
+/// # Journal format
+///
+/// Each journaled removal writes a single journal file named `<pid>-<n>.journal` to the journal
+/// directory, where `<pid>` is the ID of the process performing the removal and `<n>` is the
+/// smallest number giving an unused name. The file is UTF-8 text made up of lines with a key and
+/// a value separated by a single space, beginning with a header identifying the format version:
+///
+/// ```text
+/// win32-remove-dir-all journal 2
+/// pid 4242
+/// target /srv/data/old%20tree
+/// identity 803-1a2b3c
+/// flags one-file-system shred
+/// removed 1024 12 3
+/// ```
+///
+/// * `pid` is the ID of the process performing the removal. Journals of running processes are
+///   never resumed.
+/// * `target` is the absolute path of the directory being removed. Bytes (the UTF-8 encoding of
+///   the path on Windows) other than printable ASCII characters, and `%` itself, are
+///   percent-encoded as `%XX`.
+/// * `identity` identifies the target directory itself, as its device and inode numbers on Unix,
+///   or its volume serial number and file index on Windows, in lowercase hexadecimal separated by
+///   `-`.
+/// * `flags` lists the options restricting what is removed, each of `gitignored-only`,
+///   `one-file-system`, and `shred`, separated by spaces. The value may be empty.
+/// * `removed` is the number of files, directories, and symbolic links removed so far, in that
+///   order, including those removed by earlier interrupted runs.
+///
+/// Readers reject any other version, and ignore unknown keys so that keys can be added without
+/// changing the version. Progress is rewritten at most once per second, by writing a temporary
+/// `<pid>-<n>.tmp` file and renaming it over the journal, and the journal is removed once the
+/// removal completes.
+///
+/// # Errors
+///
+/// Returns an error if the journal directory cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{resume_pending, RemoveOptions};
+///
+/// fn main() -> std::io::Result<()> {
+///     for resumed in resume_pending("/var/lib/app/journals", &RemoveOptions::new())? {
+///         let report = resumed.into_result()?;
+///         println!("Finished removing {} files.", report.files_removed());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn resume_pending<P: AsRef<Path>>(
+    journal_dir: P,
+    options: &RemoveOptions,
+) -> io::Result<Vec<ResumedRemoval>> {
+    let dir = journal_dir.as_ref();
+    let journals = match stale_journals(dir) {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        result => result?,
+    };
+
+    let mut resumed = Vec::new();
+    for (journal, record) in journals {
+        // Every stale journal of a target is absorbed by the first resumed removal of it.
+        if fs::symlink_metadata(&journal).is_err() {
+            continue;
+        }
+
+        let (target, result) = match record {
+            Ok(record) => {
+                let result = resume(dir, &journal, &record, options);
+                (Some(record.target), result)
+            }
+            Err(error) => (None, Err(error)),
+        };
+        resumed.push(ResumedRemoval {
+            journal,
+            target,
+            result,
+        });
+    }
+
+    Ok(resumed)
+}
+
+/// Finishes the removal recorded by `record` in the journal at `journal`.
+fn resume(
+    dir: &Path,
+    journal: &Path,
+    record: &Record,
+    options: &RemoveOptions,
+) -> io::Result<RemovalReport> {
+    // The target may have been removed just before the interruption.
+    let identity = match lock::identity(&record.target) {
+        Ok(identity) => identity,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+            fs::remove_file(journal)?;
+            let mut report = RemovalReport {
+                root_removed: !record.flags.gitignored_only,
+                ..RemovalReport::default()
+            };
+            record.removed.add_to(&mut report);
+            return Ok(report);
+        }
+        Err(error) => return Err(error),
+    };
+    if identity != record.identity {
+        return Err(Error::TargetReplaced {
+            path: record.target.clone(),
+            journal: journal.to_path_buf(),
+        }
+        .into());
+    }
+
+    let mut options = options.clone();
+    options.journal = Some(dir.to_path_buf());
+    options.tombstone = None;
+    record.flags.apply(&mut options);
+    remove::remove(&record.target, &options, None)
+}
+
+/// Journal of a removal in progress.
+pub(crate) struct Journal {
+    /// Path of the journal file.
+    path: PathBuf,
+
+    /// Contents of the journal, counting only entries removed by earlier interrupted runs.
+    record: Record,
+
+    /// Time at which progress was last written.
+    last_write: Instant,
+}
+
+impl Journal {
+    /// Writes a new journal to `dir` for the removal of `target` using the specified options.
+    ///
+    /// Stale journals in `dir` for the same target are absorbed, with the entries they record as
+    /// removed counted as removed by this one, and are removed once the new journal is durable.
+    /// Journals for a different directory that was previously at the same path are left alone.
+    pub fn begin(dir: &Path, target: &Path, options: &RemoveOptions) -> io::Result<Self> {
+        let target = absolute(target)?;
+        let identity = lock::identity(&target)?;
+        fs::create_dir_all(dir)?;
+        if absolute(dir)?.starts_with(&target) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Journal directory is within the removal target.",
+            ));
+        }
+
+        let mut removed = Counts::default();
+        let mut absorbed = Vec::new();
+        for (journal, record) in stale_journals(dir)? {
+            if let Ok(record) = record {
+                if record.target == target && record.identity == identity {
+                    removed = removed.plus(&record.removed);
+                    absorbed.push(journal);
+                }
+            }
+        }
+
+        let record = Record {
+            pid: process::id(),
+            target,
+            identity,
+            flags: Flags::from_options(options),
+            removed,
+        };
+        let path = create_journal(dir, &record)?;
+        sync_dir(dir)?;
+
+        for journal in absorbed {
+            let _ = fs::remove_file(journal.with_extension(TEMP_EXTENSION));
+            match fs::remove_file(&journal) {
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+
+        Ok(Self {
+            path,
+            record,
+            last_write: Instant::now(),
+        })
+    }
+
+    /// Records the progress in `report`, unless progress was written too recently. Failures are
+    /// ignored, since the journal still records the removal itself.
+    pub fn record(&mut self, report: &RemovalReport) {
+        if self.last_write.elapsed() >= UPDATE_INTERVAL {
+            let _ = self.flush(report);
+        }
+    }
+
+    /// Writes the progress in `report` to the journal.
+    pub fn flush(&mut self, report: &RemovalReport) -> io::Result<()> {
+        let record = Record {
+            removed: self.record.removed.plus(&Counts::from_report(report)),
+            ..self.record.clone()
+        };
+
+        let temp_path = self.path.with_extension(TEMP_EXTENSION);
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(record.to_string().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.last_write = Instant::now();
+        Ok(())
+    }
+
+    /// Removes the journal of the completed removal, adding the entries removed by earlier
+    /// interrupted runs to `report`.
+    pub fn complete(self, report: &mut RemovalReport) -> io::Result<()> {
+        self.record.removed.add_to(report);
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Contents of a journal.
+#[derive(Clone, Debug, PartialEq)]
+struct Record {
+    pid: u32,
+    target: PathBuf,
+    identity: String,
+    flags: Flags,
+    removed: Counts,
+}
+
+impl Record {
+    /// Parses the contents of a journal.
+    fn parse(contents: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut lines = contents.lines();
+        let version = lines
+            .next()
+            .filter(|line| line.starts_with(HEADER) && line[HEADER.len()..].starts_with(' '))
+            .map(|line| &line[HEADER.len() + 1..])
+            .ok_or_else(|| invalid("File is not a removal journal.".to_string()))?;
+        if version.parse::<u32>().ok() != Some(VERSION) {
+            return Err(invalid(format!(
+                "Unsupported removal journal version \"{}\".",
+                version
+            )));
+        }
+
+        let (mut pid, mut target, mut identity) = (None, None, None);
+        let (mut flags, mut removed) = (Flags::default(), None);
+        for line in lines {
+            let mut fields = line.splitn(2, ' ');
+            let key = fields.next().unwrap_or("");
+            let value = fields.next().unwrap_or("");
+            match key {
+                "pid" => pid = value.parse().ok(),
+                "target" => target = decode_path(value),
+                "identity" if !value.is_empty() => identity = Some(value.to_string()),
+                "flags" => flags = Flags::parse(value),
+                "removed" => removed = Counts::parse(value),
+                _ => {}
+            }
+        }
+
+        match (pid, target, identity, removed) {
+            (Some(pid), Some(target), Some(identity), Some(removed)) => Ok(Self {
+                pid,
+                target,
+                identity,
+                flags,
+                removed,
+            }),
+            _ => Err(invalid("Removal journal is incomplete.".to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "pid {}", self.pid)?;
+        writeln!(f, "target {}", encode_path(&self.target))?;
+        writeln!(f, "identity {}", self.identity)?;
+        writeln!(f, "flags {}", self.flags)?;
+        writeln!(
+            f,
+            "removed {} {} {}",
+            self.removed.files, self.removed.dirs, self.removed.symlinks
+        )
+    }
+}
+
+/// Options recorded in a journal, restricting what a resumed removal may remove.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Flags {
+    gitignored_only: bool,
+    one_file_system: bool,
+    shred: bool,
+}
+
+impl Flags {
+    /// Returns the flags recorded for a removal using the specified options.
+    fn from_options(options: &RemoveOptions) -> Self {
+        Self {
+            gitignored_only: options.gitignored_only,
+            one_file_system: options.one_file_system,
+            shred: options.shred.is_some(),
+        }
+    }
+
+    /// Parses the space-separated flags of a journal, ignoring unknown flags.
+    fn parse(value: &str) -> Self {
+        let mut flags = Self::default();
+        for flag in value.split(' ') {
+            match flag {
+                "gitignored-only" => flags.gitignored_only = true,
+                "one-file-system" => flags.one_file_system = true,
+                "shred" => flags.shred = true,
+                _ => {}
+            }
+        }
+
+        flags
+    }
+
+    /// Enables each recorded option in `options`.
+    fn apply(&self, options: &mut RemoveOptions) {
+        options.gitignored_only |= self.gitignored_only;
+        options.one_file_system |= self.one_file_system;
+        if self.shred && options.shred.is_none() {
+            options.shred = Some(ShredOptions::default());
+        }
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (self.gitignored_only, "gitignored-only"),
+            (self.one_file_system, "one-file-system"),
+            (self.shred, "shred"),
+        ];
+        let enabled: Vec<_> = names
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, name)| *name)
+            .collect();
+        f.write_str(&enabled.join(" "))
+    }
+}
+
+/// Numbers of entries removed, by type.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Counts {
+    files: u64,
+    dirs: u64,
+    symlinks: u64,
+}
+
+impl Counts {
+    /// Returns the numbers of entries removed according to `report`.
+    fn from_report(report: &RemovalReport) -> Self {
+        Self {
+            files: report.files_removed,
+            dirs: report.dirs_removed,
+            symlinks: report.symlinks_removed,
+        }
+    }
+
+    /// Parses the space-separated file, directory, and symbolic link counts of a journal.
+    fn parse(value: &str) -> Option<Self> {
+        let mut counts = value.split(' ').map(|count| count.parse().ok());
+        let counts = Self {
+            files: counts.next()??,
+            dirs: counts.next()??,
+            symlinks: counts.next()??,
+        };
+        Some(counts)
+    }
+
+    /// Returns the sum of these counts and `other`.
+    fn plus(&self, other: &Self) -> Self {
+        Self {
+            files: self.files + other.files,
+            dirs: self.dirs + other.dirs,
+            symlinks: self.symlinks + other.symlinks,
+        }
+    }
+
+    /// Adds these counts to those of `report`.
+    fn add_to(&self, report: &mut RemovalReport) {
+        report.files_removed += self.files;
+        report.dirs_removed += self.dirs;
+        report.symlinks_removed += self.symlinks;
+    }
+}
+
+/// Creates a journal with an unused name in `dir` containing `record`, returning its path.
+fn create_journal(dir: &Path, record: &Record) -> io::Result<PathBuf> {
+    for index in 0.. {
+        let path = dir.join(format!("{}-{}.{}", record.pid, index, EXTENSION));
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        };
+
+        file.write_all(record.to_string().as_bytes())?;
+        file.sync_all()?;
+        return Ok(path);
+    }
+
+    unreachable!("journal names are exhausted")
+}
+
+/// Returns the path of each journal in `dir` that does not belong to a running process (or
+/// cannot be read), along with its contents.
+fn stale_journals(dir: &Path) -> io::Result<Vec<(PathBuf, io::Result<Record>)>> {
+    let mut journals = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new(EXTENSION)) {
+            continue;
+        }
+
+        let record = fs::read_to_string(&path).and_then(|contents| Record::parse(&contents));
+        let running = record.as_ref().ok().map_or(false, |record| {
+            record.pid == process::id() || tombstone::is_process_running(record.pid)
+        });
+        if !running {
+            journals.push((path, record));
+        }
+    }
+
+    journals.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(journals)
+}
+
+/// Returns the absolute path of `path`, relative to the current directory if needed, without
+/// resolving symbolic links.
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}
+
+/// Returns the bytes making up `path` in a journal.
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> &[u8] {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes()
+}
+
+/// Returns the bytes making up `path` in a journal, which is its UTF-8 encoding. Paths that are not
+/// valid Unicode are lossily converted, and will not be found when resumed.
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Returns the path made up of the given bytes from a journal.
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    Some(OsString::from_vec(bytes).into())
+}
+
+/// Returns the path made up of the given bytes from a journal, which must be valid UTF-8.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Escapes `path` for the `target` key of a journal, percent-encoding every byte other than
+/// printable ASCII characters, and `%` itself.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path_bytes(path).iter() {
+        if byte.is_ascii_graphic() && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+/// Decodes a percent-encoded `target` value from a journal, returning `None` if it contains an
+/// invalid escape or is not a valid path on this platform.
+fn decode_path(value: &str) -> Option<PathBuf> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = (bytes.next()? as char).to_digit(16)?;
+            let low = (bytes.next()? as char).to_digit(16)?;
+            decoded.push((high * 16 + low) as u8);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    path_from_bytes(decoded)
+}
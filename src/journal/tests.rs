@@ -0,0 +1,365 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal journal tests.
+
+use super::{decode_path, resume_pending, Counts, Flags, Journal, Record};
+use crate::{engine::tests::create_empty_file, lock, remove_dir_all_with, Error, RemoveOptions};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempfile::TempDir;
+
+/// Returns the paths of the entries within `dir`.
+fn list_dir(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect()
+}
+
+/// Creates a directory at `path` containing the given number of files.
+fn create_tree(path: &Path, files: usize) {
+    fs::create_dir(path).unwrap();
+    for index in 0..files {
+        create_empty_file(&path.join(format!("file{}", index))).unwrap();
+    }
+}
+
+/// Returns the ID of a process that has exited.
+fn exited_pid() -> u32 {
+    let mut child = Command::new("true")
+        .spawn()
+        .or_else(|_| Command::new("cmd").args(&["/C", "exit"]).spawn())
+        .unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    pid
+}
+
+/// Tests whether journals are written in the documented format and read back, including targets
+/// that need escaping.
+#[test]
+fn format_round_trips() {
+    let record = Record {
+        pid: 4242,
+        target: PathBuf::from("/srv/data/old tree%"),
+        identity: "803-1a2b3c".to_string(),
+        flags: Flags {
+            one_file_system: true,
+            shred: true,
+            ..Flags::default()
+        },
+        removed: Counts {
+            files: 1024,
+            dirs: 12,
+            symlinks: 3,
+        },
+    };
+
+    let contents = record.to_string();
+    assert_eq!(
+        contents,
+        "win32-remove-dir-all journal 2\n\
+         pid 4242\n\
+         target /srv/data/old%20tree%25\n\
+         identity 803-1a2b3c\n\
+         flags one-file-system shred\n\
+         removed 1024 12 3\n"
+    );
+    assert_eq!(Record::parse(&contents).unwrap(), record);
+
+    let extended = format!("{}checkpoint /srv/data\n", contents);
+    assert_eq!(Record::parse(&extended).unwrap(), record);
+
+    assert_eq!(decode_path("bad%2"), None);
+    assert_eq!(decode_path("bad%zz"), None);
+}
+
+/// Tests whether targets that are not valid UTF-8 are preserved.
+#[test]
+#[cfg(unix)]
+fn non_utf8_targets_round_trip() {
+    use super::encode_path;
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let target = Path::new(OsStr::from_bytes(b"/tmp/\xff\n"));
+    let encoded = encode_path(target);
+    assert_eq!(encoded, "/tmp/%FF%0A");
+    assert_eq!(decode_path(&encoded).unwrap(), target);
+}
+
+/// Tests whether journals of other versions or with missing keys are rejected.
+#[test]
+fn invalid_journals_are_rejected() {
+    for contents in [
+        "",
+        "not a journal\n",
+        "win32-remove-dir-all journal 1\npid 1\ntarget /a\nflags \nremoved 0 0 0\n",
+        "win32-remove-dir-all journal 3\npid 1\ntarget /a\nidentity 1-2\nremoved 0 0 0\n",
+        "win32-remove-dir-all journal 2\npid 1\nidentity 1-2\nflags \nremoved 0 0 0\n",
+        "win32-remove-dir-all journal 2\npid 1\ntarget /a\nflags \nremoved 0 0 0\n",
+        "win32-remove-dir-all journal 2\npid 1\ntarget /a\nidentity 1-2\nremoved 0 0\n",
+    ]
+    .iter()
+    {
+        let error = Record::parse(contents).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    let error = Record::parse("win32-remove-dir-all journal 3\n").unwrap_err();
+    assert!(error.to_string().contains("version \"3\""));
+}
+
+/// Tests whether a journaled removal removes its journal once it completes, and refuses journal
+/// directories within the target.
+#[test]
+fn completed_removal_removes_journal() {
+    let base_dir = TempDir::new().unwrap();
+    let journal_dir = base_dir.path().join("journals");
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path, 3);
+
+    let error = remove_dir_all_with(&dir_path, RemoveOptions::new().journal(dir_path.join("j")))
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(dir_path.join("file0").exists());
+
+    let report =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().journal(&journal_dir)).unwrap();
+    assert!(report.root_removed());
+    assert_eq!(report.files_removed(), 3);
+    assert!(!dir_path.exists());
+    assert!(list_dir(&journal_dir).is_empty());
+}
+
+/// Tests whether removing a target again finishes an interrupted removal recorded by a stale
+/// journal, counting the entries removed before the interruption.
+#[test]
+fn later_removal_absorbs_stale_journal() {
+    let base_dir = TempDir::new().unwrap();
+    let journal_dir = base_dir.path().join("journals");
+    let dir_path = base_dir.path().join("target");
+    let other_path = base_dir.path().join("other");
+    create_tree(&dir_path, 2);
+    fs::create_dir(&journal_dir).unwrap();
+
+    let pid = exited_pid();
+    let identities = [lock::identity(&dir_path).unwrap(), "0-0".to_string()];
+    for (index, target) in [&dir_path, &other_path].iter().enumerate() {
+        let record = Record {
+            pid,
+            target: target.to_path_buf(),
+            identity: identities[index].clone(),
+            flags: Flags::default(),
+            removed: Counts {
+                files: 5,
+                dirs: 1,
+                symlinks: 0,
+            },
+        };
+        fs::write(
+            journal_dir.join(format!("{}-{}.journal", pid, index)),
+            record.to_string(),
+        )
+        .unwrap();
+    }
+
+    let report =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().journal(&journal_dir)).unwrap();
+    assert_eq!(report.files_removed(), 7);
+    assert_eq!(report.dirs_removed(), 2);
+    assert_eq!(
+        list_dir(&journal_dir),
+        vec![journal_dir.join(format!("{}-1.journal", pid))]
+    );
+
+    // The other target no longer exists, so resuming only removes its journal.
+    let resumed = resume_pending(&journal_dir, &RemoveOptions::new()).unwrap();
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].target(), Some(other_path.as_path()));
+    let report = resumed.into_iter().next().unwrap().into_result().unwrap();
+    assert!(report.root_removed());
+    assert_eq!(report.files_removed(), 5);
+    assert!(list_dir(&journal_dir).is_empty());
+
+    assert!(
+        resume_pending(base_dir.path().join("missing"), &RemoveOptions::new())
+            .unwrap()
+            .is_empty()
+    );
+}
+
+/// Tests whether journals belonging to running processes are left alone, while unreadable
+/// journals are reported.
+#[test]
+fn running_and_unreadable_journals() {
+    let base_dir = TempDir::new().unwrap();
+    let journal_dir = base_dir.path().join("journals");
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path, 1);
+
+    let options = RemoveOptions::new().journal(&journal_dir).clone();
+    let _journal = Journal::begin(&journal_dir, &dir_path, &options).unwrap();
+    let garbage = journal_dir.join("garbage.journal");
+    fs::write(&garbage, "garbage").unwrap();
+
+    let resumed = resume_pending(&journal_dir, &RemoveOptions::new()).unwrap();
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].journal(), garbage.as_path());
+    assert_eq!(resumed[0].target(), None);
+    assert_eq!(
+        resumed[0].result().as_ref().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert!(dir_path.join("file0").exists());
+}
+
+/// Tests whether a journal is not resumed once its target has been replaced by a different
+/// directory, and is left in place.
+#[test]
+fn replaced_target_is_not_resumed() {
+    let base_dir = TempDir::new().unwrap();
+    let journal_dir = base_dir.path().join("journals");
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path, 2);
+    fs::create_dir(&journal_dir).unwrap();
+
+    let pid = exited_pid();
+    let record = Record {
+        pid,
+        target: dir_path.clone(),
+        identity: lock::identity(&dir_path).unwrap(),
+        flags: Flags::default(),
+        removed: Counts::default(),
+    };
+    let journal = journal_dir.join(format!("{}-0.journal", pid));
+    fs::write(&journal, record.to_string()).unwrap();
+
+    // Renaming the original away keeps its identity from being reused by the replacement.
+    fs::rename(&dir_path, base_dir.path().join("original")).unwrap();
+    create_tree(&dir_path, 2);
+
+    let resumed = resume_pending(&journal_dir, &RemoveOptions::new()).unwrap();
+    assert_eq!(resumed.len(), 1);
+    let error = resumed[0].result().as_ref().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    match Error::from_io_error(error) {
+        Some(Error::TargetReplaced {
+            path,
+            journal: replaced_journal,
+        }) => {
+            assert_eq!(path, &dir_path);
+            assert_eq!(replaced_journal, &journal);
+        }
+        other => panic!("unexpected error details: {:?}", other),
+    }
+    assert!(dir_path.join("file0").is_file());
+    assert!(journal.is_file());
+
+    // A new removal of the replacement leaves the journal of the original alone as well.
+    remove_dir_all_with(&dir_path, RemoveOptions::new().journal(&journal_dir)).unwrap();
+    assert_eq!(list_dir(&journal_dir), vec![journal]);
+}
+
+/// Environment variable naming the target removed by [`interrupted_removal_child`].
+const CHILD_TARGET: &str = "WIN32_REMOVE_DIR_ALL_JOURNAL_TARGET";
+
+/// Environment variable naming the journal directory used by [`interrupted_removal_child`].
+const CHILD_JOURNALS: &str = "WIN32_REMOVE_DIR_ALL_JOURNAL_DIR";
+
+/// Removes the target named by the environment using a journal when run as a child process by
+/// [`killed_removal_is_resumed`], which kills it partway through. This does nothing when run as
+/// part of the normal test suite.
+#[test]
+fn interrupted_removal_child() {
+    let (target, journal_dir) = match (
+        std::env::var_os(CHILD_TARGET),
+        std::env::var_os(CHILD_JOURNALS),
+    ) {
+        (Some(target), Some(journal_dir)) => (PathBuf::from(target), PathBuf::from(journal_dir)),
+        _ => return,
+    };
+
+    remove_dir_all_with(&target, RemoveOptions::new().journal(&journal_dir)).unwrap();
+}
+
+/// Tests whether a removal killed partway through is finished by `resume_pending`, with the counts
+/// of both runs reported.
+#[test]
+#[cfg(target_os = "linux")]
+fn killed_removal_is_resumed() {
+    use crate::engine::tests::create_empty_file;
+    use std::{process::Stdio, thread, time::Duration};
+
+    /// Number of subdirectories of the tree removed by the child.
+    const CHILD_SUBDIRS: usize = 20;
+
+    /// Number of files in each subdirectory of the tree removed by the child.
+    const CHILD_FILES: usize = 500;
+
+    let base_dir = TempDir::new().unwrap();
+    let journal_dir = base_dir.path().join("journals");
+    let dir_path = base_dir.path().join("target");
+    for subdir in 0..CHILD_SUBDIRS {
+        let subdir_path = dir_path.join(format!("dir{}", subdir));
+        fs::create_dir_all(&subdir_path).unwrap();
+        for file in 0..CHILD_FILES {
+            create_empty_file(&subdir_path.join(format!("file{}", file))).unwrap();
+        }
+    }
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(&[
+            "--exact",
+            "journal::tests::interrupted_removal_child",
+            "--nocapture",
+        ])
+        .env(CHILD_TARGET, &dir_path)
+        .env(CHILD_JOURNALS, &journal_dir)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Subdirectories are emptied one at a time, so the child is killed soon after removing the
+    // first, with most of the tree remaining.
+    for _ in 0..10_000 {
+        let remaining = fs::read_dir(&dir_path).map(Iterator::count).unwrap_or(0);
+        if remaining < CHILD_SUBDIRS || child.try_wait().unwrap().is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(dir_path.exists(), "child finished before being killed");
+
+    let journals = list_dir(&journal_dir);
+    assert_eq!(journals.len(), 1);
+    let record = Record::parse(&fs::read_to_string(&journals[0]).unwrap()).unwrap();
+    assert_eq!(record.target, dir_path);
+    assert_eq!(record.identity, lock::identity(&dir_path).unwrap());
+
+    let remaining_files: usize = list_dir(&dir_path)
+        .iter()
+        .map(|subdir| list_dir(subdir).len())
+        .sum();
+    assert!(remaining_files < CHILD_SUBDIRS * CHILD_FILES);
+
+    let resumed = resume_pending(&journal_dir, &RemoveOptions::new()).unwrap();
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].journal(), journals[0].as_path());
+    let report = resumed.into_iter().next().unwrap().into_result().unwrap();
+    assert!(report.root_removed());
+    assert!(report.files_removed() as usize >= remaining_files);
+    assert!(report.files_removed() as usize <= CHILD_SUBDIRS * CHILD_FILES);
+    assert!(!dir_path.exists());
+    assert!(list_dir(&journal_dir).is_empty());
+}
@@ -19,8 +19,8 @@
 //! This crate provides a `remove_dir_all` implementation based on both [`SHFileOperationW`] and
 //! [`IFileOperation`], with the former used as a fallback if the latter is not supported
 //! ([`IFileOperation`] is recommended over [`SHFileOperationW`], but it is only supported on
-//! Windows Vista and later). For non-Windows platforms, the standard library `remove_dir_all`
-//! function is re-exported for convenience.
+//! Windows Vista and later). For non-Windows platforms, a portable implementation equivalent to the
+//! standard library `remove_dir_all` function is provided for convenience.
 //!
 //! Due to the lack of Shell and Property System API support for UWP apps, UWP app developers are
 //! recommended to use the [`remove_dir_all` crate] instead, as it provides an alternative
@@ -30,7 +30,7 @@
 //!
 //! The [`remove_dir_all`](fn.remove_dir_all.html) function provided by this crate can be used as a
 //! drop-in replacement for [`std::fs::remove_dir_all`], even in code targeting multiple platforms;
-//! [`std::fs::remove_dir_all`] will be used automatically on non-Windows targets.
+//! an equivalent portable implementation will be used automatically on non-Windows targets.
 //!
 //! ```no_run
 //! use std::{error::Error, fs, path::Path};
@@ -49,6 +49,96 @@
 //! }
 //! ```
 //!
+//! # Removal Options
+//!
+//! [`remove_dir_all_with`](fn.remove_dir_all_with.html) accepts a set of
+//! [`RemoveOptions`](struct.RemoveOptions.html) for more specialized removal tasks, and returns a
+//! [`RemovalReport`](struct.RemovalReport.html) summarizing the work performed. Options that
+//! require inspecting each entry in a directory tree individually, such as removing only
+//! Git-ignored entries, are implemented by a portable `std::fs`-based engine on all platforms.
+//!
+//! ```no_run
+//! use win32_remove_dir_all::{remove_dir_all_with, RemoveOptions};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     // Remove the build artifacts ignored by Git, as with `git clean -fdX`.
+//!     let report = remove_dir_all_with("workspace", RemoveOptions::new().gitignored_only(true))?;
+//!     println!("Removed {} files.", report.files_removed());
+//!     Ok(())
+//! }
+//! ```
+//!
+//! On Windows, entries that another process holds open linger in a "delete pending" state after
+//! being removed. [`wait_until_gone`](fn.wait_until_gone.html), or the corresponding
+//! [`RemoveOptions`](struct.RemoveOptions.html#method.wait_until_gone) option, waits for the path
+//! to actually disappear so that it can be reused immediately.
+//!
+//! When an entry cannot be removed due to a lack of permission, the error carries an
+//! [`Error::AccessDenied`](enum.Error.html#variant.AccessDenied) explaining why where possible,
+//! such as the parent directory not being writable or the entry being marked immutable.
+//!
+//! When a removal fails because something within the directory is in use,
+//! [`RemoveOptions::diagnose_holders`](struct.RemoveOptions.html#method.diagnose_holders) can be
+//! enabled to attach the processes holding it to the error, without disturbing them.
+//!
+//! # Protected Paths
+//!
+//! Since a single bad argument to `remove_dir_all` can be catastrophic, filesystem and drive roots,
+//! home directories, and well-known system directories are refused by default, along with any
+//! directory containing them. Refusals are reported before anything is removed as an
+//! `io::ErrorKind::PermissionDenied` error carrying an [`Error`](enum.Error.html). The set of
+//! protected paths can be adjusted using [`RemoveOptions`](struct.RemoveOptions.html).
+//!
+//! Paths from untrusted sources, such as plugin manifests, can be removed using
+//! [`remove_dir_all_beneath`](fn.remove_dir_all_beneath.html), which refuses to touch anything
+//! outside of a given root directory, even through `..` components or symbolic links.
+//!
+//! Where the target may be a file, a symbolic link, or a directory,
+//! [`remove_path_all`](fn.remove_path_all.html) removes whatever is found at the path and reports
+//! which [`EntryKind`](enum.EntryKind.html) it was, avoiding a separate (and racy) check.
+//! Similarly, [`remove_dir_all_if_exists`](fn.remove_dir_all_if_exists.html) treats a missing
+//! directory as success. Entries created or removed by other processes during a removal are
+//! tolerated, with refilled directories re-scanned a bounded number of times.
+//!
+//! Large directory trees can be removed without blocking the calling thread using
+//! [`remove_dir_all_background`](fn.remove_dir_all_background.html), which returns a
+//! [`RemovalHandle`](struct.RemovalHandle.html) for monitoring progress, waiting for completion,
+//! or cancelling the removal. With the `async` crate feature enabled, Tokio-based applications
+//! can use [`remove_dir_all_async`](fn.remove_dir_all_async.html) and related functions instead,
+//! which run on Tokio's blocking thread pool.
+//!
+//! Many directories can be removed at once using [`remove_all`](fn.remove_all.html), which
+//! batches them into a single Shell or Property System operation on Windows and removes them in
+//! parallel elsewhere.
+//!
+//! Programs removing many directories, such as test harnesses cleaning up temporary directories,
+//! can use a [`Remover`](struct.Remover.html) session to run every removal on a single worker
+//! thread, avoiding the cost of setting up a COM thread for each removal on Windows.
+//!
+//! Directory trees can also be copied using [`copy_dir_all`](fn.copy_dir_all.html), which uses
+//! the same Shell and Property System backends on Windows where possible, and supports link and
+//! overwrite policies, progress callbacks, and per-entry error reporting.
+//!
+//! [`move_dir_all`](fn.move_dir_all.html) moves a directory by renaming it where possible, falling
+//! back to copying, verifying, and removing it when the destination is on another filesystem.
+//!
+//! A deployed directory can be swapped for a new version using
+//! [`replace_dir`](fn.replace_dir.html), which exchanges the two trees atomically on Linux and
+//! rolls back a failed swap elsewhere, before removing the old tree.
+//!
+//! Processes that may remove the same directory at once, such as build agents sharing a cache,
+//! can take an advisory lock on the target using
+//! [`RemoveOptions::lock`](struct.RemoveOptions.html#method.lock), waiting for or failing fast on
+//! concurrent removals instead of tripping over each other.
+//!
+//! Removals of very large trees can be journaled using
+//! [`RemoveOptions::journal`](struct.RemoveOptions.html#method.journal), so that a removal
+//! interrupted by a crash or reboot can be finished later using
+//! [`resume_pending`](fn.resume_pending.html).
+//!
+//! Where users should be able to undo a removal, [`trash_dir_all`](fn.trash_dir_all.html) moves a
+//! directory to the Recycle Bin on Windows or the freedesktop.org trash on Linux instead.
+//!
 //! # Disabling Property System ([`IFileOperation`]) Support
 //!
 //! Support for [`IFileOperation`] is gated behind the `property_system_api` crate feature, which is
@@ -66,10 +156,64 @@
 //! [`IFileOperation`]: https://docs.microsoft.com/en-us/windows/win32/api/shobjidl_core/nn-shobjidl_core-ifileoperation
 //! [`remove_dir_all` crate]: https://crates.io/crates/remove_dir_all
 
+#[cfg(feature = "async")]
+mod asynchronous;
+mod background;
+mod batch;
+mod beneath;
+mod copy;
+mod engine;
+mod entry;
+mod error;
+mod gitignore;
+mod holders;
+mod journal;
+mod lock;
+mod mounts;
+mod options;
+mod permissions;
+mod relocate;
+mod remove;
+mod replace;
+mod report;
+mod safeguard;
+mod shred;
+mod symlinks;
+mod tombstone;
+mod trash;
+mod wait;
 #[cfg(windows)]
 mod windows;
-#[cfg(windows)]
-pub use windows::remove_dir_all;
+mod worker;
 
-#[cfg(not(windows))]
-pub use std::fs::remove_dir_all;
+#[cfg(feature = "async")]
+pub use asynchronous::{
+    remove_dir_all_async, remove_dir_all_with_async, remove_dir_all_with_progress, RemovalFuture,
+};
+pub use background::{
+    remove_dir_all_background, remove_dir_all_background_with, RemovalHandle, RemovalProgress,
+};
+pub use batch::{remove_all, BatchOutcome};
+pub use beneath::remove_dir_all_beneath;
+pub use copy::{
+    copy_dir_all, CopyFailure, CopyOptions, CopyProgress, CopyReport, CopySymlinkPolicy,
+    OverwritePolicy,
+};
+pub use entry::{remove_path_all, remove_path_all_with};
+pub use error::{DenialReason, Error, ProtectionReason};
+pub use holders::{HoldKind, Holder};
+pub use journal::{resume_pending, ResumedRemoval};
+pub use lock::LockMode;
+pub use options::RemoveOptions;
+pub use relocate::{move_dir_all, MoveMethod, MoveReport};
+pub use remove::{remove_dir_all, remove_dir_all_if_exists, remove_dir_all_with};
+pub use replace::{replace_dir, replace_dir_with};
+pub use report::{EntryKind, RemovalReport, SkipReason, SkippedEntry};
+pub use shred::{ShredOptions, ShredPattern};
+pub use symlinks::SymlinkPolicy;
+pub use tombstone::TombstoneMode;
+pub use trash::trash_dir_all;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub use trash::{RestoreConflict, Trash, TrashedEntry};
+pub use wait::wait_until_gone;
+pub use worker::Remover;
@@ -0,0 +1,338 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cross-process advisory locks, for [`RemoveOptions::lock`].
+//!
+//! Lock files are named after the identity of the target (its device and inode numbers on Unix,
+//! or its volume serial number and file index on Windows) rather than its path, so that removals
+//! reaching the same directory through different paths contend for the same lock. They are locked
+//! using `flock` on Unix and `LockFileEx` on Windows. Lock files are never removed, since a
+//! process waiting on a removed lock file would no longer exclude processes locking a new file at
+//! the same path.
+//!
+//! By default, lock files reside in a directory within the system temporary directory that is
+//! private to the current user (named after the effective user ID on Unix), since anyone able to
+//! create files in the lock directory could plant links or hold locks in advance. That directory
+//! is verified to be a real directory owned by the current user and not writable by anyone else
+//! before it is used, and lock files are opened without following links. Removals by different
+//! users only contend if they share a directory set using [`RemoveOptions::lock_dir`].
+//!
+//! [`RemoveOptions::lock`]: ../struct.RemoveOptions.html#method.lock
+//! [`RemoveOptions::lock_dir`]: ../struct.RemoveOptions.html#method.lock_dir
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    wait::{self, SystemClock},
+    Error,
+};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Name of the directory within the system temporary directory holding lock files, followed by the
+/// effective user ID on Unix.
+const LOCK_DIR: &str = "win32-remove-dir-all-locks";
+
+/// How a removal waits for the lock on its target while another removal holds it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LockMode {
+    /// The removal waits for as long as the lock is held.
+    Wait,
+
+    /// The removal fails immediately if the lock is held.
+    FailFast,
+
+    /// The removal waits for up to the given duration for the lock to be released, then fails.
+    Timeout(Duration),
+}
+
+/// Advisory lock on a removal target, released when dropped.
+pub(crate) struct RemovalLock {
+    _file: File,
+}
+
+/// Locks the target at `path` against concurrent removals by other processes (or threads) using
+/// the same option, waiting according to `mode`.
+///
+/// Lock files are kept in `lock_dir` if given, or in the default private directory otherwise. If
+/// the target is replaced by a different directory while waiting, the lock on the new directory is
+/// taken instead. If it is removed while waiting, a `NotFound` error is returned.
+pub(crate) fn acquire(
+    path: &Path,
+    mode: LockMode,
+    lock_dir: Option<&Path>,
+) -> io::Result<RemovalLock> {
+    let dir = match lock_dir {
+        Some(dir) => prepare_shared_dir(dir)?,
+        None => prepare_default_dir()?,
+    };
+
+    let mut clock = SystemClock::start();
+    loop {
+        let key = identity(path)?;
+        let file = open_lock_file(&dir, &key)?;
+
+        let locked = match mode {
+            LockMode::Wait => lock_file(&file, true)?,
+            LockMode::FailFast => lock_file(&file, false)?,
+            LockMode::Timeout(timeout) => {
+                wait::poll(timeout, &mut clock, |_| lock_file(&file, false))?
+            }
+        };
+        if !locked {
+            let timeout = match mode {
+                LockMode::Timeout(timeout) => Some(timeout),
+                _ => None,
+            };
+            return Err(Error::Locked {
+                path: path.to_path_buf(),
+                timeout,
+            }
+            .into());
+        }
+
+        // The target may have been removed or replaced while waiting.
+        if identity(path)? == key {
+            return Ok(RemovalLock { _file: file });
+        }
+    }
+}
+
+/// Opens (creating if needed) the lock file in `dir` for the target with the given identity,
+/// without following links.
+fn open_lock_file(dir: &Path, key: &str) -> io::Result<File> {
+    let path = dir.join(format!("{}.lock", key));
+
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o644).custom_flags(libc::O_NOFOLLOW);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use winapi::um::winbase::FILE_FLAG_OPEN_REPARSE_POINT;
+
+        options.custom_flags(FILE_FLAG_OPEN_REPARSE_POINT);
+    }
+
+    let file = match options.open(&path) {
+        // Lock files created by other users of a shared directory may only be readable, which
+        // suffices for locking.
+        Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => {
+            options.write(false).create(false).open(&path)?
+        }
+        result => result?,
+    };
+
+    // Reparse points are opened themselves rather than followed on Windows, so they are refused
+    // here instead.
+    if !file.metadata()?.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Lock file `{}` is not a regular file.", path.display()),
+        ));
+    }
+
+    Ok(file)
+}
+
+/// Returns the default directory holding lock files, creating it if needed and verifying that it
+/// is private to the current user.
+fn prepare_default_dir() -> io::Result<PathBuf> {
+    #[cfg(unix)]
+    let name = format!("{}-{}", LOCK_DIR, unsafe { libc::geteuid() });
+    #[cfg(not(unix))]
+    let name = LOCK_DIR;
+
+    let dir = env::temp_dir().join(name);
+    #[cfg(unix)]
+    let created = {
+        use std::os::unix::fs::DirBuilderExt;
+
+        fs::DirBuilder::new().mode(0o700).create(&dir)
+    };
+    #[cfg(not(unix))]
+    let created = fs::create_dir(&dir);
+    match created {
+        Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+        result => result?,
+    }
+
+    verify_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Verifies that `dir` is a real directory owned by the current user and not writable by anyone
+/// else, since it resides in a directory shared by every user.
+#[cfg(unix)]
+fn verify_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::geteuid() }
+        || metadata.mode() & 0o022 != 0
+    {
+        return Err(untrusted_dir(dir));
+    }
+
+    Ok(())
+}
+
+/// Verifies that `dir` is a real directory rather than a link. The system temporary directory is
+/// private to each user on Windows, so ownership is not checked.
+#[cfg(not(unix))]
+fn verify_private_dir(dir: &Path) -> io::Result<()> {
+    if !fs::symlink_metadata(dir)?.is_dir() {
+        return Err(untrusted_dir(dir));
+    }
+
+    Ok(())
+}
+
+/// Returns the error reported when the default lock directory cannot be trusted.
+fn untrusted_dir(dir: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "Lock directory `{}` is not a private directory owned by the current user.",
+            dir.display()
+        ),
+    )
+}
+
+/// Returns the lock directory `dir` set by the caller, creating it if needed. Its writers are
+/// trusted by the caller, so only its existence is ensured.
+fn prepare_shared_dir(dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    Ok(dir.to_path_buf())
+}
+
+/// Returns a string identifying the entry at `path` (without following symbolic links), for use
+/// as the name of its lock file.
+#[cfg(unix)]
+pub(crate) fn identity(path: &Path) -> io::Result<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    Ok(format!("{:x}-{:x}", metadata.dev(), metadata.ino()))
+}
+
+/// Returns a string identifying the entry at `path` (without following symbolic links or
+/// junctions), for use as the name of its lock file.
+#[cfg(windows)]
+pub(crate) fn identity(path: &Path) -> io::Result<String> {
+    use std::{
+        mem,
+        os::windows::{fs::OpenOptionsExt, io::AsRawHandle},
+    };
+    use winapi::um::{
+        fileapi::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION},
+        winbase::{FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT},
+    };
+
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+        .open(path)?;
+
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { mem::zeroed() };
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, &mut info) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let index = (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow);
+    Ok(format!("{:x}-{:x}", info.dwVolumeSerialNumber, index))
+}
+
+/// Returns a string identifying the entry at `path`, for use as the name of its lock file. No
+/// stable identity is available on this platform, so the canonical path is used instead.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn identity(path: &Path) -> io::Result<String> {
+    let canonical = fs::canonicalize(path)?;
+    let mut key = String::new();
+    for byte in canonical.to_string_lossy().bytes() {
+        key.push_str(&format!("{:02x}", byte));
+    }
+
+    Ok(key)
+}
+
+/// Takes an exclusive lock on `file`, blocking until it is available if `wait` is set, and
+/// returns whether the lock was taken.
+#[cfg(unix)]
+fn lock_file(file: &File, wait: bool) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let operation = if wait {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_EX | libc::LOCK_NB
+    };
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(true);
+        }
+
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EWOULDBLOCK) => return Ok(false),
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Takes an exclusive lock on `file`, blocking until it is available if `wait` is set, and
+/// returns whether the lock was taken.
+#[cfg(windows)]
+fn lock_file(file: &File, wait: bool) -> io::Result<bool> {
+    use std::{mem, os::windows::io::AsRawHandle};
+    use winapi::{
+        shared::winerror::ERROR_LOCK_VIOLATION,
+        um::{
+            fileapi::LockFileEx,
+            minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED},
+        },
+    };
+
+    let mut flags = LOCKFILE_EXCLUSIVE_LOCK;
+    if !wait {
+        flags |= LOCKFILE_FAIL_IMMEDIATELY;
+    }
+
+    let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+    if unsafe { LockFileEx(file.as_raw_handle() as _, flags, 0, 1, 0, &mut overlapped) } != 0 {
+        return Ok(true);
+    }
+
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+        Ok(false)
+    } else {
+        Err(error)
+    }
+}
+
+/// Takes an exclusive lock on `file`, which is not supported on this platform, so the lock is
+/// always reported as taken.
+#[cfg(not(any(unix, windows)))]
+fn lock_file(_file: &File, _wait: bool) -> io::Result<bool> {
+    Ok(true)
+}
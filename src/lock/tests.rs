@@ -0,0 +1,262 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Advisory lock tests.
+
+use super::{acquire, LockMode};
+#[cfg(unix)]
+use super::{identity, verify_private_dir};
+use crate::{
+    engine::tests::{create_empty_file, populate_dir},
+    remove_dir_all_with, Error, RemoveOptions,
+};
+use std::{env, fs, io, path::PathBuf, thread, time::Duration};
+use tempfile::TempDir;
+
+/// Returns the `Error::Locked` timeout carried by `error`, panicking if it carries anything else.
+fn locked_timeout(error: &io::Error) -> Option<Duration> {
+    match Error::from_io_error(error) {
+        Some(Error::Locked { timeout, .. }) => *timeout,
+        other => panic!("unexpected error details: {:?}", other),
+    }
+}
+
+/// Tests whether a second lock on the same directory contends with the first, even when reached
+/// through a different path, and whether locks are released when dropped.
+#[test]
+fn locks_contend_within_process() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    let other_path = base_dir.path().join("other");
+    fs::create_dir(&other_path).unwrap();
+
+    let lock = acquire(&dir_path, LockMode::FailFast, None).unwrap();
+    let _other_lock = acquire(&other_path, LockMode::FailFast, None).unwrap();
+
+    let indirect_path = dir_path.join("..").join("dir");
+    let error = acquire(&indirect_path, LockMode::FailFast, None)
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(locked_timeout(&error), None);
+
+    let timeout = Duration::from_millis(20);
+    let error = acquire(&dir_path, LockMode::Timeout(timeout), None)
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(locked_timeout(&error), Some(timeout));
+
+    drop(lock);
+    acquire(&indirect_path, LockMode::FailFast, None).unwrap();
+}
+
+/// Tests whether waiting for a lock in another thread completes once it is released, failing if
+/// the target was removed in the meantime.
+#[test]
+fn waiting_observes_removal() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let lock = acquire(&dir_path, LockMode::FailFast, None).unwrap();
+    let waiter_path = dir_path.clone();
+    let waiter = thread::spawn(move || {
+        remove_dir_all_with(&waiter_path, RemoveOptions::new().lock(LockMode::Wait))
+    });
+
+    thread::sleep(Duration::from_millis(50));
+    remove_dir_all_with(&dir_path, &RemoveOptions::new()).unwrap();
+    drop(lock);
+
+    let error = waiter.join().unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
+
+/// Tests whether locks only contend within the same lock directory, and whether links planted in
+/// place of lock files are refused rather than followed.
+#[test]
+fn lock_dirs_are_separate() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    let shared_dir = base_dir.path().join("locks");
+
+    let _lock = acquire(&dir_path, LockMode::FailFast, Some(&shared_dir)).unwrap();
+    let error = acquire(&dir_path, LockMode::FailFast, Some(&shared_dir))
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    acquire(&dir_path, LockMode::FailFast, None).unwrap();
+
+    #[cfg(unix)]
+    {
+        let other_path = base_dir.path().join("other");
+        fs::create_dir(&other_path).unwrap();
+        let victim = base_dir.path().join("victim");
+        fs::write(&victim, b"contents").unwrap();
+        let lock_file = shared_dir.join(format!("{}.lock", identity(&other_path).unwrap()));
+        std::os::unix::fs::symlink(&victim, lock_file).unwrap();
+
+        assert!(acquire(&other_path, LockMode::FailFast, Some(&shared_dir)).is_err());
+        assert_eq!(fs::read(&victim).unwrap(), b"contents");
+    }
+}
+
+/// Tests whether the default lock directory is only trusted if it is a real directory owned by
+/// the current user and not writable by anyone else.
+#[test]
+#[cfg(unix)]
+fn untrusted_lock_dirs_are_refused() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let base_dir = TempDir::new().unwrap();
+    let private_dir = base_dir.path().join("private");
+    fs::create_dir(&private_dir).unwrap();
+    fs::set_permissions(&private_dir, fs::Permissions::from_mode(0o700)).unwrap();
+    verify_private_dir(&private_dir).unwrap();
+
+    let link = base_dir.path().join("link");
+    symlink(&private_dir, &link).unwrap();
+    let error = verify_private_dir(&link).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+    fs::set_permissions(&private_dir, fs::Permissions::from_mode(0o1777)).unwrap();
+    let error = verify_private_dir(&private_dir).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+}
+
+/// Environment variable naming the target locked by [`lock_holder_child`].
+const CHILD_TARGET: &str = "WIN32_REMOVE_DIR_ALL_LOCK_TARGET";
+
+/// Environment variable giving the number of milliseconds [`lock_holder_child`] holds its lock.
+const CHILD_HOLD_MS: &str = "WIN32_REMOVE_DIR_ALL_LOCK_HOLD_MS";
+
+/// Locks the target named by the environment when run as a child process by the contention tests,
+/// then signals that it holds the lock by creating a `ready` file next to the target, and removes
+/// the target once it has held the lock for the requested time. This does nothing when run as
+/// part of the normal test suite.
+#[test]
+fn lock_holder_child() {
+    let (target, hold_ms) = match (env::var_os(CHILD_TARGET), env::var(CHILD_HOLD_MS)) {
+        (Some(target), Ok(hold_ms)) => (PathBuf::from(target), hold_ms.parse().unwrap()),
+        _ => return,
+    };
+
+    let _lock = acquire(&target, LockMode::FailFast, None).unwrap();
+    create_empty_file(&target.with_file_name("ready")).unwrap();
+    thread::sleep(Duration::from_millis(hold_ms));
+    remove_dir_all_with(&target, &RemoveOptions::new()).unwrap();
+}
+
+/// Child process holding the lock on a target, killed when dropped.
+#[cfg(target_os = "linux")]
+struct LockHolder(std::process::Child);
+
+#[cfg(target_os = "linux")]
+impl LockHolder {
+    /// Spawns a child process locking `target` for `hold_ms` milliseconds before removing it,
+    /// waiting until it holds the lock.
+    fn spawn(target: &std::path::Path, hold_ms: u64) -> Self {
+        use std::process::{Command, Stdio};
+
+        let child = Command::new(env::current_exe().unwrap())
+            .args(&["--exact", "lock::tests::lock_holder_child", "--nocapture"])
+            .env(CHILD_TARGET, target)
+            .env(CHILD_HOLD_MS, hold_ms.to_string())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut holder = Self(child);
+
+        let ready = target.with_file_name("ready");
+        for _ in 0..1000 {
+            if ready.exists() || holder.0.try_wait().unwrap().is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(ready.exists(), "child did not lock the target");
+        holder
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for LockHolder {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Tests whether removals fail fast or time out while another process holds the lock, without
+/// removing anything, and succeed once it exits.
+#[test]
+#[cfg(target_os = "linux")]
+fn contention_with_child_process() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let holder = LockHolder::spawn(&dir_path, 60_000);
+
+    let error =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().lock(LockMode::FailFast)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(locked_timeout(&error), None);
+
+    let timeout = Duration::from_millis(100);
+    let error = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new().lock(LockMode::Timeout(timeout)),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(locked_timeout(&error), Some(timeout));
+    assert!(dir_path.join("baz").join("qux").is_file());
+
+    // Locks are released when the process holding them exits, however it exits.
+    drop(holder);
+    let report =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().lock(LockMode::FailFast)).unwrap();
+    assert!(report.root_removed());
+}
+
+/// Tests whether a removal waiting for the lock held by another process proceeds once it is
+/// released, reporting that the other process removed the target rather than failing partway.
+#[test]
+#[cfg(target_os = "linux")]
+fn waiting_for_child_process() {
+    use std::time::Instant;
+
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let _holder = LockHolder::spawn(&dir_path, 200);
+    let start = Instant::now();
+    let error =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().lock(LockMode::Wait)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(!dir_path.exists());
+
+    fs::create_dir(&dir_path).unwrap();
+    fs::remove_file(base_dir.path().join("ready")).unwrap();
+    let _holder = LockHolder::spawn(&dir_path, 200);
+    let error = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new().lock(LockMode::Timeout(Duration::from_secs(10))),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
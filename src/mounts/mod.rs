@@ -0,0 +1,172 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Filesystem boundary detection for [`RemoveOptions::one_file_system`].
+//!
+//! On Unix, entries residing on a different device than the removal target are treated as
+//! boundary crossings. Bind mounts can share a device with their parent filesystem, so on Linux,
+//! mount points listed in `/proc/self/mountinfo` are treated as crossings as well. On Windows,
+//! volume mount points are detected using `GetVolumeNameForVolumeMountPointW`.
+//!
+//! [`RemoveOptions::one_file_system`]: ../struct.RemoveOptions.html#method.one_file_system
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Set of mount point paths, as listed in `/proc/self/mountinfo`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MountPoints {
+    paths: HashSet<PathBuf>,
+}
+
+impl MountPoints {
+    /// Loads the mount points visible to the current process.
+    ///
+    /// This is only supported on Linux; an empty set is returned on other platforms.
+    pub fn load() -> io::Result<Self> {
+        if cfg!(target_os = "linux") {
+            match fs::read_to_string("/proc/self/mountinfo") {
+                Ok(mountinfo) => return Ok(Self::parse(&mountinfo)),
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(Self::default())
+    }
+
+    /// Parses the contents of a `mountinfo` file, as described in `proc(5)`.
+    pub fn parse(mountinfo: &str) -> Self {
+        Self {
+            paths: mountinfo
+                .lines()
+                .filter_map(|line| line.split(' ').nth(4))
+                .map(|mount_point| PathBuf::from(unescape_mountinfo_field(mount_point)))
+                .collect(),
+        }
+    }
+
+    /// Returns whether `path` (which should be canonical) is a mount point.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(path)
+    }
+
+    /// Returns an iterator over each mount point, in no particular order.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        self.paths.iter().map(PathBuf::as_path)
+    }
+}
+
+/// Decodes the octal escapes (such as `\040` for spaces) used in `mountinfo` fields.
+fn unescape_mountinfo_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|digits| {
+            bytes[i] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit))
+        });
+        match escape {
+            Some(digits) => {
+                unescaped.push(
+                    digits
+                        .iter()
+                        .fold(0u8, |value, digit| (value << 3) | (digit - b'0')),
+                );
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Filesystem boundary of a removal target, used to detect entries residing on other filesystems.
+#[derive(Debug)]
+pub(crate) struct Boundary {
+    /// Removal target path, as provided by the caller.
+    root: PathBuf,
+
+    /// Canonical form of `root`, used to look up mount points.
+    canonical_root: PathBuf,
+
+    /// Device ID of the filesystem containing `root`.
+    #[cfg(unix)]
+    device: u64,
+
+    /// Mount points to treat as boundaries regardless of their device IDs.
+    mount_points: MountPoints,
+}
+
+impl Boundary {
+    /// Records the filesystem boundary of the directory at `root`, using the specified mount
+    /// points to detect bind mounts.
+    pub fn new(root: &Path, mount_points: MountPoints) -> io::Result<Self> {
+        #[cfg(unix)]
+        use std::os::unix::fs::MetadataExt;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            canonical_root: fs::canonicalize(root)?,
+            #[cfg(unix)]
+            device: fs::metadata(root)?.dev(),
+            mount_points,
+        })
+    }
+
+    /// Returns whether the directory at `path`, located within the removal target, resides on a
+    /// different filesystem than the target itself.
+    ///
+    /// `path` must not be a symbolic link.
+    pub fn is_crossed_by_dir(&self, path: &Path) -> io::Result<bool> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            if fs::symlink_metadata(path)?.dev() != self.device {
+                return Ok(true);
+            }
+        }
+
+        // Since symbolic links are never followed, the canonical path of an entry within the
+        // target can be derived from the canonical path of the target itself.
+        let canonical = match path.strip_prefix(&self.root) {
+            Ok(relative) => self.canonical_root.join(relative),
+            Err(_) => fs::canonicalize(path)?,
+        };
+
+        Ok(self.mount_points.contains(&canonical))
+    }
+
+    /// Returns whether the symbolic link or reparse point at `path` is a volume mount point, which
+    /// mounts a separate filesystem.
+    ///
+    /// Volume mount points are reported as directory links by `std::fs` on Windows.
+    #[cfg(windows)]
+    pub fn is_crossed_by_link(&self, path: &Path) -> bool {
+        crate::windows::is_volume_mount_point(path)
+    }
+
+    /// Returns whether the symbolic link at `path` mounts a separate filesystem, which is never the
+    /// case outside of Windows.
+    #[cfg(not(windows))]
+    pub fn is_crossed_by_link(&self, _path: &Path) -> bool {
+        false
+    }
+}
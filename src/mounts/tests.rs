@@ -0,0 +1,34 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mount point parsing tests.
+
+use super::MountPoints;
+use std::path::Path;
+
+/// Tests parsing of mount point paths from `mountinfo` content, including octal escapes.
+#[test]
+fn mountinfo_parsing_works() {
+    let mount_points = MountPoints::parse(concat!(
+        "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n",
+        "36 22 0:31 / /tmp/with\\040space rw - tmpfs none rw\n",
+        "37 22 8:1 /srv /mnt/bind rw,relatime shared:1 - ext4 /dev/sda1 rw\n",
+    ));
+
+    assert!(mount_points.contains(Path::new("/")));
+    assert!(mount_points.contains(Path::new("/tmp/with space")));
+    assert!(mount_points.contains(Path::new("/mnt/bind")));
+    assert!(!mount_points.contains(Path::new("/srv")));
+}
+
+/// Tests whether the mount points of the current process can be loaded.
+#[test]
+#[cfg(target_os = "linux")]
+fn load_works() {
+    assert!(MountPoints::load().unwrap().contains(Path::new("/")));
+}
@@ -0,0 +1,340 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Configuration for [`remove_dir_all_with`](../fn.remove_dir_all_with.html).
+
+use crate::{safeguard::Safeguards, LockMode, ShredOptions, SymlinkPolicy, TombstoneMode};
+use std::{path::PathBuf, time::Duration};
+
+/// Options and flags which can be used to configure how a directory tree is removed.
+///
+/// This follows the same builder conventions as [`std::fs::OpenOptions`]: create a set of options
+/// with [`new`](#method.new), chain calls to configure each option, then pass the result to
+/// [`remove_dir_all_with`](fn.remove_dir_all_with.html).
+///
+/// [`std::fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_dir_all_with, RemoveOptions};
+///
+/// fn main() -> std::io::Result<()> {
+///     // Remove only the build artifacts ignored by Git, like `git clean -fdX`.
+///     let report = remove_dir_all_with("workspace", RemoveOptions::new().gitignored_only(true))?;
+///     println!("Removed {} files.", report.files_removed());
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RemoveOptions {
+    pub(crate) gitignored_only: bool,
+    pub(crate) one_file_system: bool,
+    pub(crate) shred: Option<ShredOptions>,
+    pub(crate) tombstone: Option<TombstoneMode>,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) diagnose_holders: bool,
+    pub(crate) wait_until_gone: Option<Duration>,
+    pub(crate) durable: bool,
+    pub(crate) journal: Option<PathBuf>,
+    pub(crate) lock: Option<LockMode>,
+    pub(crate) lock_dir: Option<PathBuf>,
+    pub(crate) safeguards: Safeguards,
+}
+
+impl RemoveOptions {
+    /// Creates a blank set of options, equivalent to calling `remove_dir_all`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether only entries ignored by Git should be removed, mirroring `git clean -fdX`.
+    ///
+    /// When enabled, the target directory itself is always kept. Ignore rules are loaded from the
+    /// `.gitignore` files in the target and its ancestors up to the enclosing work tree, along with
+    /// `$GIT_COMMON_DIR/info/exclude` (that of the main repository, even in linked work trees).
+    /// Like `git clean`, files listed in the Git index are never removed, `.git` entries are never
+    /// touched, and nested repositories are left alone. The user's global `core.excludesFile` is
+    /// not consulted.
+    pub fn gitignored_only(&mut self, gitignored_only: bool) -> &mut Self {
+        self.gitignored_only = gitignored_only;
+        self
+    }
+
+    /// Sets whether entries residing on a different filesystem than the target should be left in
+    /// place, like `rm --one-file-system`.
+    ///
+    /// Each crossing is reported as [`SkipReason::FileSystemBoundary`], and its ancestors are kept
+    /// as well since they cannot be emptied. On Unix, a directory is considered a crossing if its
+    /// device ID differs from that of the target, or (on Linux) if it is listed as a mount point in
+    /// `/proc/self/mountinfo`, which catches bind mounts of the same filesystem. On Windows,
+    /// volume mount points are treated as crossings. Junctions and symbolic links are never
+    /// followed by default, so only the links themselves are removed; see
+    /// [`symlinks`](#method.symlinks). Links that would be followed to a crossing are left in place
+    /// and reported as crossings themselves.
+    ///
+    /// [`SkipReason::FileSystemBoundary`]: enum.SkipReason.html#variant.FileSystemBoundary
+    pub fn one_file_system(&mut self, one_file_system: bool) -> &mut Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Overwrites the contents of each regular file before removing it, using the given
+    /// [`ShredOptions`](struct.ShredOptions.html).
+    ///
+    /// Each file is overwritten the configured number of times with the configured pattern,
+    /// flushed to the device after every pass, then truncated and unlinked. Files with more than
+    /// one hard link are skipped or refused, so data shared with other paths is never destroyed.
+    /// Shredded files are listed by [`RemovalReport::shredded`].
+    ///
+    /// Overwriting in place is best-effort: copy-on-write and journaling filesystems, SSD wear
+    /// leveling, and snapshots may all retain old copies of the data.
+    ///
+    /// [`RemovalReport::shredded`]: struct.RemovalReport.html#method.shredded
+    pub fn shred(&mut self, options: ShredOptions) -> &mut Self {
+        self.shred = Some(options);
+        self
+    }
+
+    /// Renames the target to a hidden sibling (a tombstone) before removing it, so that its path is
+    /// freed immediately and can be recreated while the removal is still in progress.
+    ///
+    /// The rename happens within the same parent directory, so it is atomic. The tombstone is then
+    /// removed either before returning or on a background thread, depending on the
+    /// [`TombstoneMode`](enum.TombstoneMode.html). Background removals report only the rename,
+    /// and any failure to remove the tombstone is not reported at all. The tombstone path is
+    /// returned by [`RemovalReport::tombstone`].
+    ///
+    /// Tombstones left behind by processes that exited before removing them are detected by the
+    /// process ID recorded in their names, and are removed along with the target whenever a
+    /// sibling directory is removed with this option. This option cannot be combined with
+    /// [`gitignored_only`](#method.gitignored_only), which never removes the target itself.
+    ///
+    /// [`RemovalReport::tombstone`]: struct.RemovalReport.html#method.tombstone
+    pub fn tombstone(&mut self, mode: TombstoneMode) -> &mut Self {
+        self.tombstone = Some(mode);
+        self
+    }
+
+    /// Sets how symbolic links (and junctions on Windows) are handled, including when the target
+    /// itself is a link.
+    ///
+    /// By default, links are removed without following them, using
+    /// [`SymlinkPolicy::RemoveLink`](enum.SymlinkPolicy.html#variant.RemoveLink). Following links
+    /// cannot be combined with [`gitignored_only`](#method.gitignored_only), and the target of a
+    /// root link that is followed is subject to the same protections as the root itself.
+    pub fn symlinks(&mut self, policy: SymlinkPolicy) -> &mut Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sets whether the processes holding entries within the target are identified when removal
+    /// fails because something is in use, which is disabled by default.
+    ///
+    /// When enabled, busy and in-use errors (such as sharing violations on Windows, or `EBUSY` on
+    /// Unix) are replaced by an error of the same kind carrying an [`Error::InUse`], which lists
+    /// each process with an open handle, working directory, or memory mapping within the target.
+    /// Processes are found by scanning `/proc` on Linux and using Restart Manager on Windows,
+    /// which only reports processes holding files open. Holders cannot be identified on other
+    /// platforms, and errors are left unchanged if none are found.
+    ///
+    /// [`Error::InUse`]: enum.Error.html#variant.InUse
+    pub fn diagnose_holders(&mut self, enabled: bool) -> &mut Self {
+        self.diagnose_holders = enabled;
+        self
+    }
+
+    /// Sets whether removals are flushed to the storage device before returning, so that they
+    /// survive a crash or power loss, which is disabled by default.
+    ///
+    /// When enabled, each remaining directory that entries were removed from (including the
+    /// parent of the target, if the target itself was removed) is synced once after every entry
+    /// has been removed, using `fsync` on Unix and `FlushFileBuffers` on Windows. Directories
+    /// removed along the way are not synced, since their own removal is made durable by syncing
+    /// their parent. Any error syncing a directory is returned, even though the entries have been
+    /// removed, as their removal may not survive a crash.
+    pub fn durable(&mut self, durable: bool) -> &mut Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Records the removal in a journal within `dir`, so that it can be finished after being
+    /// interrupted by a failure, crash, or reboot.
+    ///
+    /// The journal directory is created if necessary, and must not be within the target. A small
+    /// journal file recording the target, the options restricting what is removed, and the
+    /// number of entries removed so far is written to it before anything is removed, updated at
+    /// most once per second, and removed once the removal completes. The format is described in
+    /// the documentation of [`resume_pending`], which finishes every interrupted removal
+    /// journaled in a directory. Removing the same target again with the same journal directory
+    /// finishes it as well. Either way, entries removed before the interruption are included in
+    /// the returned report. This option cannot be combined with
+    /// [`tombstone`](#method.tombstone), as stale tombstones are already reclaimed after an
+    /// interruption.
+    ///
+    /// Journals are resumed as deletions of the directories they name, so the journal directory
+    /// must not be writable by untrusted users.
+    ///
+    /// [`resume_pending`]: fn.resume_pending.html
+    pub fn journal<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.journal = Some(dir.into());
+        self
+    }
+
+    /// Takes a cross-process advisory lock on the target before removing it, waiting for other
+    /// removals using this option to release it according to `mode`.
+    ///
+    /// This prevents processes removing the same directory at once from failing with spurious
+    /// not-found or not-empty errors as they remove entries from under each other. The lock is
+    /// keyed by the identity of the target rather than its path, so removals reaching it through
+    /// different paths still exclude each other, and it is held until the removal returns. Locks
+    /// are taken on files using `flock` on Unix and `LockFileEx` on Windows, so they only exclude
+    /// removals using this option. By default, lock files are kept in a directory within the
+    /// system temporary directory that is private to the current user, so only removals by the
+    /// same user exclude each other; see [`lock_dir`](#method.lock_dir). A removal that waits for
+    /// another to remove the target fails with `io::ErrorKind::NotFound`.
+    ///
+    /// If the lock is held, [`LockMode::FailFast`] fails with `io::ErrorKind::WouldBlock`, and
+    /// [`LockMode::Timeout`] fails with `io::ErrorKind::TimedOut` once the timeout elapses, both
+    /// carrying an [`Error::Locked`].
+    ///
+    /// [`LockMode::FailFast`]: enum.LockMode.html#variant.FailFast
+    /// [`LockMode::Timeout`]: enum.LockMode.html#variant.Timeout
+    /// [`Error::Locked`]: enum.Error.html#variant.Locked
+    pub fn lock(&mut self, mode: LockMode) -> &mut Self {
+        self.lock = Some(mode);
+        self
+    }
+
+    /// Sets the directory holding the lock files used by [`lock`](#method.lock), which is created
+    /// if necessary.
+    ///
+    /// Removals only exclude each other if they use the same lock directory, so this allows
+    /// removals by different users to contend for the same locks. Anyone able to create files in
+    /// the directory can hold locks indefinitely or replace lock files, so it must not be writable
+    /// by untrusted users. Lock files are created readable by every user, and are locked through a
+    /// read-only handle if they cannot be opened for writing.
+    pub fn lock_dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.lock_dir = Some(dir.into());
+        self
+    }
+
+    /// Waits up to `timeout` after removing the target for its path to actually disappear,
+    /// returning only once it has.
+    ///
+    /// On Windows, an entry held open by another process when it is removed lingers in a "delete
+    /// pending" state until every handle to it is closed, so creating a new directory at the same
+    /// path immediately afterwards can fail. This polls the target path as
+    /// [`wait_until_gone`](fn.wait_until_gone.html) does, failing with an
+    /// `io::ErrorKind::TimedOut` error carrying an [`Error::StillExists`] if it remains once
+    /// `timeout` elapses. Nothing is waited for if the target itself was not removed.
+    ///
+    /// [`Error::StillExists`]: enum.Error.html#variant.StillExists
+    pub fn wait_until_gone(&mut self, timeout: Duration) -> &mut Self {
+        self.wait_until_gone = Some(timeout);
+        self
+    }
+
+    /// Sets whether the built-in protected paths are refused, which is enabled by default.
+    ///
+    /// The built-in protected paths are filesystem and drive roots, the current user's home or
+    /// profile directory (`$HOME`, or `%USERPROFILE%` on Windows), every directory directly within
+    /// `/home` and `/Users` (or the parent of `%USERPROFILE%` on Windows), and well-known system
+    /// directories such as `/usr` and `/etc`, or `%SystemRoot%` and `%ProgramFiles%` on Windows.
+    /// Any ancestor of a protected path is refused as well, since removing it would remove the
+    /// protected path too.
+    ///
+    /// Refusals are reported before anything is removed, as an `io::ErrorKind::PermissionDenied`
+    /// error carrying an [`Error::ProtectedPath`]. Paths protected using
+    /// [`protect_path`](#method.protect_path) are still refused if this is disabled.
+    ///
+    /// [`Error::ProtectedPath`]: enum.Error.html#variant.ProtectedPath
+    pub fn default_protections(&mut self, enabled: bool) -> &mut Self {
+        self.safeguards.defaults = enabled;
+        self
+    }
+
+    /// Protects an additional path from removal, along with its contents and ancestors.
+    pub fn protect_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.safeguards.denied.push(path.into());
+        self
+    }
+
+    /// Allows a path and its contents to be removed even if they would otherwise be protected.
+    ///
+    /// This takes precedence over both the built-in protected paths and those added using
+    /// [`protect_path`](#method.protect_path). Ancestors of an allowed path are not affected.
+    pub fn allow_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.safeguards.allowed.push(path.into());
+        self
+    }
+
+    /// Sets whether targets containing the current working directory or the running executable are
+    /// refused, which is enabled by default.
+    ///
+    /// Removing the working directory fails partway through on Windows, and removing the directory
+    /// containing the running executable is almost always a bug. Paths are compared after
+    /// resolving `..` components and symbolic links, except for a link being removed itself, which
+    /// is never followed. Unlike the built-in protected paths, this cannot be overridden using
+    /// [`allow_path`](#method.allow_path).
+    pub fn guard_process_paths(&mut self, enabled: bool) -> &mut Self {
+        self.safeguards.process_paths = enabled;
+        self
+    }
+
+    /// Registers a path as in use, refusing the removal of any target that is or contains it.
+    ///
+    /// This is checked the same way as the current working directory and executable (see
+    /// [`guard_process_paths`](#method.guard_process_paths)), but regardless of whether that option
+    /// is enabled.
+    pub fn guard_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.safeguards.in_use.push(path.into());
+        self
+    }
+
+    /// Returns whether each entry in a directory tree must be visited individually by the portable
+    /// removal engine, rather than handing entire subtrees off to the platform backend.
+    #[cfg(windows)]
+    pub(crate) fn needs_entry_walk(&self) -> bool {
+        self.one_file_system || self.shred.is_some() || self.symlinks != SymlinkPolicy::RemoveLink
+    }
+
+    /// Returns whether whole directory trees may be handed off to the platform backend in a single
+    /// batch operation, which honours the safeguards and nothing else.
+    ///
+    /// Every field is listed so that new options are routed through the individual removal path
+    /// unless they are known to be compatible.
+    #[cfg(windows)]
+    pub(crate) fn allows_backend_batch(&self) -> bool {
+        let Self {
+            gitignored_only,
+            one_file_system,
+            shred,
+            tombstone,
+            symlinks,
+            diagnose_holders,
+            wait_until_gone,
+            durable,
+            journal,
+            lock,
+            lock_dir: _,
+            safeguards: _,
+        } = self;
+
+        !gitignored_only
+            && !one_file_system
+            && shred.is_none()
+            && tombstone.is_none()
+            && *symlinks == SymlinkPolicy::RemoveLink
+            && !diagnose_holders
+            && wait_until_gone.is_none()
+            && !durable
+            && journal.is_none()
+            && lock.is_none()
+    }
+}
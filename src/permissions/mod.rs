@@ -0,0 +1,81 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Explanations for entries that could not be removed due to a lack of permission.
+//!
+//! When the removal engine fails to remove an entry with a permission error, the entry and its
+//! parent directory are inspected to work out why, and the [`DenialReason`] found is attached to
+//! the error as [`Error::AccessDenied`]. On Unix, this considers read-only filesystems, the
+//! immutable and append-only attributes (read with `FS_IOC_GETFLAGS` on Linux), the permission
+//! bits of the parent directory, and sticky directories. On Windows, only the read-only attribute
+//! is considered.
+//!
+//! [`DenialReason`]: ../enum.DenialReason.html
+//! [`Error::AccessDenied`]: ../enum.Error.html#variant.AccessDenied
+
+#[cfg(test)]
+mod tests;
+#[cfg(unix)]
+mod unix;
+
+use crate::{DenialReason, Error};
+use std::{io, path::Path};
+
+/// Attaches the reason the entry at `path` could not be removed to `error` if it is a permission
+/// error, returning the resulting error.
+///
+/// `error` is returned unchanged if it has another cause or if no reason could be found.
+pub(crate) fn explain(path: &Path, error: io::Error) -> io::Error {
+    match find_reason(path, &error) {
+        Some(reason) => Error::AccessDenied {
+            path: path.to_path_buf(),
+            reason,
+            source: error,
+        }
+        .into(),
+        None => error,
+    }
+}
+
+/// Returns why the entry at `path` could not be removed, given the error its removal failed with.
+#[cfg(unix)]
+fn find_reason(path: &Path, error: &io::Error) -> Option<DenialReason> {
+    let errno = error.raw_os_error()?;
+    if ![libc::EACCES, libc::EPERM, libc::EROFS].contains(&errno) {
+        return None;
+    }
+    if errno == libc::EROFS {
+        return Some(DenialReason::ReadOnlyFilesystem);
+    }
+
+    unix::find_reason(path, &unix::Credentials::current())
+}
+
+/// Returns why the entry at `path` could not be removed, given the error its removal failed with.
+#[cfg(windows)]
+fn find_reason(path: &Path, error: &io::Error) -> Option<DenialReason> {
+    use std::fs;
+
+    if error.kind() != io::ErrorKind::PermissionDenied {
+        return None;
+    }
+
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if metadata.permissions().readonly() {
+        Some(DenialReason::ReadOnlyAttribute)
+    } else {
+        None
+    }
+}
+
+/// Returns why the entry at `path` could not be removed, which cannot be determined on this
+/// platform.
+#[cfg(not(any(unix, windows)))]
+fn find_reason(_path: &Path, _error: &io::Error) -> Option<DenialReason> {
+    None
+}
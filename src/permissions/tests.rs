@@ -0,0 +1,165 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Permission explanation tests.
+
+#![cfg(unix)]
+
+use super::{
+    explain,
+    unix::{find_reason, Credentials},
+};
+use crate::{engine::tests::create_empty_file, DenialReason, Error};
+use std::{
+    fs::{self, Permissions},
+    io,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+use tempfile::TempDir;
+
+/// Returns credentials for a user other than the owner of `path` and the superuser, belonging to
+/// no groups.
+fn other_user(path: &Path) -> Credentials {
+    let owner = fs::metadata(path).unwrap().uid();
+    Credentials {
+        uid: owner.max(1) + 1000,
+        gids: Vec::new(),
+    }
+}
+
+/// Returns the reason `credentials` cannot remove `file` while its parent has the given mode.
+fn reason_with_mode(file: &Path, mode: u32, credentials: &Credentials) -> Option<DenialReason> {
+    let parent = file.parent().unwrap();
+    fs::set_permissions(parent, Permissions::from_mode(mode)).unwrap();
+    let reason = find_reason(file, credentials);
+    fs::set_permissions(parent, Permissions::from_mode(0o755)).unwrap();
+    reason
+}
+
+/// Tests whether missing write and search permissions on the parent directory are explained.
+#[test]
+fn parent_permissions_are_explained() {
+    let base_dir = TempDir::new().unwrap();
+    let parent = base_dir.path().join("parent");
+    let file = parent.join("file");
+    fs::create_dir(&parent).unwrap();
+    create_empty_file(&file).unwrap();
+    let other = other_user(&parent);
+
+    assert_eq!(
+        reason_with_mode(&file, 0o555, &other),
+        Some(DenialReason::ParentNotWritable)
+    );
+    assert_eq!(
+        reason_with_mode(&file, 0o776, &other),
+        Some(DenialReason::ParentNotSearchable)
+    );
+    assert_eq!(reason_with_mode(&file, 0o777, &other), None);
+
+    let group_member = Credentials {
+        gids: vec![fs::metadata(&parent).unwrap().gid()],
+        ..other.clone()
+    };
+    assert_eq!(reason_with_mode(&file, 0o770, &group_member), None);
+
+    let owner = Credentials {
+        uid: fs::metadata(&parent).unwrap().uid(),
+        gids: Vec::new(),
+    };
+    assert_eq!(reason_with_mode(&file, 0o700, &owner), None);
+
+    let superuser = Credentials {
+        uid: 0,
+        gids: Vec::new(),
+    };
+    assert_eq!(reason_with_mode(&file, 0o555, &superuser), None);
+}
+
+/// Tests whether entries owned by another user in a sticky directory are explained.
+#[test]
+fn sticky_directories_are_explained() {
+    let base_dir = TempDir::new().unwrap();
+    let parent = base_dir.path().join("parent");
+    let file = parent.join("file");
+    fs::create_dir(&parent).unwrap();
+    create_empty_file(&file).unwrap();
+
+    assert_eq!(
+        reason_with_mode(&file, 0o1777, &other_user(&parent)),
+        Some(DenialReason::StickyDirectory)
+    );
+
+    let owner = Credentials {
+        uid: fs::metadata(&file).unwrap().uid(),
+        gids: Vec::new(),
+    };
+    assert_eq!(reason_with_mode(&file, 0o1777, &owner), None);
+}
+
+/// Tests whether explanations are attached to permission errors only, preserving their kind.
+#[test]
+fn explain_works() {
+    let base_dir = TempDir::new().unwrap();
+    let file = base_dir.path().join("file");
+    create_empty_file(&file).unwrap();
+
+    let read_only = io::Error::from_raw_os_error(libc::EROFS);
+    let kind = read_only.kind();
+    let error = explain(&file, read_only);
+    assert_eq!(error.kind(), kind);
+    match Error::from_io_error(&error) {
+        Some(Error::AccessDenied { path, reason, .. }) => {
+            assert_eq!(path, &file);
+            assert_eq!(reason, &DenialReason::ReadOnlyFilesystem);
+        }
+        other => panic!("unexpected error details: {:?}", other),
+    }
+    assert!(error.to_string().contains("read-only filesystem"));
+
+    let error = explain(&file, io::Error::from(io::ErrorKind::NotFound));
+    assert!(Error::from_io_error(&error).is_none());
+}
+
+/// Tests whether removals blocked by the immutable attribute are explained, if the attribute can
+/// be set.
+#[test]
+#[cfg(target_os = "linux")]
+fn immutable_attribute_is_explained() {
+    use std::{fs::File, os::unix::io::AsRawFd};
+
+    const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+
+    let set_flags = |path: &Path, flags: libc::c_int| {
+        let file = File::open(path).unwrap();
+        unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) == 0 }
+    };
+
+    let base_dir = TempDir::new().unwrap();
+    let root = base_dir.path().join("root");
+    let file = root.join("file");
+    fs::create_dir(&root).unwrap();
+    create_empty_file(&file).unwrap();
+
+    // Setting the attribute requires `CAP_LINUX_IMMUTABLE` and filesystem support.
+    if !set_flags(&file, FS_IMMUTABLE_FL) {
+        return;
+    }
+    let result = crate::remove_dir_all(&root);
+    assert!(set_flags(&file, 0));
+
+    let error = result.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    match Error::from_io_error(&error) {
+        Some(Error::AccessDenied { path, reason, .. }) => {
+            assert_eq!(path, &file);
+            assert_eq!(reason, &DenialReason::Immutable { path: file.clone() });
+        }
+        other => panic!("unexpected error details: {:?}", other),
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Permission explanations for Unix platforms.
+
+use crate::DenialReason;
+use std::{
+    ffi::CString,
+    fs::{self, Metadata},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::Path,
+};
+
+/// Write permission bit, for any class of user.
+const WRITE: u32 = 0o2;
+
+/// Execute (search) permission bit, for any class of user.
+const EXECUTE: u32 = 0o1;
+
+/// Sticky bit, restricting removal within a directory to the owners of it and its entries.
+const STICKY: u32 = 0o1000;
+
+/// Identity the permissions of an entry are checked against.
+#[derive(Clone, Debug)]
+pub(super) struct Credentials {
+    pub uid: u32,
+    pub gids: Vec<u32>,
+}
+
+impl Credentials {
+    /// Returns the effective user and group IDs of the current process, along with its
+    /// supplementary group IDs.
+    pub fn current() -> Self {
+        let uid = unsafe { libc::geteuid() };
+        let mut gids = vec![unsafe { libc::getegid() }];
+
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        if count > 0 {
+            let mut groups = vec![0; count as usize];
+            let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+            if count > 0 {
+                groups.truncate(count as usize);
+                gids.extend(groups);
+            }
+        }
+
+        Self { uid, gids }
+    }
+
+    /// Returns whether the given permission bits are granted by `metadata`.
+    ///
+    /// The superuser is assumed to bypass permission checks on directories.
+    fn permits(&self, metadata: &Metadata, bits: u32) -> bool {
+        if self.uid == 0 {
+            return true;
+        }
+
+        let shift = if metadata.uid() == self.uid {
+            6
+        } else if self.gids.contains(&metadata.gid()) {
+            3
+        } else {
+            0
+        };
+        (metadata.mode() >> shift) & bits == bits
+    }
+}
+
+/// Returns why `credentials` could not remove the entry at `path`, if a reason can be found.
+pub(super) fn find_reason(path: &Path, credentials: &Credentials) -> Option<DenialReason> {
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+
+    if is_read_only_filesystem(parent) {
+        return Some(DenialReason::ReadOnlyFilesystem);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        for target in [path, parent].iter() {
+            if let Some(reason) = attribute_reason(target) {
+                return Some(reason);
+            }
+        }
+    }
+
+    let parent_metadata = fs::metadata(parent).ok()?;
+    if !credentials.permits(&parent_metadata, EXECUTE) {
+        return Some(DenialReason::ParentNotSearchable);
+    }
+    if !credentials.permits(&parent_metadata, WRITE) {
+        return Some(DenialReason::ParentNotWritable);
+    }
+
+    if parent_metadata.mode() & STICKY != 0
+        && credentials.uid != 0
+        && parent_metadata.uid() != credentials.uid
+        && fs::symlink_metadata(path)
+            .ok()
+            .map_or(false, |metadata| metadata.uid() != credentials.uid)
+    {
+        return Some(DenialReason::StickyDirectory);
+    }
+
+    None
+}
+
+/// Returns whether the filesystem containing `path` is mounted read-only.
+fn is_read_only_filesystem(path: &Path) -> bool {
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    unsafe { libc::statvfs(path.as_ptr(), &mut stat) == 0 && stat.f_flag & libc::ST_RDONLY != 0 }
+}
+
+/// Returns the reason the immutable or append-only attribute of the entry at `path` prevents
+/// removal, if either is set.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn attribute_reason(path: &Path) -> Option<DenialReason> {
+    use std::{
+        fs::OpenOptions,
+        os::{raw::c_int, unix::fs::OpenOptionsExt, unix::io::AsRawFd},
+    };
+
+    /// Inode flag marking a file as immutable, from `linux/fs.h`.
+    const FS_IMMUTABLE_FL: c_int = 0x10;
+
+    /// Inode flag marking a file as append-only, from `linux/fs.h`.
+    const FS_APPEND_FL: c_int = 0x20;
+
+    // Symbolic links cannot carry attributes, and opening special files may have side effects.
+    let file_type = fs::symlink_metadata(path).ok()?.file_type();
+    if !file_type.is_file() && !file_type.is_dir() {
+        return None;
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+        .ok()?;
+
+    let mut flags: c_int = 0;
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+        return None;
+    }
+
+    if flags & FS_IMMUTABLE_FL != 0 {
+        Some(DenialReason::Immutable {
+            path: path.to_path_buf(),
+        })
+    } else if flags & FS_APPEND_FL != 0 {
+        Some(DenialReason::AppendOnly {
+            path: path.to_path_buf(),
+        })
+    } else {
+        None
+    }
+}
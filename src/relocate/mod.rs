@@ -0,0 +1,240 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Directory moves that fall back to copying when a rename is not possible.
+//!
+//! A move is first attempted as a single rename, which is atomic. If the source and destination
+//! reside on different filesystems, the source is instead copied with [`copy_dir_all`], the copy
+//! is compared against the source, and only then is the source removed using
+//! [`remove_dir_all_with`]. A copy that fails or does not match is removed again, leaving the
+//! source untouched.
+//!
+//! [`copy_dir_all`]: ../fn.copy_dir_all.html
+//! [`remove_dir_all_with`]: ../fn.remove_dir_all_with.html
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    copy, copy_dir_all, remove_dir_all_with, CopyOptions, CopyReport, RemovalReport, RemoveOptions,
+};
+use std::{fs, io, path::Path};
+
+/// How a directory was moved by [`move_dir_all`](fn.move_dir_all.html).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MoveMethod {
+    /// The directory was renamed in a single atomic operation.
+    Renamed,
+
+    /// The directory resided on a different filesystem than the destination, so it was copied,
+    /// verified, and then removed.
+    Copied,
+}
+
+/// Summary of a move performed by [`move_dir_all`](fn.move_dir_all.html).
+#[derive(Debug)]
+pub struct MoveReport {
+    method: MoveMethod,
+    copy: Option<CopyReport>,
+    removal: Option<RemovalReport>,
+}
+
+impl MoveReport {
+    /// Returns how the directory was moved.
+    pub fn method(&self) -> MoveMethod {
+        self.method
+    }
+
+    /// Returns the report of copying the source to the destination, if the directory was copied.
+    pub fn copy_report(&self) -> Option<&CopyReport> {
+        self.copy.as_ref()
+    }
+
+    /// Returns the report of removing the source after it was copied, if the directory was
+    /// copied.
+    pub fn removal_report(&self) -> Option<&RemovalReport> {
+        self.removal.as_ref()
+    }
+}
+
+/// Moves the directory at `source`, along with all its contents, to `destination`, even if they
+/// reside on different filesystems.
+///
+/// The directory is renamed if possible. Otherwise, it is copied using
+/// [`copy_dir_all`](fn.copy_dir_all.html) with links copied as links, the copy is verified
+/// against the source (comparing the type of every entry, the size of every file, and the target
+/// of every link), and the source is removed using
+/// [`remove_dir_all_with`](fn.remove_dir_all_with.html). The returned
+/// [`MoveReport`](struct.MoveReport.html) records which [`MoveMethod`](enum.MoveMethod.html) was
+/// used. If `source` is a link to a directory, the link itself is moved.
+///
+/// Any missing parents of `destination` are created, but `destination` itself must not exist.
+///
+/// # Platform-specific behavior
+///
+/// On Windows, the fallback copies and removes entries individually rather than using
+/// `IFileOperation::MoveItem`, since the Shell copies the targets of links instead of the links
+/// themselves and does not allow the copy to be verified before the source is removed.
+///
+/// # Errors
+///
+/// Returns an error if `source` is not a directory or directory symlink, if it is protected from
+/// removal (see [`remove_dir_all`](fn.remove_dir_all.html)), if `destination` already exists, or
+/// if the directory could not be moved. If copying or verification fails, the partial copy is
+/// removed and the source is left in place. If the source cannot be removed after being copied,
+/// the destination is complete, but the source may be partially removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{move_dir_all, MoveMethod};
+///
+/// fn main() -> std::io::Result<()> {
+///     let report = move_dir_all("/tmp/build-output", "/home/user/artifacts")?;
+///     if report.method() == MoveMethod::Copied {
+///         println!("Moved across filesystems.");
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn move_dir_all<P, Q>(source: P, destination: Q) -> io::Result<MoveReport>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let source = source.as_ref();
+    let destination = destination.as_ref();
+    let remove_options = RemoveOptions::new();
+    remove_options.safeguards.check(source)?;
+
+    let file_type = fs::symlink_metadata(source)?.file_type();
+    if !fs::metadata(source)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Source is not a directory or directory symlink.",
+        ));
+    }
+
+    if fs::symlink_metadata(destination).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Destination `{}` already exists.", destination.display()),
+        ));
+    }
+
+    if let Some(parent) = destination.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    match fs::rename(source, destination) {
+        Ok(()) => {
+            return Ok(MoveReport {
+                method: MoveMethod::Renamed,
+                copy: None,
+                removal: None,
+            })
+        }
+        Err(ref error) if is_cross_device(error) => {}
+        Err(error) => return Err(error),
+    }
+
+    if file_type.is_symlink() {
+        copy::create_link(source, destination)?;
+        let removal = remove_dir_all_with(source, &remove_options)?;
+        return Ok(MoveReport {
+            method: MoveMethod::Copied,
+            copy: None,
+            removal: Some(removal),
+        });
+    }
+
+    let copied = copy_dir_all(source, destination, &CopyOptions::new())
+        .and_then(|report| verify_copy(source, destination).map(|_| report));
+    let copy_report = match copied {
+        Ok(report) => report,
+        Err(error) => {
+            // The destination did not exist beforehand, so anything there is part of the copy.
+            let _ = remove_dir_all_with(destination, &remove_options);
+            return Err(error);
+        }
+    };
+
+    let removal = remove_dir_all_with(source, &remove_options)?;
+    Ok(MoveReport {
+        method: MoveMethod::Copied,
+        copy: Some(copy_report),
+        removal: Some(removal),
+    })
+}
+
+/// Returns whether `error` was caused by renaming across filesystems.
+#[cfg(unix)]
+fn is_cross_device(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(libc::EXDEV)
+}
+
+/// Returns whether `error` was caused by renaming across volumes.
+#[cfg(windows)]
+fn is_cross_device(error: &io::Error) -> bool {
+    use winapi::shared::winerror::ERROR_NOT_SAME_DEVICE;
+
+    error.raw_os_error() == Some(ERROR_NOT_SAME_DEVICE as i32)
+}
+
+/// Checks that the tree at `destination` matches the tree at `source`, comparing the type of
+/// every entry, the size of every file, and the target of every link.
+fn verify_copy(source: &Path, destination: &Path) -> io::Result<()> {
+    let mismatch = |path: &Path| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Copied entry `{}` does not match its source.",
+                path.display()
+            ),
+        )
+    };
+
+    let mut expected = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let copy_path = destination.join(entry.file_name());
+        let source_metadata = entry.metadata()?;
+        let copy_metadata = match fs::symlink_metadata(&copy_path) {
+            Ok(metadata) => metadata,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(mismatch(&copy_path))
+            }
+            Err(error) => return Err(error),
+        };
+
+        let (source_type, copy_type) = (source_metadata.file_type(), copy_metadata.file_type());
+        if source_type.is_symlink() {
+            if !copy_type.is_symlink() || fs::read_link(entry.path())? != fs::read_link(&copy_path)?
+            {
+                return Err(mismatch(&copy_path));
+            }
+        } else if source_type.is_dir() {
+            if !copy_type.is_dir() {
+                return Err(mismatch(&copy_path));
+            }
+            verify_copy(&entry.path(), &copy_path)?;
+        } else if !copy_type.is_file() || source_metadata.len() != copy_metadata.len() {
+            return Err(mismatch(&copy_path));
+        }
+
+        expected += 1;
+    }
+
+    if fs::read_dir(destination)?.count() != expected {
+        return Err(mismatch(destination));
+    }
+
+    Ok(())
+}
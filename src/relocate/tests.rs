@@ -0,0 +1,140 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Directory move tests.
+
+use super::{move_dir_all, verify_copy, MoveMethod};
+use crate::engine::tests::populate_dir;
+use std::{fs, io, path::Path};
+use tempfile::TempDir;
+
+/// Creates a directory at `path` containing a few entries, with some file contents.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)?;
+    fs::write(path.join("baz").join("data"), b"contents")
+}
+
+/// Tests whether moves within a filesystem are performed as a rename.
+#[test]
+fn rename_works() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    let destination = base_dir.path().join("nested").join("destination");
+    create_tree(&source).unwrap();
+
+    let report = move_dir_all(&source, &destination).unwrap();
+    assert_eq!(report.method(), MoveMethod::Renamed);
+    assert!(report.copy_report().is_none());
+    assert!(!source.exists());
+    assert_eq!(
+        fs::read(destination.join("baz").join("data")).unwrap(),
+        b"contents"
+    );
+}
+
+/// Tests whether moves from a tmpfs mount to another filesystem fall back to copying, preserving
+/// file contents and links, and remove the source afterwards.
+#[test]
+#[cfg(target_os = "linux")]
+fn cross_device_move_works() {
+    use std::os::unix::fs::{symlink, MetadataExt};
+
+    let tmpfs_dir = match TempDir::new_in("/dev/shm") {
+        Ok(dir) => dir,
+        Err(_) => return,
+    };
+    let base_dir = TempDir::new().unwrap();
+    if fs::metadata(tmpfs_dir.path()).unwrap().dev() == fs::metadata(base_dir.path()).unwrap().dev()
+    {
+        return;
+    }
+
+    let source = tmpfs_dir.path().join("source");
+    let destination = base_dir.path().join("destination");
+    create_tree(&source).unwrap();
+    symlink("baz/data", source.join("link")).unwrap();
+
+    let report = move_dir_all(&source, &destination).unwrap();
+    assert_eq!(report.method(), MoveMethod::Copied);
+    let copy_report = report.copy_report().unwrap();
+    assert_eq!(copy_report.files_copied(), 4);
+    assert_eq!(copy_report.symlinks_copied(), 1);
+    assert!(report.removal_report().unwrap().root_removed());
+
+    assert!(fs::symlink_metadata(&source).is_err());
+    assert_eq!(
+        fs::read_link(destination.join("link")).unwrap(),
+        Path::new("baz/data")
+    );
+    assert_eq!(fs::read(destination.join("link")).unwrap(), b"contents");
+
+    // Links to directories are moved as links.
+    let link_source = tmpfs_dir.path().join("dir-link");
+    let link_destination = base_dir.path().join("dir-link");
+    symlink(&destination, &link_source).unwrap();
+    let report = move_dir_all(&link_source, &link_destination).unwrap();
+    assert_eq!(report.method(), MoveMethod::Copied);
+    assert!(fs::symlink_metadata(&link_source).is_err());
+    assert_eq!(fs::read_link(&link_destination).unwrap(), destination);
+    assert!(destination.join("foo").is_file());
+}
+
+/// Tests whether copies that differ from their source fail verification.
+#[test]
+fn verification_detects_mismatches() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    let copy = base_dir.path().join("copy");
+    create_tree(&source).unwrap();
+    create_tree(&copy).unwrap();
+    verify_copy(&source, &copy).unwrap();
+
+    fs::write(copy.join("baz").join("data"), b"truncated").unwrap();
+    assert_eq!(
+        verify_copy(&source, &copy).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+
+    fs::write(copy.join("baz").join("data"), b"contents").unwrap();
+    fs::write(copy.join("extra"), b"").unwrap();
+    assert_eq!(
+        verify_copy(&source, &copy).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+
+    fs::remove_file(copy.join("extra")).unwrap();
+    fs::remove_file(copy.join("foo")).unwrap();
+    assert_eq!(
+        verify_copy(&source, &copy).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+/// Tests whether existing destinations and non-directory sources are refused, leaving the source
+/// in place.
+#[test]
+fn invalid_moves_fail() {
+    let base_dir = TempDir::new().unwrap();
+    let source = base_dir.path().join("source");
+    let destination = base_dir.path().join("destination");
+    create_tree(&source).unwrap();
+    fs::create_dir(&destination).unwrap();
+
+    assert_eq!(
+        move_dir_all(&source, &destination).unwrap_err().kind(),
+        io::ErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        move_dir_all(source.join("foo"), base_dir.path().join("file"))
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert!(source.join("baz").join("data").is_file());
+}
@@ -0,0 +1,241 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Configurable removal entry points.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    background::Monitor, engine::Engine, gitignore, holders, journal::Journal, lock, symlinks,
+    tombstone, wait, RemovalReport, RemoveOptions,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Removes a directory at this path, after removing all its contents. Use
+/// carefully!
+///
+/// This function does **not** follow symbolic links and it will simply remove the
+/// symbolic link itself.
+///
+/// Filesystem roots, home directories, and well-known system directories (along with their
+/// ancestors) are refused before anything is removed; use
+/// [`remove_dir_all_with`](fn.remove_dir_all_with.html) and
+/// [`RemoveOptions`](struct.RemoveOptions.html) to configure these protections.
+///
+/// # Platform-specific behavior
+///
+/// This function currently corresponds to `opendir`, `lstat`, `rm` and `rmdir` functions on Unix,
+/// and either the `SHFileOperation` function or the `IFileOperation` COM interface on Windows
+/// depending on the Windows version used at runtime.
+/// Note that, this [may change in the future][changes].
+///
+/// [changes]: https://doc.rust-lang.org/std/io/index.html#platform-specific-behavior
+///
+/// # Errors
+///
+/// See [`fs::remove_file`] and [`fs::remove_dir`]. Targets that are neither directories nor
+/// directory symlinks are rejected with `io::ErrorKind::InvalidData`, and protected paths are
+/// rejected with `io::ErrorKind::PermissionDenied` (see [`Error::ProtectedPath`]).
+///
+/// [`fs::remove_file`]: https://doc.rust-lang.org/std/fs/fn.remove_file.html
+/// [`fs::remove_dir`]: https://doc.rust-lang.org/std/fs/fn.remove_dir.html
+/// [`Error::ProtectedPath`]: enum.Error.html#variant.ProtectedPath
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::remove_dir_all;
+///
+/// fn main() -> std::io::Result<()> {
+///     remove_dir_all("/some/dir")?;
+///     Ok(())
+/// }
+/// ```
+pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    remove_dir_all_with(path, &RemoveOptions::new()).map(|_| ())
+}
+
+/// Removes a directory at this path, after removing all its contents, using the specified options.
+///
+/// With default options, this behaves like [`remove_dir_all`](fn.remove_dir_all.html), using the
+/// Windows Shell or Property System APIs on Windows where possible and the portable `std::fs`-based
+/// engine elsewhere. A [`RemovalReport`](struct.RemovalReport.html) summarizing what was removed or
+/// deliberately skipped is returned on success.
+///
+/// Other processes may create or remove entries within the directory while it is being removed.
+/// Entries that disappear before they can be removed are treated as removed, although they are
+/// not counted in the report. A directory that is refilled after its contents were removed is
+/// re-scanned, up to 5 times in total, before the removal fails with an error of the same kind as
+/// the final attempt carrying an [`Error::KeptChanging`]. Directory trees handed off to the
+/// Windows Shell or Property System backends are not re-scanned.
+///
+/// [`Error::KeptChanging`]: enum.Error.html#variant.KeptChanging
+///
+/// # Errors
+///
+/// Returns an error if the target does not exist, is not a directory or directory symlink, is
+/// protected (see [`RemoveOptions::default_protections`]), or if any entry could not be removed.
+/// Entries removed before the failure are not restored.
+///
+/// [`RemoveOptions::default_protections`]: struct.RemoveOptions.html#method.default_protections
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_dir_all_with, RemoveOptions};
+///
+/// fn main() -> std::io::Result<()> {
+///     let report = remove_dir_all_with("/some/dir", &RemoveOptions::new())?;
+///     assert!(report.root_removed());
+///     Ok(())
+/// }
+/// ```
+pub fn remove_dir_all_with<P: AsRef<Path>>(
+    path: P,
+    options: &RemoveOptions,
+) -> io::Result<RemovalReport> {
+    remove(path.as_ref(), options, None)
+}
+
+/// Removes a directory at this path, after removing all its contents, unless nothing exists at the
+/// path.
+///
+/// This behaves like [`remove_dir_all`](fn.remove_dir_all.html), except that a missing target
+/// (including one removed by another process before this could) is treated as success rather
+/// than an `io::ErrorKind::NotFound` error. Returns whether the target existed.
+///
+/// # Errors
+///
+/// See [`remove_dir_all`](fn.remove_dir_all.html).
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::remove_dir_all_if_exists;
+///
+/// fn main() -> std::io::Result<()> {
+///     if !remove_dir_all_if_exists("target/cache")? {
+///         println!("Nothing to clean.");
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn remove_dir_all_if_exists<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let path = path.as_ref();
+    match remove_dir_all(path) {
+        Ok(()) => Ok(true),
+        Err(ref error)
+            if error.kind() == io::ErrorKind::NotFound
+                && fs::symlink_metadata(path)
+                    .err()
+                    .map_or(false, |error| error.kind() == io::ErrorKind::NotFound) =>
+        {
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Removes the directory at `path` using the specified options, publishing progress to `monitor`
+/// if provided.
+pub(crate) fn remove(
+    path: &Path,
+    options: &RemoveOptions,
+    monitor: Option<&Arc<Monitor>>,
+) -> io::Result<RemovalReport> {
+    options.safeguards.check(path)?;
+    remove_checked(path, options, monitor)
+}
+
+/// Removes the directory at `path`, which has already passed the safeguards, using the specified
+/// options, publishing progress to `monitor` if provided.
+pub(crate) fn remove_checked(
+    path: &Path,
+    options: &RemoveOptions,
+    monitor: Option<&Arc<Monitor>>,
+) -> io::Result<RemovalReport> {
+    symlinks::check_root(path, options)?;
+    run_removal(path, options, || match options.tombstone {
+        Some(mode) => tombstone::remove(path, options, mode, monitor),
+        None => (remove_in_place(path, options, monitor), None),
+    })
+}
+
+/// Removes the entry at `path` using `remove_entry`, while holding the lock requested by the
+/// options, then diagnoses holders of whatever remains on failure or waits for the entry to
+/// disappear on success, if requested.
+///
+/// `remove_entry` returns the path of the tombstone the entry was renamed to, if any, alongside its
+/// result, since the remaining entries are found there rather than at `path`.
+pub(crate) fn run_removal<F>(
+    path: &Path,
+    options: &RemoveOptions,
+    remove_entry: F,
+) -> io::Result<RemovalReport>
+where
+    F: FnOnce() -> (io::Result<RemovalReport>, Option<PathBuf>),
+{
+    let _lock = match options.lock {
+        Some(mode) => Some(lock::acquire(
+            path,
+            mode,
+            options.lock_dir.as_ref().map(PathBuf::as_path),
+        )?),
+        None => None,
+    };
+
+    let (result, tombstone) = remove_entry();
+    let report = if options.diagnose_holders {
+        // Whatever is left of a tombstoned target is no longer at its original path.
+        let remaining = tombstone.as_ref().map_or(path, PathBuf::as_path);
+        result.map_err(|error| holders::diagnose(remaining, error))?
+    } else {
+        result?
+    };
+
+    if let Some(timeout) = options.wait_until_gone {
+        if report.root_removed {
+            wait::wait_until_gone(path, timeout)?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Removes the directory at `path` without renaming it first, publishing progress to `monitor` if
+/// provided.
+fn remove_in_place(
+    path: &Path,
+    options: &RemoveOptions,
+    monitor: Option<&Arc<Monitor>>,
+) -> io::Result<RemovalReport> {
+    let mut engine = Engine::new(options).with_monitor(monitor.cloned());
+    if let Some(ref dir) = options.journal {
+        // Nothing is journaled for targets that don't exist.
+        fs::symlink_metadata(path)?;
+        engine = engine.with_journal(Journal::begin(dir, path, options)?);
+    }
+
+    let result = if options.gitignored_only {
+        gitignore::clean(path, &mut engine)
+    } else {
+        engine.remove_root(path)
+    };
+    if let Err(error) = result {
+        // The journal is left in place, recording what was removed, so the removal can be resumed.
+        let _ = engine.flush_journal();
+        return Err(error);
+    }
+
+    engine.finish()
+}
@@ -0,0 +1,35 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal entry point tests.
+
+use super::remove_dir_all_if_exists;
+use crate::engine::tests::{create_empty_file, populate_dir};
+use std::{fs, io};
+use tempfile::TempDir;
+
+/// Tests whether `remove_dir_all_if_exists` treats a missing target as success.
+#[test]
+fn remove_dir_all_if_exists_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    assert!(remove_dir_all_if_exists(&dir_path).unwrap());
+    assert!(!dir_path.exists());
+    assert!(!remove_dir_all_if_exists(&dir_path).unwrap());
+    assert!(!remove_dir_all_if_exists(dir_path.join("child")).unwrap());
+
+    let file_path = base_dir.path().join("file");
+    create_empty_file(&file_path).unwrap();
+    assert_eq!(
+        remove_dir_all_if_exists(&file_path).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
@@ -0,0 +1,198 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Replacement of a directory by swapping a new tree into its place.
+//!
+//! On Linux, the two trees are exchanged in a single `renameat2` call with `RENAME_EXCHANGE`, so
+//! the target path always refers to either the old or the new tree. Elsewhere, and on filesystems
+//! that do not support exchanging entries, the old tree is first renamed to a tombstone beside the
+//! target and the new tree is then renamed into place, with the tombstone renamed back if the
+//! second rename fails. Either way, the old tree is then removed by the usual removal machinery.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{engine::is_dir_symlink, remove, tombstone, RemovalReport, RemoveOptions};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Replaces the directory at `target` with the directory at `new`, then removes the previous
+/// contents of `target`.
+///
+/// This is equivalent to calling [`replace_dir_with`](fn.replace_dir_with.html) with the default
+/// set of options.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::replace_dir;
+///
+/// fn main() -> std::io::Result<()> {
+///     // Deploy a freshly built site without the served directory ever going missing.
+///     replace_dir("public.staging", "public")?;
+///     Ok(())
+/// }
+/// ```
+pub fn replace_dir<P, Q>(new: P, target: Q) -> io::Result<RemovalReport>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    replace_dir_with(new, target, &RemoveOptions::new())
+}
+
+/// Replaces the directory at `target` with the directory at `new`, then removes the previous
+/// contents of `target` using the specified options.
+///
+/// The new tree is moved into place with renames, so `new` and `target` must reside on the same
+/// filesystem, and `new` no longer exists once this returns successfully. The returned
+/// [`RemovalReport`](struct.RemovalReport.html) describes the removal of the old tree. If nothing
+/// exists at `target`, `new` is simply renamed to it and an empty report is returned.
+///
+/// The old tree is removed as if by [`remove_dir_all_with`](fn.remove_dir_all_with.html), so
+/// setting [`RemoveOptions::tombstone`] with
+/// [`TombstoneMode::Background`](enum.TombstoneMode.html#variant.Background) removes it on a
+/// background thread, returning as soon as the new tree is in place.
+///
+/// # Platform-specific behavior
+///
+/// On Linux, the trees are exchanged atomically using `renameat2` with `RENAME_EXCHANGE`, so there
+/// is no moment at which `target` does not exist. If the kernel or filesystem does not support
+/// exchanging entries, as well as on all other platforms (including Windows, which has no
+/// equivalent for directories), the old tree is renamed to a hidden tombstone beside `target`
+/// before the new tree is renamed into place, leaving `target` briefly missing between the two
+/// renames. If the second rename fails, the tombstone is renamed back to `target`.
+///
+/// # Errors
+///
+/// Returns an error if `new` is not a directory, if `target` exists but is not a directory or
+/// directory symlink, if `target` is protected from removal (see
+/// [`remove_dir_all`](fn.remove_dir_all.html)), or if the trees could not be swapped, in which
+/// case `target` is left as it was. If the old tree cannot be removed once the new tree is in
+/// place, the error from removing it is returned, and whatever remains of it is left at `new` or
+/// at a tombstone beside `target`.
+///
+/// [`RemoveOptions::tombstone`]: struct.RemoveOptions.html#method.tombstone
+pub fn replace_dir_with<P, Q>(
+    new: P,
+    target: Q,
+    options: &RemoveOptions,
+) -> io::Result<RemovalReport>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let new = new.as_ref();
+    let target = target.as_ref();
+    options.safeguards.check(target)?;
+
+    if !fs::symlink_metadata(new)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Replacement is not a directory.",
+        ));
+    }
+
+    match fs::symlink_metadata(target) {
+        Ok(metadata) => {
+            let file_type = metadata.file_type();
+            if !file_type.is_dir() && !is_dir_symlink(target, file_type) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Target is not a directory or directory symlink.",
+                ));
+            }
+        }
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+            fs::rename(new, target)?;
+            return Ok(RemovalReport::default());
+        }
+        Err(error) => return Err(error),
+    }
+
+    let old = swap(new, target)?;
+    remove::remove(&old, options, None)
+}
+
+/// Swaps the directory at `new` into place at `target`, returning the path the old tree now
+/// resides at.
+fn swap(new: &Path, target: &Path) -> io::Result<PathBuf> {
+    #[cfg(target_os = "linux")]
+    {
+        match exchange(new, target) {
+            Ok(()) => return Ok(new.to_path_buf()),
+            // `renameat2` is either unsupported by the kernel or `RENAME_EXCHANGE` is unsupported
+            // by the filesystem, so fall back to renaming each tree in turn.
+            Err(ref error)
+                if [libc::ENOSYS, libc::EINVAL].contains(&error.raw_os_error().unwrap_or(0)) => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    swap_by_renaming(new, target)
+}
+
+/// Atomically exchanges the entries at `a` and `b` using `renameat2` with `RENAME_EXCHANGE`.
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let to_cstring = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Path contains an interior nul byte.",
+            )
+        })
+    };
+    let (a, b) = (to_cstring(a)?, to_cstring(b)?);
+
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Swaps the directory at `new` into place at `target` by renaming the old tree to a tombstone
+/// and then renaming the new tree to `target`, returning the path of the tombstone.
+///
+/// If the new tree cannot be renamed, the tombstone is renamed back to `target` and the original
+/// error is returned.
+fn swap_by_renaming(new: &Path, target: &Path) -> io::Result<PathBuf> {
+    let old = tombstone::rename_to_tombstone(target)?;
+
+    match fs::rename(new, target) {
+        Ok(()) => Ok(old),
+        Err(error) => match fs::rename(&old, target) {
+            Ok(()) => Err(error),
+            Err(restore_error) => Err(io::Error::new(
+                error.kind(),
+                format!(
+                    "Failed to move the replacement into place ({}), and the original directory \
+                     could not be restored from `{}` ({}).",
+                    error,
+                    old.display(),
+                    restore_error
+                ),
+            )),
+        },
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Directory replacement tests.
+
+use super::{replace_dir, replace_dir_with, swap_by_renaming};
+use crate::{engine::tests::populate_dir, RemoveOptions, TombstoneMode};
+use std::{fs, io, path::Path, thread, time::Duration};
+use tempfile::TempDir;
+
+/// Creates a directory at `path` containing a few entries, with `version` written to a file.
+fn create_tree(path: &Path, version: &str) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)?;
+    fs::write(path.join("version"), version)
+}
+
+/// Returns the number of entries within `path`.
+fn entry_count(path: &Path) -> usize {
+    fs::read_dir(path).unwrap().count()
+}
+
+/// Tests whether the new tree takes the place of the target and the old tree is removed.
+#[test]
+fn replace_dir_works() {
+    let base_dir = TempDir::new().unwrap();
+    let new = base_dir.path().join("new");
+    let target = base_dir.path().join("target");
+    create_tree(&new, "2").unwrap();
+    create_tree(&target, "1").unwrap();
+    fs::write(target.join("stale"), b"").unwrap();
+
+    let report = replace_dir(&new, &target).unwrap();
+    assert!(report.root_removed());
+    assert!(report.files_removed() > 0);
+    assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "2");
+    assert!(!target.join("stale").exists());
+    assert!(!new.exists());
+    assert_eq!(entry_count(base_dir.path()), 1);
+}
+
+/// Tests whether a missing target is simply replaced by renaming the new tree.
+#[test]
+fn missing_target_is_renamed() {
+    let base_dir = TempDir::new().unwrap();
+    let new = base_dir.path().join("new");
+    let target = base_dir.path().join("target");
+    create_tree(&new, "1").unwrap();
+
+    let report = replace_dir(&new, &target).unwrap();
+    assert!(!report.root_removed());
+    assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "1");
+    assert!(!new.exists());
+}
+
+/// Tests whether the old tree is restored if the new tree cannot be renamed into place.
+#[test]
+fn failed_swap_is_rolled_back() {
+    let base_dir = TempDir::new().unwrap();
+    let new = base_dir.path().join("new");
+    let target = base_dir.path().join("target");
+    create_tree(&target, "1").unwrap();
+
+    let error = swap_by_renaming(&new, &target).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "1");
+    assert_eq!(entry_count(base_dir.path()), 1);
+}
+
+/// Tests whether the fallback rename sequence moves the old tree to a tombstone.
+#[test]
+fn swap_by_renaming_works() {
+    let base_dir = TempDir::new().unwrap();
+    let new = base_dir.path().join("new");
+    let target = base_dir.path().join("target");
+    create_tree(&new, "2").unwrap();
+    create_tree(&target, "1").unwrap();
+
+    let old = swap_by_renaming(&new, &target).unwrap();
+    assert_eq!(old.parent(), Some(base_dir.path()));
+    assert_eq!(fs::read_to_string(old.join("version")).unwrap(), "1");
+    assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "2");
+    assert!(!new.exists());
+}
+
+/// Tests whether the old tree can be removed on a background thread.
+#[test]
+fn background_removal_works() {
+    let base_dir = TempDir::new().unwrap();
+    let new = base_dir.path().join("new");
+    let target = base_dir.path().join("target");
+    create_tree(&new, "2").unwrap();
+    create_tree(&target, "1").unwrap();
+
+    let report = replace_dir_with(
+        &new,
+        &target,
+        RemoveOptions::new().tombstone(TombstoneMode::Background),
+    )
+    .unwrap();
+    assert_eq!(fs::read_to_string(target.join("version")).unwrap(), "2");
+
+    let tombstone = report.tombstone().unwrap();
+    for _ in 0..100 {
+        if !tombstone.exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!tombstone.exists());
+    assert!(!new.exists());
+}
+
+/// Tests whether replacements involving files or protected targets are refused without touching
+/// either path.
+#[test]
+fn invalid_replacements_are_refused() {
+    let base_dir = TempDir::new().unwrap();
+    let new = base_dir.path().join("new");
+    let file = base_dir.path().join("file");
+    create_tree(&new, "1").unwrap();
+    fs::write(&file, b"contents").unwrap();
+
+    let error = replace_dir(&file, &new).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let error = replace_dir(&new, &file).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fs::read(&file).unwrap(), b"contents");
+
+    let root = base_dir.path().ancestors().last().unwrap();
+    let error = replace_dir(&new, root).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    assert!(new.join("version").exists());
+}
@@ -0,0 +1,147 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Summary of the work performed by a removal operation.
+
+use std::path::{Path, PathBuf};
+
+/// Summary of the entries removed and skipped by
+/// [`remove_dir_all_with`](fn.remove_dir_all_with.html).
+///
+/// Entries removed through the Windows Shell or Property System APIs cannot be counted
+/// individually, so each directory tree handed off to one of those backends is counted as a single
+/// removed directory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RemovalReport {
+    pub(crate) files_removed: u64,
+    pub(crate) dirs_removed: u64,
+    pub(crate) symlinks_removed: u64,
+    pub(crate) root_removed: bool,
+    pub(crate) skipped: Vec<SkippedEntry>,
+    pub(crate) shredded: Vec<PathBuf>,
+    pub(crate) tombstone: Option<PathBuf>,
+    pub(crate) reclaimed: Vec<PathBuf>,
+    pub(crate) entry_kind: Option<EntryKind>,
+}
+
+impl RemovalReport {
+    /// Returns the number of regular files removed.
+    pub fn files_removed(&self) -> u64 {
+        self.files_removed
+    }
+
+    /// Returns the number of directories removed, including the target directory itself.
+    pub fn dirs_removed(&self) -> u64 {
+        self.dirs_removed
+    }
+
+    /// Returns the number of symbolic links (and junctions on Windows) removed. Link targets are
+    /// never removed.
+    pub fn symlinks_removed(&self) -> u64 {
+        self.symlinks_removed
+    }
+
+    /// Returns whether the target directory itself was removed.
+    pub fn root_removed(&self) -> bool {
+        self.root_removed
+    }
+
+    /// Returns the entries that were deliberately left in place, along with the reason for each.
+    pub fn skipped(&self) -> &[SkippedEntry] {
+        &self.skipped
+    }
+
+    /// Returns the paths of the files that were overwritten before being removed, if shredding was
+    /// enabled using [`RemoveOptions::shred`]. Shredded files are counted by
+    /// [`files_removed`](#method.files_removed) as well.
+    ///
+    /// [`RemoveOptions::shred`]: struct.RemoveOptions.html#method.shred
+    pub fn shredded(&self) -> &[PathBuf] {
+        &self.shredded
+    }
+
+    /// Returns the path the target was renamed to before being removed, if tombstones were enabled
+    /// using [`RemoveOptions::tombstone`]. Paths of skipped and shredded entries are located within
+    /// the tombstone rather than the original target.
+    ///
+    /// [`RemoveOptions::tombstone`]: struct.RemoveOptions.html#method.tombstone
+    pub fn tombstone(&self) -> Option<&Path> {
+        self.tombstone.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Returns the stale tombstones left behind by interrupted removals in other processes that
+    /// were removed along with the target. Entries within them are not counted.
+    pub fn reclaimed(&self) -> &[PathBuf] {
+        &self.reclaimed
+    }
+
+    /// Returns the kind of entry found at the target path, if it was removed using
+    /// [`remove_path_all`](fn.remove_path_all.html) or
+    /// [`remove_path_all_with`](fn.remove_path_all_with.html).
+    pub fn entry_kind(&self) -> Option<EntryKind> {
+        self.entry_kind
+    }
+
+    /// Records an entry that was deliberately left in place.
+    pub(crate) fn skip(&mut self, path: PathBuf, reason: SkipReason) {
+        self.skipped.push(SkippedEntry { path, reason });
+    }
+}
+
+/// Kind of entry found at the target path of a removal operation.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EntryKind {
+    /// A regular file, or any other entry that is neither a directory nor a symbolic link, such as
+    /// a FIFO or socket.
+    File,
+
+    /// A symbolic link to a file, or a dangling symbolic link on platforms that don't distinguish
+    /// between file and directory links. The link target is never removed.
+    FileSymlink,
+
+    /// A symbolic link to a directory, or a junction on Windows. The link target is never removed.
+    DirSymlink,
+
+    /// A directory, which is removed along with all its contents.
+    Directory,
+}
+
+/// Entry deliberately left in place during a removal operation.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SkippedEntry {
+    path: PathBuf,
+    reason: SkipReason,
+}
+
+impl SkippedEntry {
+    /// Returns the path of the entry that was left in place.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns why the entry was left in place.
+    pub fn reason(&self) -> SkipReason {
+        self.reason
+    }
+}
+
+/// Reason an entry was deliberately left in place.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SkipReason {
+    /// The entry matches an ignore rule but is tracked in the Git index.
+    Tracked,
+
+    /// The entry is an ignored directory containing a separate Git repository.
+    NestedRepository,
+
+    /// The entry resides on a different filesystem than the target.
+    FileSystemBoundary,
+
+    /// The entry is a file with other hard links, which is never shredded.
+    HardLinked,
+}
@@ -0,0 +1,344 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Protected-path safeguards, checked before any removal backend is invoked.
+//!
+//! By default, filesystem and drive roots, home directories, and well-known system directories are
+//! protected, along with every ancestor of a protected path. Callers can protect additional paths
+//! (along with everything inside them) or explicitly allow paths that would otherwise be protected.
+//!
+//! Separately, targets containing the current working directory, the running executable, or any
+//! path registered as in use by the caller are refused, since removing them is almost always a bug
+//! (and on Windows, removing the working directory fails partway through).
+
+#[cfg(test)]
+mod tests;
+
+use crate::{Error, ProtectionReason};
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Well-known system directories protected by default on Unix platforms.
+#[cfg(unix)]
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/media",
+    "/mnt",
+    "/opt",
+    "/proc",
+    "/root",
+    "/run",
+    "/sbin",
+    "/srv",
+    "/sys",
+    "/tmp",
+    "/usr",
+    "/usr/bin",
+    "/usr/lib",
+    "/usr/local",
+    "/usr/sbin",
+    "/usr/share",
+    "/var",
+    "/var/lib",
+    "/var/log",
+    "/var/tmp",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/Volumes",
+    "/private",
+    "/private/etc",
+    "/private/var",
+];
+
+/// Directories containing the home directories of each user on Unix platforms.
+#[cfg(unix)]
+const HOME_PARENT_DIRS: &[&str] = &["/home", "/Users"];
+
+/// Environment variables naming well-known system directories protected by default on Windows.
+#[cfg(windows)]
+const SYSTEM_DIR_VARS: &[&str] = &[
+    "SystemRoot",
+    "windir",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramW6432",
+    "ProgramData",
+    "CommonProgramFiles",
+    "CommonProgramFiles(x86)",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PUBLIC",
+];
+
+/// Caller-configured safeguard settings, stored in [`RemoveOptions`].
+///
+/// [`RemoveOptions`]: ../struct.RemoveOptions.html
+#[derive(Clone, Debug)]
+pub(crate) struct Safeguards {
+    /// Whether the built-in protected paths are checked.
+    pub defaults: bool,
+
+    /// Additional paths protected by the caller, along with their contents.
+    pub denied: Vec<PathBuf>,
+
+    /// Paths (and their contents) which may be removed even if protected.
+    pub allowed: Vec<PathBuf>,
+
+    /// Whether targets containing the current working directory or executable are refused.
+    pub process_paths: bool,
+
+    /// Paths registered as in use by the caller, which may not be removed along with any ancestor.
+    pub in_use: Vec<PathBuf>,
+}
+
+impl Default for Safeguards {
+    fn default() -> Self {
+        Self {
+            defaults: true,
+            denied: Vec::new(),
+            allowed: Vec::new(),
+            process_paths: true,
+            in_use: Vec::new(),
+        }
+    }
+}
+
+/// Process state consulted by the safeguards, abstracted for testing.
+trait Environment {
+    /// Returns the value of an environment variable.
+    fn var(&self, name: &str) -> Option<OsString>;
+
+    /// Returns the current working directory.
+    fn current_dir(&self) -> io::Result<PathBuf>;
+
+    /// Returns the path of the running executable.
+    fn current_exe(&self) -> io::Result<PathBuf>;
+}
+
+/// [`Environment`] of the current process.
+struct ProcessEnvironment;
+
+impl Environment for ProcessEnvironment {
+    fn var(&self, name: &str) -> Option<OsString> {
+        env::var_os(name)
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        env::current_dir()
+    }
+
+    fn current_exe(&self) -> io::Result<PathBuf> {
+        env::current_exe()
+    }
+}
+
+impl Safeguards {
+    /// Checks whether `path` may be removed, returning an [`Error::ProtectedPath`] if not.
+    ///
+    /// [`Error::ProtectedPath`]: ../enum.Error.html#variant.ProtectedPath
+    pub fn check(&self, path: &Path) -> io::Result<()> {
+        self.check_in(path, &ProcessEnvironment)
+    }
+
+    /// Checks whether `path` may be removed in the context of the given environment.
+    fn check_in<E: Environment>(&self, path: &Path, env: &E) -> io::Result<()> {
+        let target = resolve_target(path, env)?;
+        let target_key = comparison_key(&target);
+        let refuse = |protected: &Path, reason| -> io::Result<()> {
+            Err(Error::ProtectedPath {
+                path: path.to_path_buf(),
+                protected: protected.to_path_buf(),
+                reason,
+            }
+            .into())
+        };
+
+        // In-use paths are checked first, as they cannot be overridden by allowed paths.
+        let mut in_use: Vec<_> = self
+            .in_use
+            .iter()
+            .map(|path| (resolve(path), ProtectionReason::InUse))
+            .collect();
+        if self.process_paths {
+            in_use.extend(
+                env.current_dir()
+                    .ok()
+                    .map(|dir| (resolve(&dir), ProtectionReason::CurrentDirectory)),
+            );
+            in_use.extend(
+                env.current_exe()
+                    .ok()
+                    .map(|exe| (resolve(&exe), ProtectionReason::CurrentExecutable)),
+            );
+        }
+
+        for (protected, reason) in in_use {
+            if comparison_key(&protected).starts_with(&target_key) {
+                return refuse(&protected, reason);
+            }
+        }
+
+        if self
+            .allowed
+            .iter()
+            .any(|allowed| target_key.starts_with(comparison_key(&resolve(allowed))))
+        {
+            return Ok(());
+        }
+
+        for denied in &self.denied {
+            let denied = resolve(denied);
+            let denied_key = comparison_key(&denied);
+            if target_key.starts_with(&denied_key) || denied_key.starts_with(&target_key) {
+                return refuse(&denied, ProtectionReason::Denylisted);
+            }
+        }
+
+        if !self.defaults {
+            return Ok(());
+        }
+
+        if target.parent().is_none() {
+            return refuse(&target, ProtectionReason::Root);
+        }
+
+        for (protected, reason) in builtin_protected_paths(env) {
+            if comparison_key(&protected).starts_with(&target_key) {
+                return refuse(&protected, reason);
+            }
+        }
+
+        if let Some(parent) = target.parent() {
+            let parent_key = comparison_key(parent);
+            if home_parent_dirs(env)
+                .iter()
+                .any(|home_parent| comparison_key(&resolve(home_parent)) == parent_key)
+            {
+                return refuse(&target, ProtectionReason::HomeDirectory);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves the removal target `path` to an absolute path.
+///
+/// Symbolic links are resolved in every component except the last, since a link being removed is
+/// never followed.
+fn resolve_target<E: Environment>(path: &Path, env: &E) -> io::Result<PathBuf> {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env.current_dir()?.join(path)
+    };
+
+    Ok(match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => resolve(parent).join(name),
+        _ => resolve(&absolute),
+    })
+}
+
+/// Canonicalizes `path`.
+///
+/// If `path` does not exist, its longest existing ancestor is canonicalized instead, with the
+/// remaining components (including any `..` components) applied lexically.
+pub(crate) fn resolve(path: &Path) -> PathBuf {
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+
+    match (path.parent(), path.components().next_back()) {
+        (Some(parent), Some(Component::ParentDir)) => {
+            let parent = resolve(parent);
+            parent.parent().map_or(parent.clone(), Path::to_path_buf)
+        }
+        (Some(parent), Some(Component::Normal(name))) => resolve(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Returns a form of `path` suitable for comparison against other paths, which is case-insensitive
+/// on Windows.
+#[cfg(windows)]
+pub(crate) fn comparison_key(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().to_lowercase())
+}
+
+/// Returns a form of `path` suitable for comparison against other paths.
+#[cfg(not(windows))]
+pub(crate) fn comparison_key(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Returns the paths protected by default, along with the reason each is protected.
+#[cfg(unix)]
+fn builtin_protected_paths<E: Environment>(env: &E) -> Vec<(PathBuf, ProtectionReason)> {
+    env.var("HOME")
+        .filter(|home| !home.is_empty())
+        .map(|home| (resolve(Path::new(&home)), ProtectionReason::HomeDirectory))
+        .into_iter()
+        .chain(
+            SYSTEM_DIRS
+                .iter()
+                .map(|dir| (resolve(Path::new(dir)), ProtectionReason::SystemDirectory)),
+        )
+        .collect()
+}
+
+/// Returns the paths protected by default, along with the reason each is protected.
+#[cfg(windows)]
+fn builtin_protected_paths<E: Environment>(env: &E) -> Vec<(PathBuf, ProtectionReason)> {
+    let protected = |name: &&str, reason| {
+        env.var(name)
+            .filter(|value| !value.is_empty())
+            .map(|value| (resolve(Path::new(&value)), reason))
+    };
+
+    ["USERPROFILE", "HOME"]
+        .iter()
+        .filter_map(|name| protected(name, ProtectionReason::HomeDirectory))
+        .chain(
+            SYSTEM_DIR_VARS
+                .iter()
+                .filter_map(|name| protected(name, ProtectionReason::SystemDirectory)),
+        )
+        .chain(env.var("SystemRoot").map(|root| {
+            (
+                resolve(&Path::new(&root).join("System32")),
+                ProtectionReason::SystemDirectory,
+            )
+        }))
+        .collect()
+}
+
+/// Returns the directories containing each user's home directory.
+#[cfg(unix)]
+fn home_parent_dirs<E: Environment>(_env: &E) -> Vec<PathBuf> {
+    HOME_PARENT_DIRS.iter().map(PathBuf::from).collect()
+}
+
+/// Returns the directories containing each user's profile directory.
+#[cfg(windows)]
+fn home_parent_dirs<E: Environment>(env: &E) -> Vec<PathBuf> {
+    env.var("USERPROFILE")
+        .and_then(|profile| Path::new(&profile).parent().map(Path::to_path_buf))
+        .into_iter()
+        .collect()
+}
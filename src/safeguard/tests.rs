@@ -0,0 +1,245 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Protected-path safeguard tests.
+
+use super::{Environment, Safeguards};
+use crate::{remove_dir_all_with, Error, ProtectionReason, RemoveOptions};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+/// Fake process environment with a configurable home directory, working directory, and executable.
+#[derive(Default)]
+struct FakeEnvironment {
+    home: PathBuf,
+    current_dir: Option<PathBuf>,
+    current_exe: Option<PathBuf>,
+}
+
+impl Environment for FakeEnvironment {
+    fn var(&self, name: &str) -> Option<OsString> {
+        if name == "HOME" || name == "USERPROFILE" {
+            Some(self.home.clone().into_os_string())
+        } else {
+            None
+        }
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        self.current_dir
+            .clone()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn current_exe(&self) -> io::Result<PathBuf> {
+        self.current_exe
+            .clone()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+/// Checks `path` against `safeguards` in the context of `env`, returning the protected path and
+/// reason if refused.
+fn check_in(
+    safeguards: &Safeguards,
+    path: &Path,
+    env: &FakeEnvironment,
+) -> Option<(PathBuf, ProtectionReason)> {
+    match safeguards.check_in(path, env) {
+        Ok(()) => None,
+        Err(error) => {
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+            match Error::from_io_error(&error) {
+                Some(Error::ProtectedPath {
+                    protected, reason, ..
+                }) => Some((protected.clone(), *reason)),
+                _ => panic!("unexpected error: {}", error),
+            }
+        }
+    }
+}
+
+/// Checks `path` against `safeguards` with `$HOME` set to `home`, returning the protected path and
+/// reason if refused.
+fn check(safeguards: &Safeguards, path: &Path, home: &Path) -> Option<(PathBuf, ProtectionReason)> {
+    let env = FakeEnvironment {
+        home: home.to_path_buf(),
+        ..FakeEnvironment::default()
+    };
+    check_in(safeguards, path, &env)
+}
+
+/// Tests whether filesystem roots and system directories are refused, including through `..`
+/// components, while their contents are not.
+#[test]
+#[cfg(unix)]
+fn roots_and_system_dirs_are_refused() {
+    let safeguards = Safeguards::default();
+    let home = TempDir::new().unwrap();
+    let home = home.path();
+
+    assert_eq!(
+        check(&safeguards, Path::new("/"), home).map(|(_, reason)| reason),
+        Some(ProtectionReason::Root)
+    );
+    assert_eq!(
+        check(&safeguards, Path::new("/etc"), home),
+        Some((
+            fs::canonicalize("/etc").unwrap(),
+            ProtectionReason::SystemDirectory
+        ))
+    );
+    assert_eq!(
+        check(&safeguards, Path::new("/usr/bin/.."), home).map(|(_, reason)| reason),
+        Some(ProtectionReason::SystemDirectory)
+    );
+    assert_eq!(
+        check(&safeguards, Path::new("/home/someone"), home),
+        Some((
+            PathBuf::from("/home/someone"),
+            ProtectionReason::HomeDirectory
+        ))
+    );
+    assert_eq!(
+        check(&safeguards, Path::new("/usr/share/no-such-package"), home),
+        None
+    );
+}
+
+/// Tests whether the home directory and its ancestors are refused, and whether the refusal can be
+/// overridden with an allowed path.
+#[test]
+fn home_directory_is_refused() {
+    let base_dir = TempDir::new().unwrap();
+    let home = fs::canonicalize(base_dir.path()).unwrap().join("home");
+    fs::create_dir(&home).unwrap();
+
+    let mut safeguards = Safeguards::default();
+    assert_eq!(
+        check(&safeguards, &home, &home),
+        Some((home.clone(), ProtectionReason::HomeDirectory))
+    );
+    assert_eq!(
+        check(&safeguards, base_dir.path(), &home),
+        Some((home.clone(), ProtectionReason::HomeDirectory))
+    );
+    assert_eq!(check(&safeguards, &home.join("project"), &home), None);
+
+    safeguards.allowed.push(home.clone());
+    assert_eq!(check(&safeguards, &home, &home), None);
+    assert!(check(&safeguards, base_dir.path(), &home).is_some());
+
+    safeguards.defaults = false;
+    assert_eq!(check(&safeguards, base_dir.path(), &home), None);
+}
+
+/// Tests whether caller-protected paths are refused along with their contents and ancestors, and
+/// whether symbolic links to protected paths can still be removed.
+#[test]
+fn denylisted_paths_are_refused() {
+    let base_dir = TempDir::new().unwrap();
+    let root = fs::canonicalize(base_dir.path()).unwrap();
+    let protected = root.join("protected");
+    fs::create_dir_all(protected.join("inner")).unwrap();
+    fs::create_dir(root.join("sibling")).unwrap();
+
+    let mut options = RemoveOptions::new();
+    options.protect_path(&protected);
+    let safeguards = &options.safeguards;
+    let denied = Some((protected.clone(), ProtectionReason::Denylisted));
+    assert_eq!(check(safeguards, &protected, &root), denied);
+    assert_eq!(check(safeguards, &protected.join("inner"), &root), denied);
+    assert_eq!(
+        check(safeguards, &root.join("sibling/../protected"), &root),
+        denied
+    );
+    assert_eq!(check(safeguards, &root, &root), denied);
+    assert_eq!(check(safeguards, &root.join("sibling"), &root), None);
+
+    #[cfg(unix)]
+    {
+        let link = root.join("sibling").join("link");
+        std::os::unix::fs::symlink(&protected, &link).unwrap();
+        assert_eq!(check(safeguards, &link, &root), None);
+    }
+
+    let error = remove_dir_all_with(&protected, &options).unwrap_err();
+    assert!(Error::from_io_error(&error).is_some());
+    assert!(protected.join("inner").is_dir());
+}
+
+/// Tests whether targets containing the working directory, executable, or registered in-use paths
+/// are refused, resolving `..` components and symbolic links along the way.
+#[test]
+fn process_paths_are_refused() {
+    let base_dir = TempDir::new().unwrap();
+    let root = fs::canonicalize(base_dir.path()).unwrap();
+    let cwd = root.join("work").join("cwd");
+    let exe = root.join("bin").join("app");
+    let in_use = root.join("data").join("db");
+    fs::create_dir_all(&cwd).unwrap();
+    fs::create_dir_all(&in_use).unwrap();
+    fs::create_dir_all(exe.parent().unwrap()).unwrap();
+    fs::write(&exe, b"").unwrap();
+
+    let env = FakeEnvironment {
+        home: root.join("home"),
+        current_dir: Some(cwd.clone()),
+        current_exe: Some(exe.clone()),
+    };
+    let mut safeguards = Safeguards::default();
+    safeguards.in_use.push(in_use.clone());
+    safeguards.allowed.push(root.clone());
+
+    let refused_cwd = Some((cwd.clone(), ProtectionReason::CurrentDirectory));
+    assert_eq!(check_in(&safeguards, &cwd, &env), refused_cwd);
+    assert_eq!(check_in(&safeguards, Path::new("."), &env), refused_cwd);
+    assert_eq!(check_in(&safeguards, Path::new(".."), &env), refused_cwd);
+    assert_eq!(
+        check_in(&safeguards, Path::new("sub/../.."), &env),
+        refused_cwd
+    );
+    assert_eq!(
+        check_in(&safeguards, &root.join("bin"), &env),
+        Some((exe, ProtectionReason::CurrentExecutable))
+    );
+    assert_eq!(
+        check_in(&safeguards, &root.join("data"), &env),
+        Some((in_use.clone(), ProtectionReason::InUse))
+    );
+    assert_eq!(check_in(&safeguards, &cwd.join("child"), &env), None);
+
+    #[cfg(unix)]
+    {
+        // A symbolic link to the working directory's parent only removes the link, while a path
+        // through the link still resolves to the working directory.
+        let link = root.join("link");
+        std::os::unix::fs::symlink(root.join("work"), &link).unwrap();
+        assert_eq!(check_in(&safeguards, &link, &env), None);
+        assert_eq!(check_in(&safeguards, &link.join("cwd"), &env), refused_cwd);
+    }
+
+    safeguards.process_paths = false;
+    assert_eq!(check_in(&safeguards, &cwd, &env), None);
+    assert_eq!(
+        check_in(&safeguards, &in_use, &env),
+        Some((in_use, ProtectionReason::InUse))
+    );
+}
+
+/// Tests whether the working directory of the test process itself is refused.
+#[test]
+fn actual_current_dir_is_refused() {
+    let cwd = std::env::current_dir().unwrap();
+    let error = Safeguards::default().check(&cwd).unwrap_err();
+    assert!(Error::from_io_error(&error).is_some());
+}
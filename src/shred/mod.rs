@@ -0,0 +1,356 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Overwriting file contents before removal, for [`RemoveOptions::shred`].
+//!
+//! Each pass overwrites the full length of a file and is flushed to the device with `fsync` before
+//! the next begins. Afterwards, the file is truncated (so its size is not left behind either) and
+//! flushed once more before being unlinked. Files reachable through other hard links are never
+//! overwritten, since their contents are still in use elsewhere.
+//!
+//! Overwriting in place cannot reach old copies of the data kept by copy-on-write or journaling
+//! filesystems, SSD wear leveling, or snapshots, so this is a best-effort measure.
+//!
+//! [`RemoveOptions::shred`]: ../struct.RemoveOptions.html#method.shred
+
+#[cfg(test)]
+mod tests;
+
+use crate::Error;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Size of the buffer used to write each pass.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Data written over file contents by each shred pass.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ShredPattern {
+    /// Zero bytes.
+    Zeros,
+
+    /// Pseudorandom bytes, generated anew for each pass. This is the default.
+    Random,
+
+    /// The given bytes, repeated over the full length of the file. An empty pattern writes zero
+    /// bytes.
+    Repeat(Vec<u8>),
+}
+
+impl Default for ShredPattern {
+    fn default() -> Self {
+        ShredPattern::Random
+    }
+}
+
+/// Options for overwriting files before they are removed, passed to
+/// [`RemoveOptions::shred`](struct.RemoveOptions.html#method.shred).
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::{remove_dir_all_with, RemoveOptions, ShredOptions, ShredPattern};
+///
+/// fn main() -> std::io::Result<()> {
+///     let mut shred = ShredOptions::new();
+///     shred.passes(2).pattern(ShredPattern::Zeros);
+///
+///     let report = remove_dir_all_with("credentials", RemoveOptions::new().shred(shred))?;
+///     for path in report.shredded() {
+///         println!("Shredded {}.", path.display());
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ShredOptions {
+    passes: u32,
+    pattern: ShredPattern,
+    refuse_hard_links: bool,
+}
+
+impl Default for ShredOptions {
+    fn default() -> Self {
+        Self {
+            passes: 3,
+            pattern: ShredPattern::default(),
+            refuse_hard_links: false,
+        }
+    }
+}
+
+impl ShredOptions {
+    /// Creates the default shred options: three passes of pseudorandom data, skipping files with
+    /// multiple hard links.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of times each file is overwritten. With zero passes, files are only
+    /// truncated before being removed.
+    pub fn passes(&mut self, passes: u32) -> &mut Self {
+        self.passes = passes;
+        self
+    }
+
+    /// Sets the data written over file contents by each pass.
+    pub fn pattern(&mut self, pattern: ShredPattern) -> &mut Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Sets whether files with more than one hard link fail the removal rather than being skipped.
+    ///
+    /// By default, such files are left in place (along with their ancestors) and reported as
+    /// [`SkipReason::HardLinked`]. When refused, the removal fails with an
+    /// `io::ErrorKind::PermissionDenied` error carrying an [`Error::HardLinked`], although any
+    /// entries visited before it have already been shredded or removed.
+    ///
+    /// [`SkipReason::HardLinked`]: enum.SkipReason.html#variant.HardLinked
+    /// [`Error::HardLinked`]: enum.Error.html#variant.HardLinked
+    pub fn refuse_hard_links(&mut self, refuse: bool) -> &mut Self {
+        self.refuse_hard_links = refuse;
+        self
+    }
+}
+
+/// Overwrites, truncates, and removes the regular file at `path`.
+///
+/// Returns `false` without modifying the file if it has more than one hard link and such files are
+/// not refused. If the file had to be made writable, its original permissions are restored unless
+/// it is removed.
+pub(crate) fn shred_file(path: &Path, options: &ShredOptions) -> io::Result<bool> {
+    // Links are checked before anything is modified, since permissions are shared by every link.
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_file() {
+        return Err(not_regular_file());
+    }
+    if !check_links(path, path_link_count(path, &metadata)?, options)? {
+        return Ok(false);
+    }
+
+    let (file, original_permissions) = open_for_writing(path, metadata.permissions())?;
+    let result = shred_open_file(path, file, options).and_then(|shredded| {
+        if shredded {
+            fs::remove_file(path)?;
+        }
+        Ok(shredded)
+    });
+
+    if let Some(permissions) = original_permissions {
+        if result.as_ref().ok() != Some(&true) {
+            let _ = fs::set_permissions(path, permissions);
+        }
+    }
+    result
+}
+
+/// Overwrites and truncates `file`, opened from `path`, after checking again that it is a regular
+/// file with no other hard links, in case it was replaced since it was first checked.
+fn shred_open_file(path: &Path, mut file: File, options: &ShredOptions) -> io::Result<bool> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(not_regular_file());
+    }
+    if !check_links(path, link_count(&file, &metadata)?, options)? {
+        return Ok(false);
+    }
+
+    overwrite(&mut file, metadata.len(), options)?;
+    file.set_len(0)?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+/// Returns the error reported for shred targets that are not regular files.
+fn not_regular_file() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Shred target is not a regular file.",
+    )
+}
+
+/// Returns whether the file at `path` with the given number of hard links may be shredded, failing
+/// if it has other links and such files are refused.
+fn check_links(path: &Path, links: u64, options: &ShredOptions) -> io::Result<bool> {
+    if links <= 1 {
+        return Ok(true);
+    }
+    if options.refuse_hard_links {
+        return Err(Error::HardLinked {
+            path: path.to_path_buf(),
+            links,
+        }
+        .into());
+    }
+    Ok(false)
+}
+
+/// Opens the file at `path`, which has the given permissions, for writing without following
+/// symbolic links, making it writable first if necessary.
+///
+/// Returns the original permissions along with the file if they were changed, so that they can be
+/// restored if the file is not removed.
+fn open_for_writing(
+    path: &Path,
+    permissions: fs::Permissions,
+) -> io::Result<(File, Option<fs::Permissions>)> {
+    let mut open_options = OpenOptions::new();
+    open_options.write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.custom_flags(libc::O_NOFOLLOW);
+    }
+
+    match open_options.open(path) {
+        // Read-only files can be removed without write access on Unix, so shredding them requires
+        // lifting the restriction first, like `shred --force`.
+        Err(ref error)
+            if error.kind() == io::ErrorKind::PermissionDenied && permissions.readonly() =>
+        {
+            fs::set_permissions(path, writable(&permissions))?;
+            match open_options.open(path) {
+                Ok(file) => Ok((file, Some(permissions))),
+                Err(error) => {
+                    let _ = fs::set_permissions(path, permissions);
+                    Err(error)
+                }
+            }
+        }
+        result => result.map(|file| (file, None)),
+    }
+}
+
+/// Returns `permissions` with write access granted to the owner only.
+#[cfg(unix)]
+fn writable(permissions: &fs::Permissions) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::Permissions::from_mode(permissions.mode() | 0o200)
+}
+
+/// Returns `permissions` without the read-only attribute.
+#[cfg(not(unix))]
+fn writable(permissions: &fs::Permissions) -> fs::Permissions {
+    let mut permissions = permissions.clone();
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    permissions
+}
+
+/// Overwrites the first `len` bytes of `file` once per configured pass, flushing each pass to the
+/// device.
+pub(crate) fn overwrite(file: &mut File, len: u64, options: &ShredOptions) -> io::Result<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut random = XorShift::seeded();
+    for _ in 0..options.passes {
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut offset = 0u64;
+        while offset < len {
+            let chunk_len = (len - offset).min(BUFFER_SIZE as u64) as usize;
+            let chunk = &mut buffer[..chunk_len];
+            match options.pattern {
+                ShredPattern::Zeros => chunk.iter_mut().for_each(|byte| *byte = 0),
+                ShredPattern::Random => random.fill(chunk),
+                ShredPattern::Repeat(ref pattern) if pattern.is_empty() => {
+                    chunk.iter_mut().for_each(|byte| *byte = 0)
+                }
+                ShredPattern::Repeat(ref pattern) => {
+                    for (i, byte) in chunk.iter_mut().enumerate() {
+                        *byte = pattern[((offset + i as u64) % pattern.len() as u64) as usize];
+                    }
+                }
+            }
+
+            file.write_all(chunk)?;
+            offset += chunk_len as u64;
+        }
+
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Returns the number of hard links to the file at `path`, which has the given metadata, without
+/// opening it for writing.
+#[cfg(unix)]
+fn path_link_count(_path: &Path, metadata: &fs::Metadata) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(metadata.nlink())
+}
+
+/// Returns the number of hard links to the file at `path`, which has the given metadata, without
+/// opening it for writing.
+#[cfg(windows)]
+fn path_link_count(path: &Path, metadata: &fs::Metadata) -> io::Result<u64> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use winapi::um::winbase::FILE_FLAG_OPEN_REPARSE_POINT;
+
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT)
+        .open(path)?;
+    link_count(&file, metadata)
+}
+
+/// Returns the number of hard links to the open file `file`.
+#[cfg(unix)]
+fn link_count(_file: &File, metadata: &fs::Metadata) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(metadata.nlink())
+}
+
+/// Returns the number of hard links to the open file `file`.
+#[cfg(windows)]
+fn link_count(file: &File, _metadata: &fs::Metadata) -> io::Result<u64> {
+    use std::{mem, os::windows::io::AsRawHandle};
+    use winapi::um::fileapi::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION};
+
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { mem::zeroed() };
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, &mut info) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(info.nNumberOfLinks.into())
+}
+
+/// Xorshift pseudorandom number generator.
+///
+/// Shred passes only need to be hard to compress or predict from the original contents, not to be
+/// cryptographically secure, so a fast generator seeded from the clock suffices.
+struct XorShift(u64);
+
+impl XorShift {
+    /// Creates a generator seeded from the current time.
+    fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+        Self(nanos | 1)
+    }
+
+    /// Fills `buffer` with pseudorandom bytes.
+    fn fill(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            chunk.copy_from_slice(&self.0.to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
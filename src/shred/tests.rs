@@ -0,0 +1,131 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Shred tests.
+
+use super::{overwrite, ShredOptions, ShredPattern};
+use crate::{remove_dir_all_with, Error, RemoveOptions, SkipReason};
+use std::{
+    fs::{self, OpenOptions},
+    io,
+};
+use tempfile::TempDir;
+
+/// Tests whether each pass overwrites the full length of a file with the configured pattern,
+/// continuing the pattern across buffer boundaries.
+#[test]
+fn overwrite_works() {
+    let base_dir = TempDir::new().unwrap();
+    let path = base_dir.path().join("file");
+    let len = super::BUFFER_SIZE + 1001;
+    fs::write(&path, vec![0x5a; len]).unwrap();
+
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+    let mut options = ShredOptions::new();
+    options
+        .passes(2)
+        .pattern(ShredPattern::Repeat(vec![1, 2, 3]));
+    overwrite(&mut file, len as u64, &options).unwrap();
+
+    let contents = fs::read(&path).unwrap();
+    assert_eq!(contents.len(), len);
+    assert!(contents
+        .iter()
+        .enumerate()
+        .all(|(i, &byte)| byte == [1, 2, 3][i % 3]));
+
+    options.pattern(ShredPattern::Random);
+    overwrite(&mut file, len as u64, &options).unwrap();
+    let contents = fs::read(&path).unwrap();
+    assert_eq!(contents.len(), len);
+    assert!(contents.iter().filter(|&&byte| byte == 0x5a).count() < len / 64);
+}
+
+/// Tests whether shredding removes a directory tree and reports every shredded file.
+#[test]
+fn shredding_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("secrets");
+    fs::create_dir_all(dir_path.join("nested")).unwrap();
+    fs::write(dir_path.join("key"), b"hunter2").unwrap();
+    fs::write(dir_path.join("nested").join("export.csv"), b"a,b,c").unwrap();
+
+    let read_only = dir_path.join("nested").join("read-only");
+    fs::write(&read_only, b"secret").unwrap();
+    let mut permissions = fs::metadata(&read_only).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&read_only, permissions).unwrap();
+
+    let report =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().shred(ShredOptions::new())).unwrap();
+    assert!(report.root_removed());
+    assert_eq!(report.files_removed(), 3);
+
+    let mut shredded = report.shredded().to_vec();
+    shredded.sort();
+    assert_eq!(
+        shredded,
+        vec![
+            dir_path.join("key"),
+            dir_path.join("nested").join("export.csv"),
+            read_only,
+        ]
+    );
+    assert!(!dir_path.exists());
+}
+
+/// Tests whether files with other hard links are skipped by default and refused if requested,
+/// leaving their contents intact either way.
+#[test]
+fn hard_linked_files_are_not_shredded() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    let shared = base_dir.path().join("shared");
+    fs::create_dir(&dir_path).unwrap();
+    fs::write(&shared, b"shared data").unwrap();
+    fs::hard_link(&shared, dir_path.join("link")).unwrap();
+
+    let mut shred = ShredOptions::new();
+    let report = remove_dir_all_with(&dir_path, RemoveOptions::new().shred(shred.clone())).unwrap();
+    assert!(!report.root_removed());
+    assert!(report.shredded().is_empty());
+    assert_eq!(report.skipped()[0].path(), dir_path.join("link"));
+    assert_eq!(report.skipped()[0].reason(), SkipReason::HardLinked);
+
+    shred.refuse_hard_links(true);
+    let error = remove_dir_all_with(&dir_path, RemoveOptions::new().shred(shred)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    match Error::from_io_error(&error) {
+        Some(Error::HardLinked { links: 2, .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+
+    assert_eq!(fs::read(&shared).unwrap(), b"shared data");
+    assert_eq!(fs::read(dir_path.join("link")).unwrap(), b"shared data");
+}
+
+/// Tests whether read-only files with other hard links are skipped without being made writable,
+/// since their permissions are shared with every other link.
+#[test]
+fn read_only_hard_linked_files_keep_permissions() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    let shared = base_dir.path().join("shared");
+    fs::create_dir(&dir_path).unwrap();
+    fs::write(&shared, b"shared data").unwrap();
+    let mut permissions = fs::metadata(&shared).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&shared, permissions.clone()).unwrap();
+    fs::hard_link(&shared, dir_path.join("link")).unwrap();
+
+    let report =
+        remove_dir_all_with(&dir_path, RemoveOptions::new().shred(ShredOptions::new())).unwrap();
+    assert_eq!(report.skipped()[0].reason(), SkipReason::HardLinked);
+    assert_eq!(fs::metadata(&shared).unwrap().permissions(), permissions);
+    assert_eq!(fs::read(&shared).unwrap(), b"shared data");
+}
@@ -0,0 +1,154 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Handling of symbolic links and junctions, for [`RemoveOptions::symlinks`].
+//!
+//! When links are followed, the engine tracks the canonical path of every directory it is
+//! currently emptying. A link is only followed if its canonical target lies within the canonical
+//! root of the removal and is not an ancestor of (or the same as) any directory being emptied, so
+//! a followed link can neither escape the target nor send the engine around a cycle. The same
+//! tracking guards against cycles when copying with [`CopySymlinkPolicy::Follow`], which is not
+//! confined to the source directory.
+//!
+//! [`RemoveOptions::symlinks`]: ../struct.RemoveOptions.html#method.symlinks
+//! [`CopySymlinkPolicy::Follow`]: ../enum.CopySymlinkPolicy.html#variant.Follow
+
+#[cfg(test)]
+mod tests;
+
+use crate::{Error, RemoveOptions};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// How symbolic links (and junctions on Windows) encountered during a removal are handled.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Links are removed without following them, leaving their targets untouched.
+    RemoveLink,
+
+    /// The removal fails with an [`Error::SymlinkRefused`](enum.Error.html#variant.SymlinkRefused)
+    /// if the target or any entry within it is a link. Entries visited before the link was found
+    /// are not restored.
+    Refuse,
+
+    /// Links to directories are followed, and the contents of their targets are removed before
+    /// the links themselves. The target directories are emptied but left in place.
+    ///
+    /// Links are only followed to directories within the removal target (or within the target of
+    /// the root link, if the target itself is a link), and following one that resolves elsewhere
+    /// fails with an [`Error::EscapesRoot`](enum.Error.html#variant.EscapesRoot). Links to files,
+    /// dangling links, and links leading back into a directory that is already being emptied are
+    /// removed without being followed.
+    DeleteTargetContents,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        SymlinkPolicy::RemoveLink
+    }
+}
+
+/// Checks whether the removal of the target at `path` may follow links, refusing options that
+/// cannot be combined with following them and protected paths reached through a root link.
+pub(crate) fn check_root(path: &Path, options: &RemoveOptions) -> io::Result<()> {
+    if options.symlinks != SymlinkPolicy::DeleteTargetContents {
+        return Ok(());
+    }
+
+    if options.gitignored_only {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Links cannot be followed when only removing Git-ignored entries.",
+        ));
+    }
+
+    // The contents of a root link's target are removed as well, so the target must not be
+    // protected either.
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        options.safeguards.check(&fs::canonicalize(path)?)?;
+    }
+
+    Ok(())
+}
+
+/// Directories being visited while following links, used to confine and detect cycles of links.
+#[derive(Debug)]
+pub(crate) struct FollowedLinks {
+    /// Canonical path of the target, which followed links must resolve within, if confined.
+    root: Option<PathBuf>,
+
+    /// Canonical paths of the directories currently being visited, outermost first.
+    active: Vec<PathBuf>,
+}
+
+impl FollowedLinks {
+    /// Starts tracking the removal of the target at `root`, confining followed links to it.
+    pub fn new(root: &Path) -> io::Result<Self> {
+        Ok(Self {
+            root: Some(fs::canonicalize(root)?),
+            active: Vec::new(),
+        })
+    }
+
+    /// Starts tracking a traversal that may follow links anywhere, only guarding against cycles.
+    pub fn unconfined() -> Self {
+        Self {
+            root: None,
+            active: Vec::new(),
+        }
+    }
+
+    /// Resolves the link at `path`, returning the canonical path of its target if it is a directory
+    /// that should be followed, or `None` if only the link itself should be handled.
+    ///
+    /// `path` must be an entry of the directory most recently entered, if any.
+    pub fn resolve(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        // Dangling links and cycles consisting only of links cannot be resolved at all.
+        match fs::metadata(path) {
+            Ok(ref metadata) if metadata.is_dir() => {}
+            _ => return Ok(None),
+        }
+
+        let target = fs::canonicalize(path)?;
+        if let Some(ref root) = self.root {
+            if !target.starts_with(root) {
+                return Err(Error::EscapesRoot {
+                    root: root.clone(),
+                    path: path.to_path_buf(),
+                }
+                .into());
+            }
+        }
+
+        if self.active.iter().any(|dir| dir.starts_with(&target)) {
+            return Ok(None);
+        }
+
+        Ok(Some(target))
+    }
+
+    /// Records that the directory at `path` is being visited, with `canonical` being its canonical
+    /// path if already known.
+    pub fn enter(&mut self, path: &Path, canonical: Option<PathBuf>) -> io::Result<()> {
+        let canonical = match (canonical, self.active.last(), path.file_name()) {
+            (Some(canonical), _, _) => canonical,
+            // Directories reached without following a link are named within their parent.
+            (None, Some(parent), Some(name)) => parent.join(name),
+            (None, _, _) => fs::canonicalize(path)?,
+        };
+        self.active.push(canonical);
+        Ok(())
+    }
+
+    /// Records that the directory most recently entered has been fully visited.
+    pub fn leave(&mut self) {
+        self.active.pop();
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Symbolic link policy tests.
+
+use crate::{remove_dir_all_with, RemoveOptions, SymlinkPolicy};
+use std::io;
+use tempfile::TempDir;
+
+#[cfg(any(unix, all(windows, feature = "symlink_tests")))]
+use crate::{engine::tests::populate_dir, Error};
+#[cfg(any(unix, all(windows, feature = "symlink_tests")))]
+use std::{fs, path::Path};
+
+#[cfg(unix)]
+use std::os::unix::fs::{symlink as symlink_dir, symlink as symlink_file};
+#[cfg(all(windows, feature = "symlink_tests"))]
+use std::os::windows::fs::{symlink_dir, symlink_file};
+
+/// Creates a directory at `path` containing a few entries.
+#[cfg(any(unix, all(windows, feature = "symlink_tests")))]
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)
+}
+
+/// Returns options using the given link policy.
+fn options(policy: SymlinkPolicy) -> RemoveOptions {
+    let mut options = RemoveOptions::new();
+    options.symlinks(policy);
+    options
+}
+
+/// Tests whether links within the tree and at the root are refused, leaving the root link and
+/// its target in place.
+#[test]
+#[cfg(any(unix, all(windows, feature = "symlink_tests")))]
+fn refuse_works() {
+    let base_dir = TempDir::new().unwrap();
+    let outside_path = base_dir.path().join("outside");
+    create_tree(&outside_path).unwrap();
+
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+    symlink_dir(&outside_path, dir_path.join("link")).unwrap();
+    let error = remove_dir_all_with(&dir_path, &options(SymlinkPolicy::Refuse)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    match Error::from_io_error(&error) {
+        Some(Error::SymlinkRefused { path }) => assert_eq!(path, &dir_path.join("link")),
+        _ => panic!("unexpected error: {}", error),
+    }
+
+    let root_link_path = base_dir.path().join("root-link");
+    symlink_dir(&outside_path, &root_link_path).unwrap();
+    let error = remove_dir_all_with(&root_link_path, &options(SymlinkPolicy::Refuse)).unwrap_err();
+    match Error::from_io_error(&error) {
+        Some(Error::SymlinkRefused { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(fs::symlink_metadata(&root_link_path).is_ok());
+    assert!(outside_path.join("baz").join("qux").is_file());
+}
+
+/// Tests whether following a root link empties its target, including the targets of links within
+/// it, and removes the link while leaving the target directory in place.
+#[test]
+#[cfg(any(unix, all(windows, feature = "symlink_tests")))]
+fn delete_target_contents_works() {
+    let base_dir = TempDir::new().unwrap();
+    let real_path = base_dir.path().join("real");
+    create_tree(&real_path).unwrap();
+    create_tree(&real_path.join("inner")).unwrap();
+    symlink_dir(
+        real_path.join("inner"),
+        real_path.join("baz").join("inner-link"),
+    )
+    .unwrap();
+    symlink_file(real_path.join("foo"), real_path.join("file-link")).unwrap();
+
+    let link_path = base_dir.path().join("link");
+    symlink_dir(&real_path, &link_path).unwrap();
+
+    let report =
+        remove_dir_all_with(&link_path, &options(SymlinkPolicy::DeleteTargetContents)).unwrap();
+    assert!(report.root_removed());
+    assert_eq!(report.symlinks_removed(), 3);
+    assert!(fs::symlink_metadata(&link_path).is_err());
+    assert_eq!(fs::read_dir(&real_path).unwrap().count(), 0);
+}
+
+/// Tests whether dangling links are removed without being followed.
+#[test]
+#[cfg(unix)]
+fn dangling_links_are_removed() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+    symlink_dir(base_dir.path().join("missing"), dir_path.join("dangling")).unwrap();
+
+    let report =
+        remove_dir_all_with(&dir_path, &options(SymlinkPolicy::DeleteTargetContents)).unwrap();
+    assert_eq!(report.symlinks_removed(), 1);
+    assert!(fs::symlink_metadata(&dir_path).is_err());
+}
+
+/// Tests whether links to ancestors, links between directories being emptied, and cycles of links
+/// are removed without looping.
+#[test]
+#[cfg(unix)]
+fn loops_are_detected() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path.join("a")).unwrap();
+    create_tree(&dir_path.join("x")).unwrap();
+    create_tree(&dir_path.join("y")).unwrap();
+
+    symlink_dir(&dir_path, dir_path.join("a").join("to-root")).unwrap();
+    symlink_dir(dir_path.join("a"), dir_path.join("a").join("to-self")).unwrap();
+    symlink_dir(dir_path.join("y"), dir_path.join("x").join("to-y")).unwrap();
+    symlink_dir(dir_path.join("x"), dir_path.join("y").join("to-x")).unwrap();
+    symlink_dir(dir_path.join("cycle-b"), dir_path.join("cycle-a")).unwrap();
+    symlink_dir(dir_path.join("cycle-a"), dir_path.join("cycle-b")).unwrap();
+
+    let report =
+        remove_dir_all_with(&dir_path, &options(SymlinkPolicy::DeleteTargetContents)).unwrap();
+    assert!(report.root_removed());
+    assert_eq!(report.symlinks_removed(), 6);
+    assert!(fs::symlink_metadata(&dir_path).is_err());
+}
+
+/// Tests whether following a link that resolves outside of the target is refused, leaving the
+/// link's target untouched.
+#[test]
+#[cfg(unix)]
+fn links_outside_target_fail() {
+    let base_dir = TempDir::new().unwrap();
+    let outside_path = base_dir.path().join("outside");
+    create_tree(&outside_path).unwrap();
+
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+    symlink_dir(&outside_path, dir_path.join("baz").join("escape")).unwrap();
+
+    let error =
+        remove_dir_all_with(&dir_path, &options(SymlinkPolicy::DeleteTargetContents)).unwrap_err();
+    match Error::from_io_error(&error) {
+        Some(Error::EscapesRoot { root, path }) => {
+            assert_eq!(root, &fs::canonicalize(&dir_path).unwrap());
+            assert_eq!(path, &dir_path.join("baz").join("escape"));
+        }
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(outside_path.join("baz").join("qux").is_file());
+
+    // Links to files outside of the target are removed without touching their targets.
+    fs::remove_file(dir_path.join("baz").join("escape")).unwrap();
+    symlink_file(outside_path.join("foo"), dir_path.join("file-link")).unwrap();
+    remove_dir_all_with(&dir_path, &options(SymlinkPolicy::DeleteTargetContents)).unwrap();
+    assert!(outside_path.join("foo").is_file());
+}
+
+/// Tests whether the target of a followed root link is checked against the protected paths.
+#[test]
+#[cfg(unix)]
+fn protected_root_link_target_fails() {
+    let base_dir = TempDir::new().unwrap();
+    let protected_path = base_dir.path().join("protected");
+    create_tree(&protected_path).unwrap();
+    let link_path = base_dir.path().join("link");
+    symlink_dir(&protected_path, &link_path).unwrap();
+
+    let mut options = options(SymlinkPolicy::DeleteTargetContents);
+    options.protect_path(fs::canonicalize(&protected_path).unwrap());
+    let error = remove_dir_all_with(&link_path, &options).unwrap_err();
+    match Error::from_io_error(&error) {
+        Some(Error::ProtectedPath { .. }) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+    assert!(protected_path.join("foo").is_file());
+
+    // The link itself can still be removed when it is not followed.
+    options.symlinks(SymlinkPolicy::RemoveLink);
+    remove_dir_all_with(&link_path, &options).unwrap();
+    assert!(protected_path.join("foo").is_file());
+}
+
+/// Tests whether `one_file_system` leaves links in place rather than following them to mount
+/// points within the target, whose contents would otherwise be removed through the link.
+#[test]
+#[cfg(unix)]
+fn followed_links_stop_at_mount_points() {
+    use crate::{engine::Engine, mounts::MountPoints, SkipReason};
+
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    let mount_path = dir_path.join("mnt");
+    create_tree(&mount_path).unwrap();
+    let link_path = dir_path.join("link");
+    symlink_dir(&mount_path, &link_path).unwrap();
+
+    let mountinfo = format!(
+        "36 35 98:0 / {} rw,noatime master:1 - ext3 /dev/root rw\n",
+        fs::canonicalize(&mount_path).unwrap().to_string_lossy()
+    );
+    let mut options = options(SymlinkPolicy::DeleteTargetContents);
+    options.one_file_system(true);
+    let mut engine = Engine::new(&options).with_mount_points(MountPoints::parse(&mountinfo));
+    engine.remove_root(&dir_path).unwrap();
+
+    let report = engine.finish().unwrap();
+    assert!(!report.root_removed());
+    assert_eq!(report.symlinks_removed(), 0);
+    let mut skipped: Vec<_> = report
+        .skipped()
+        .iter()
+        .map(|skipped| (skipped.path(), skipped.reason()))
+        .collect();
+    skipped.sort();
+    assert_eq!(
+        skipped,
+        [
+            (link_path.as_path(), SkipReason::FileSystemBoundary),
+            (mount_path.as_path(), SkipReason::FileSystemBoundary),
+        ]
+    );
+    assert!(fs::symlink_metadata(&link_path).is_ok());
+    assert!(mount_path.join("foo").is_file());
+    assert!(mount_path.join("baz").join("qux").is_file());
+}
+
+/// Tests whether following links is refused when only removing Git-ignored entries.
+#[test]
+fn gitignored_only_fails() {
+    let base_dir = TempDir::new().unwrap();
+    let mut options = options(SymlinkPolicy::DeleteTargetContents);
+    options.gitignored_only(true);
+
+    let error = remove_dir_all_with(base_dir.path(), &options).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
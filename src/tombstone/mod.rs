@@ -0,0 +1,278 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rename-then-delete removal, for [`RemoveOptions::tombstone`].
+//!
+//! The target is first renamed to a hidden sibling (a "tombstone") within the same parent
+//! directory, which frees its path immediately and is atomic since both paths reside on the same
+//! volume. The tombstone is then removed like any other target.
+//!
+//! Tombstone names are of the form `.rmdir-tombstone-<pid>-<counter>-<name>`, recording the ID of
+//! the process that created them. A tombstone whose process is no longer running was left behind
+//! by an interrupted removal, so it is reclaimed the next time a sibling is removed with
+//! tombstones enabled.
+//!
+//! [`RemoveOptions::tombstone`]: ../struct.RemoveOptions.html#method.tombstone
+
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    background::Monitor,
+    engine::{is_dir_symlink, sync_dir, Engine},
+    RemovalReport, RemoveOptions,
+};
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+/// Prefix of every tombstone name.
+const PREFIX: &str = ".rmdir-tombstone-";
+
+/// Counter distinguishing the tombstones created by the current process.
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// When the tombstone left in place of a removal target is removed.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TombstoneMode {
+    /// The tombstone is removed before returning, so only the target path is freed early.
+    Synchronous,
+
+    /// The tombstone is removed on a background thread, and the removal returns as soon as the
+    /// target has been renamed.
+    Background,
+}
+
+/// Renames the directory at `path` to a tombstone, then removes the tombstone (along with any
+/// stale tombstones left by other processes) according to `mode`.
+///
+/// Progress of a synchronous removal is published to `monitor` if provided. The path of the
+/// tombstone is returned alongside the result, even if the removal failed, unless the target was
+/// never renamed.
+pub(crate) fn remove(
+    path: &Path,
+    options: &RemoveOptions,
+    mode: TombstoneMode,
+    monitor: Option<&Arc<Monitor>>,
+) -> (io::Result<RemovalReport>, Option<PathBuf>) {
+    let tombstone = match prepare(path, options) {
+        Ok(tombstone) => tombstone,
+        Err(error) => return (Err(error), None),
+    };
+    let stale = tombstone.parent().map_or_else(Vec::new, stale_tombstones);
+
+    let result = match mode {
+        TombstoneMode::Synchronous => {
+            remove_tombstones(&tombstone, &stale, options, monitor).map(|report| RemovalReport {
+                tombstone: Some(tombstone.clone()),
+                ..report
+            })
+        }
+        TombstoneMode::Background => remove_in_background(&tombstone, stale, options),
+    };
+    (result, Some(tombstone))
+}
+
+/// Checks that the options and the target at `path` allow a tombstone, then renames the target to
+/// one, returning the path of the tombstone.
+fn prepare(path: &Path, options: &RemoveOptions) -> io::Result<PathBuf> {
+    if options.gitignored_only {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Tombstones cannot be used when only removing Git-ignored entries.",
+        ));
+    }
+    if options.journal.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Tombstones cannot be used with journals.",
+        ));
+    }
+
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if !file_type.is_dir() && !is_dir_symlink(path, file_type) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Target is not a directory or directory symlink.",
+        ));
+    }
+
+    rename_to_tombstone(path)
+}
+
+/// Removes `tombstone` and the `stale` tombstones on a background thread, returning a report of
+/// the rename alone.
+fn remove_in_background(
+    tombstone: &Path,
+    stale: Vec<PathBuf>,
+    options: &RemoveOptions,
+) -> io::Result<RemovalReport> {
+    // Only the rename is complete when this returns, so it alone is made durable here.
+    if options.durable {
+        if let Some(parent) = tombstone.parent() {
+            sync_dir(parent)?;
+        }
+    }
+
+    let options = options.clone();
+    let background_tombstone = tombstone.to_path_buf();
+    thread::spawn(move || remove_tombstones(&background_tombstone, &stale, &options, None));
+
+    Ok(RemovalReport {
+        root_removed: true,
+        tombstone: Some(tombstone.to_path_buf()),
+        ..RemovalReport::default()
+    })
+}
+
+/// Renames the entry at `path` to an unused tombstone name within the same directory, returning
+/// the path of the tombstone.
+///
+/// Tombstones left behind by an interrupted process are reclaimed by later tombstone removals in
+/// the same directory.
+pub(crate) fn rename_to_tombstone(path: &Path) -> io::Result<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Target path does not end in a directory name.",
+        )
+    })?;
+
+    create_tombstone(path, parent, name)
+}
+
+/// Renames the entry at `path` to an unused tombstone name within `parent`, returning the path of
+/// the tombstone.
+fn create_tombstone(path: &Path, parent: &Path, name: &OsStr) -> io::Result<PathBuf> {
+    loop {
+        let mut tombstone_name = OsString::from(format!(
+            "{}{}-{}-",
+            PREFIX,
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        tombstone_name.push(name);
+        let tombstone = parent.join(tombstone_name);
+
+        // Renaming a directory over an empty directory succeeds on Unix, so a leftover tombstone
+        // with the same name (from a previous process with the same ID) must be avoided here.
+        match fs::symlink_metadata(&tombstone) {
+            Ok(_) => continue,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        fs::rename(path, &tombstone)?;
+        #[cfg(windows)]
+        crate::windows::hide(&tombstone);
+
+        return Ok(tombstone);
+    }
+}
+
+/// Returns the ID of the process that created the tombstone with the given file name, or `None` if
+/// the name is not that of a tombstone.
+fn tombstone_owner(name: &OsStr) -> Option<u32> {
+    let name = name.to_string_lossy();
+    if !name.starts_with(PREFIX) {
+        return None;
+    }
+
+    let mut fields = name[PREFIX.len()..].splitn(3, '-');
+    let pid = fields.next()?.parse().ok()?;
+    fields.next()?.parse::<u64>().ok()?;
+    fields.next()?;
+    Some(pid)
+}
+
+/// Returns the tombstones within `parent` whose creating processes are no longer running.
+///
+/// Tombstones created by the current process are never considered stale, as they may still be
+/// removed by a background thread. Errors reading `parent` are ignored, since reclaiming stale
+/// tombstones is opportunistic.
+fn stale_tombstones(parent: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            tombstone_owner(&entry.file_name()).map_or(false, |pid| {
+                pid != process::id() && !is_process_running(pid)
+            })
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Removes `tombstone` followed by each of the `stale` tombstones, returning the combined report.
+///
+/// Errors removing stale tombstones are ignored, leaving them to be retried the next time around.
+fn remove_tombstones(
+    tombstone: &Path,
+    stale: &[PathBuf],
+    options: &RemoveOptions,
+    monitor: Option<&Arc<Monitor>>,
+) -> io::Result<RemovalReport> {
+    let mut engine = Engine::new(options).with_monitor(monitor.cloned());
+    let result = engine.remove_root(tombstone).and_then(|_| engine.finish());
+
+    let reclaimed = stale
+        .iter()
+        .filter(|path| {
+            let mut engine = Engine::new(options);
+            engine.remove_root(path).is_ok()
+                && engine
+                    .finish()
+                    .ok()
+                    .map_or(false, |report| report.root_removed)
+        })
+        .cloned()
+        .collect();
+
+    result.map(|report| RemovalReport {
+        reclaimed,
+        ..report
+    })
+}
+
+/// Returns whether a process with the given ID is running.
+#[cfg(unix)]
+pub(crate) fn is_process_running(pid: u32) -> bool {
+    // Signal 0 only checks whether the process exists, which it does if it belongs to another user
+    // and sending it a signal is not permitted.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// Returns whether a process with the given ID is running.
+#[cfg(windows)]
+pub(crate) fn is_process_running(pid: u32) -> bool {
+    crate::windows::is_process_running(pid)
+}
+
+/// Returns whether a process with the given ID is running, which cannot be determined on this
+/// platform, so every process is assumed to be.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn is_process_running(_pid: u32) -> bool {
+    true
+}
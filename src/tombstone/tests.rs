@@ -0,0 +1,171 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tombstone tests.
+
+use super::{tombstone_owner, TombstoneMode, PREFIX};
+use crate::{engine::tests::populate_dir, remove_dir_all_with, RemoveOptions};
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::Path,
+    process::{self, Command},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+/// Creates a directory at `path` containing a few entries.
+fn create_tree(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    populate_dir(path)
+}
+
+/// Returns the names of the entries within `dir_path`, sorted.
+fn entry_names(dir_path: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Tests whether tombstone names are parsed correctly.
+#[test]
+fn tombstone_names_are_parsed() {
+    assert_eq!(
+        tombstone_owner(OsStr::new(".rmdir-tombstone-1234-5-target")),
+        Some(1234)
+    );
+    assert_eq!(
+        tombstone_owner(OsStr::new(".rmdir-tombstone-1234-5-with-dashes")),
+        Some(1234)
+    );
+    assert_eq!(tombstone_owner(OsStr::new(".rmdir-tombstone-1234-5")), None);
+    assert_eq!(
+        tombstone_owner(OsStr::new(".rmdir-tombstone-abc-5-target")),
+        None
+    );
+    assert_eq!(tombstone_owner(OsStr::new("target")), None);
+}
+
+/// Tests whether synchronous tombstone removal frees the target path and removes the tombstone
+/// before returning.
+#[test]
+fn synchronous_removal_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+
+    let report = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new().tombstone(TombstoneMode::Synchronous),
+    )
+    .unwrap();
+    assert!(report.root_removed());
+    if cfg!(not(windows)) {
+        assert_eq!(report.files_removed(), 3);
+        assert_eq!(report.dirs_removed(), 2);
+    }
+
+    let tombstone = report.tombstone().unwrap();
+    assert_eq!(tombstone.parent(), Some(base_dir.path()));
+    assert_eq!(
+        tombstone_owner(tombstone.file_name().unwrap()),
+        Some(process::id())
+    );
+    assert!(entry_names(base_dir.path()).is_empty());
+}
+
+/// Tests whether background tombstone removal frees the target path before returning and
+/// eventually removes the tombstone.
+#[test]
+fn background_removal_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+
+    let report = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new().tombstone(TombstoneMode::Background),
+    )
+    .unwrap();
+    assert!(report.root_removed());
+    assert_eq!(
+        fs::symlink_metadata(&dir_path)
+            .err()
+            .map(|error| error.kind()),
+        Some(io::ErrorKind::NotFound)
+    );
+
+    // The path can be reused immediately.
+    fs::create_dir(&dir_path).unwrap();
+
+    let tombstone = report.tombstone().unwrap().to_path_buf();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while tombstone.exists() {
+        assert!(Instant::now() < deadline, "tombstone was never removed");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(entry_names(base_dir.path()), vec!["target"]);
+}
+
+/// Tests whether tombstones left by exited processes are reclaimed, while those of running
+/// processes are left alone.
+#[test]
+fn stale_tombstones_are_reclaimed() {
+    let base_dir = TempDir::new().unwrap();
+
+    // A process that has been waited on no longer exists, so its ID is free (barring reuse).
+    let mut child = if cfg!(windows) {
+        Command::new("cmd").args(&["/C", "exit"]).spawn().unwrap()
+    } else {
+        Command::new("true").spawn().unwrap()
+    };
+    let exited = child.id();
+    child.wait().unwrap();
+
+    let stale = base_dir.path().join(format!("{}{}-0-old", PREFIX, exited));
+    let live = base_dir
+        .path()
+        .join(format!("{}{}-0-busy", PREFIX, process::id()));
+    create_tree(&stale).unwrap();
+    create_tree(&live).unwrap();
+
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+    let report = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new().tombstone(TombstoneMode::Synchronous),
+    )
+    .unwrap();
+    assert_eq!(report.reclaimed(), &[stale][..]);
+    assert_eq!(
+        entry_names(base_dir.path()),
+        vec![live.file_name().unwrap().to_string_lossy().into_owned()]
+    );
+}
+
+/// Tests whether tombstones are refused when only Git-ignored entries are removed.
+#[test]
+fn gitignored_only_is_refused() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("target");
+    create_tree(&dir_path).unwrap();
+
+    let error = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new()
+            .gitignored_only(true)
+            .tombstone(TombstoneMode::Synchronous),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(dir_path.join("baz").join("qux").exists());
+}
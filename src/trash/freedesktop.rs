@@ -0,0 +1,713 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! [freedesktop.org Trash specification] implementation.
+//!
+//! Each trash directory contains a `files` directory holding the trashed entries themselves and an
+//! `info` directory holding a `.trashinfo` file for each entry, named after the entry. The info
+//! file is always created first (exclusively, which is what reserves the entry's name) and removed
+//! again if the entry cannot be moved into `files`.
+//!
+//! [freedesktop.org Trash specification]: https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+
+use crate::{engine::Engine, mounts::MountPoints, safeguard::Safeguards, RemoveOptions};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    },
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Sticky bit, which must be set on administrator-created `$topdir/.Trash` directories.
+const STICKY_BIT: u32 = 0o1000;
+
+/// File name extension of the info file describing each trashed entry.
+const INFO_EXTENSION: &str = ".trashinfo";
+
+/// Prefix of the line holding the original path of a trashed entry within its info file.
+const PATH_KEY: &[u8] = b"Path=";
+
+/// Prefix of the line holding the deletion date of a trashed entry within its info file.
+const DELETION_DATE_KEY: &[u8] = b"DeletionDate=";
+
+/// Trash of the current user, following the [freedesktop.org Trash specification].
+///
+/// The trash comprises the home trash (`$XDG_DATA_HOME/Trash`) and a trash directory at the top of
+/// each other filesystem, as used by [`trash_dir_all`](fn.trash_dir_all.html). Entries trashed by
+/// desktop environments and other applications following the specification can be managed as
+/// well.
+///
+/// This is only available on Linux and other Unix platforms except macOS. Trash directories on
+/// other filesystems are only discovered on Linux, where mount points can be listed.
+///
+/// [freedesktop.org Trash specification]: https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use win32_remove_dir_all::{RestoreConflict, Trash};
+///
+/// fn main() -> std::io::Result<()> {
+///     let trash = Trash::new()?;
+///     for entry in trash.entries()? {
+///         println!("{} was trashed from {}.", entry.path().display(), entry.original_path().display());
+///     }
+///
+///     // Empty everything trashed more than 30 days ago.
+///     trash.purge_older_than(Duration::from_secs(30 * 24 * 60 * 60))?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Trash {
+    /// Home trash directory, `$XDG_DATA_HOME/Trash`.
+    home: PathBuf,
+
+    /// Real user ID of the current process, used to name per-filesystem trash directories.
+    uid: u32,
+}
+
+/// Entry in the trash, along with the details recorded in its `.trashinfo` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TrashedEntry {
+    /// Trash directory containing the entry.
+    trash_dir: PathBuf,
+
+    /// Name of the entry within the trash directory's `files` directory.
+    name: OsString,
+
+    original_path: PathBuf,
+    deletion_date: Option<SystemTime>,
+}
+
+impl TrashedEntry {
+    /// Returns the name of the entry within the trash, which may differ from its original name if
+    /// other entries with the same name were trashed.
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Returns the current location of the entry within the trash.
+    pub fn path(&self) -> PathBuf {
+        self.trash_dir.join("files").join(&self.name)
+    }
+
+    /// Returns the absolute path the entry was trashed from.
+    pub fn original_path(&self) -> &Path {
+        &self.original_path
+    }
+
+    /// Returns when the entry was trashed, or `None` if its info file does not record a valid
+    /// date.
+    ///
+    /// Dates are recorded with a precision of one second.
+    pub fn deletion_date(&self) -> Option<SystemTime> {
+        self.deletion_date
+    }
+
+    /// Returns the path of the entry's `.trashinfo` file.
+    fn info_path(&self) -> PathBuf {
+        let mut info_name = self.name.clone();
+        info_name.push(INFO_EXTENSION);
+        self.trash_dir.join("info").join(info_name)
+    }
+}
+
+/// How [`Trash::restore`] handles an existing entry at the original path of a restored entry.
+///
+/// [`Trash::restore`]: struct.Trash.html#method.restore
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RestoreConflict {
+    /// Fail with `io::ErrorKind::AlreadyExists`, leaving both entries in place.
+    Fail,
+
+    /// Restore the entry next to the existing one, appending a numeric suffix to its name.
+    KeepBoth,
+
+    /// Move the existing entry to the trash before restoring the entry in its place.
+    Replace,
+}
+
+impl Trash {
+    /// Locates the trash of the current user using `XDG_DATA_HOME`, falling back to
+    /// `$HOME/.local/share` if it is unset or not absolute.
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::ErrorKind::NotFound` if neither `XDG_DATA_HOME` nor `HOME` is set.
+    pub fn new() -> io::Result<Self> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|data_home| data_home.is_absolute())
+            .or_else(|| {
+                env::var_os("HOME")
+                    .filter(|home| !home.is_empty())
+                    .map(|home| Path::new(&home).join(".local").join("share"))
+            })
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Unable to locate the home trash, as neither `XDG_DATA_HOME` nor `HOME` is set.",
+                )
+            })?;
+
+        Ok(Self::with_data_home(data_home))
+    }
+
+    /// Creates a handle to the trash of the current user with the given `XDG_DATA_HOME` directory.
+    pub fn with_data_home<P: AsRef<Path>>(data_home: P) -> Self {
+        Self {
+            home: data_home.as_ref().join("Trash"),
+            uid: unsafe { libc::getuid() },
+        }
+    }
+
+    /// Moves the file or directory at `path` to the trash without following symbolic links,
+    /// returning the trashed entry.
+    ///
+    /// Protected paths are refused in the same manner as
+    /// [`remove_dir_all`](fn.remove_dir_all.html).
+    pub fn trash<P: AsRef<Path>>(&self, path: P) -> io::Result<TrashedEntry> {
+        let path = path.as_ref();
+        Safeguards::default().check(path)?;
+        self.trash_entry(path)
+    }
+
+    /// Moves the entry at `path` to the trash without checking for protected paths.
+    pub(crate) fn trash_entry(&self, path: &Path) -> io::Result<TrashedEntry> {
+        let device = fs::symlink_metadata(path)?.dev();
+        let original_path = absolute_path(path)?;
+        let trash_dir = self.trash_dir_for(&original_path, device)?;
+
+        // Dates are only recorded to the second, so report the date as it will be read back.
+        let now = SystemTime::now();
+        let deletion_date = now
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| UNIX_EPOCH + Duration::from_secs(duration.as_secs()))
+            .unwrap_or(now);
+
+        let (name, info_path) = trash_dir.create_info(&original_path, deletion_date)?;
+        if let Err(error) = fs::rename(path, trash_dir.files().join(&name)) {
+            let _ = fs::remove_file(&info_path);
+            return Err(error);
+        }
+
+        Ok(TrashedEntry {
+            trash_dir: trash_dir.path,
+            name,
+            original_path,
+            deletion_date: Some(deletion_date),
+        })
+    }
+
+    /// Returns every entry in the home trash and in the trash directories of other filesystems.
+    ///
+    /// Entries whose info files cannot be parsed, or which have no info file, are not listed. Nor
+    /// are entries whose recorded paths contain `..` components, or which lie outside the top
+    /// directory of the filesystem holding their trash directory, since restoring them could move
+    /// entries to arbitrary locations.
+    pub fn entries(&self) -> io::Result<Vec<TrashedEntry>> {
+        let mut entries = Vec::new();
+        for trash_dir in self.trash_dirs()? {
+            trash_dir.read_entries(&mut entries)?;
+        }
+
+        Ok(entries)
+    }
+
+    /// Moves `entry` from the trash back to its original path, returning the path it was restored
+    /// to.
+    ///
+    /// Missing parent directories of the original path are recreated. If an entry already exists
+    /// at the original path, it is handled according to `conflict`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry is no longer in the trash, if an entry exists at the original
+    /// path and `conflict` is [`RestoreConflict::Fail`], or if the entry could not be moved (such
+    /// as if the original path is now on a different filesystem).
+    ///
+    /// [`RestoreConflict::Fail`]: enum.RestoreConflict.html#variant.Fail
+    pub fn restore(&self, entry: &TrashedEntry, conflict: RestoreConflict) -> io::Result<PathBuf> {
+        let source = entry.path();
+        fs::symlink_metadata(&source)?;
+
+        let mut destination = entry.original_path.clone();
+        if fs::symlink_metadata(&destination).is_ok() {
+            match conflict {
+                RestoreConflict::Fail => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("`{}` already exists.", destination.display()),
+                    ))
+                }
+                RestoreConflict::KeepBoth => destination = unused_sibling(&destination),
+                RestoreConflict::Replace => {
+                    self.trash(&destination)?;
+                }
+            }
+        }
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&source, &destination)?;
+        remove_info_file(entry)?;
+
+        Ok(destination)
+    }
+
+    /// Permanently removes `entry` from the trash, along with all its contents.
+    pub fn purge(&self, entry: &TrashedEntry) -> io::Result<()> {
+        let path = entry.path();
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) => {
+                let options = RemoveOptions::new();
+                Engine::new(&options).remove_entry(&path, metadata.file_type())?;
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        remove_info_file(entry)
+    }
+
+    /// Permanently removes every entry trashed more than `age` ago, returning the removed entries.
+    ///
+    /// Entries without a valid deletion date are left in place.
+    pub fn purge_older_than(&self, age: Duration) -> io::Result<Vec<TrashedEntry>> {
+        let cutoff = match SystemTime::now().checked_sub(age) {
+            Some(cutoff) => cutoff,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut purged = Vec::new();
+        for entry in self.entries()? {
+            if entry.deletion_date.map_or(false, |date| date <= cutoff) {
+                self.purge(&entry)?;
+                purged.push(entry);
+            }
+        }
+
+        Ok(purged)
+    }
+
+    /// Returns the trash directory to use for an entry at the absolute path `original` residing on
+    /// the given device, creating it if necessary.
+    fn trash_dir_for(&self, original: &Path, device: u64) -> io::Result<TrashDir> {
+        if nearest_device(&self.home)? == device {
+            let trash_dir = TrashDir {
+                path: self.home.clone(),
+                topdir: None,
+            };
+            trash_dir.create(self.uid)?;
+            return Ok(trash_dir);
+        }
+
+        let topdir = find_topdir(original, device)?;
+
+        // An administrator-created `$topdir/.Trash` is only used if it is a real directory with the
+        // sticky bit set, as otherwise other users could tamper with its contents.
+        let admin_dir = topdir.join(".Trash");
+        if fs::symlink_metadata(&admin_dir)
+            .ok()
+            .map_or(false, |metadata| {
+                metadata.is_dir() && metadata.mode() & STICKY_BIT != 0
+            })
+        {
+            let trash_dir = TrashDir {
+                path: admin_dir.join(self.uid.to_string()),
+                topdir: Some(topdir.clone()),
+            };
+            if trash_dir.create(self.uid).is_ok() {
+                return Ok(trash_dir);
+            }
+        }
+
+        let trash_dir = TrashDir {
+            path: topdir.join(format!(".Trash-{}", self.uid)),
+            topdir: Some(topdir),
+        };
+        trash_dir.create(self.uid)?;
+        Ok(trash_dir)
+    }
+
+    /// Returns every existing trash directory of the current user.
+    fn trash_dirs(&self) -> io::Result<Vec<TrashDir>> {
+        let mut trash_dirs = vec![TrashDir {
+            path: self.home.clone(),
+            topdir: None,
+        }];
+        for topdir in MountPoints::load()?.iter() {
+            for path in &[
+                topdir.join(".Trash").join(self.uid.to_string()),
+                topdir.join(format!(".Trash-{}", self.uid)),
+            ] {
+                if fs::symlink_metadata(path).ok().map_or(false, |metadata| {
+                    metadata.is_dir() && metadata.uid() == self.uid
+                }) {
+                    trash_dirs.push(TrashDir {
+                        path: path.clone(),
+                        topdir: Some(topdir.to_path_buf()),
+                    });
+                }
+            }
+        }
+
+        Ok(trash_dirs)
+    }
+}
+
+/// Single trash directory, containing `files` and `info` subdirectories.
+#[derive(Clone, Debug)]
+struct TrashDir {
+    /// Path of the trash directory itself.
+    path: PathBuf,
+
+    /// Top directory of the filesystem containing this trash directory, against which original
+    /// paths are recorded, or `None` for the home trash, which records absolute paths.
+    topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    /// Returns the directory containing the trashed entries themselves.
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    /// Returns the directory containing the `.trashinfo` file of each trashed entry.
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+
+    /// Creates this trash directory along with its subdirectories if they do not already exist,
+    /// verifying that it is a real directory owned by `uid`.
+    fn create(&self, uid: u32) -> io::Result<()> {
+        let mut builder = DirBuilder::new();
+        builder.recursive(true).mode(0o700);
+        builder.create(&self.path)?;
+
+        let metadata = fs::symlink_metadata(&self.path)?;
+        if !metadata.is_dir() || metadata.uid() != uid {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Trash directory `{}` is not a directory owned by the current user.",
+                    self.path.display()
+                ),
+            ));
+        }
+
+        builder.create(self.files())?;
+        builder.create(self.info())
+    }
+
+    /// Reserves a unique name for an entry trashed from the absolute path `original` by creating
+    /// its `.trashinfo` file, returning the name along with the path of the info file.
+    ///
+    /// Names are derived from the original file name, with a numeric suffix appended if an entry
+    /// of the same name is already in the trash.
+    fn create_info(
+        &self,
+        original: &Path,
+        deletion_date: SystemTime,
+    ) -> io::Result<(OsString, PathBuf)> {
+        let file_name = original.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Path does not name an entry.")
+        })?;
+        let recorded = match self.topdir {
+            Some(ref topdir) => original.strip_prefix(topdir).unwrap_or(original),
+            None => original,
+        };
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(recorded),
+            format_deletion_date(deletion_date)?
+        );
+
+        let mut index = 1u32;
+        loop {
+            let mut name = file_name.to_os_string();
+            if index > 1 {
+                name.push(format!(".{}", index));
+            }
+            index += 1;
+
+            let mut info_name = name.clone();
+            info_name.push(INFO_EXTENSION);
+            let info_path = self.info().join(info_name);
+            let mut file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&info_path)
+            {
+                Ok(file) => file,
+                Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => return Err(error),
+            };
+
+            // Entries left in `files` without an info file (such as by an interrupted trash
+            // operation) must not be overwritten either.
+            let result = if fs::symlink_metadata(self.files().join(&name)).is_ok() {
+                Ok(false)
+            } else {
+                file.write_all(contents.as_bytes()).map(|_| true)
+            };
+            match result {
+                Ok(true) => return Ok((name, info_path)),
+                Ok(false) => fs::remove_file(&info_path)?,
+                Err(error) => {
+                    let _ = fs::remove_file(&info_path);
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+impl TrashDir {
+    /// Appends every entry in this trash directory with a valid info file to `entries`.
+    fn read_entries(&self, entries: &mut Vec<TrashedEntry>) -> io::Result<()> {
+        let info_entries = match fs::read_dir(self.info()) {
+            Ok(info_entries) => info_entries,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        for info_entry in info_entries {
+            let info_entry = info_entry?;
+            let info_name = info_entry.file_name();
+            let info_name = info_name.as_bytes();
+            if info_name.len() <= INFO_EXTENSION.len()
+                || !info_name.ends_with(INFO_EXTENSION.as_bytes())
+            {
+                continue;
+            }
+            let name =
+                OsString::from_vec(info_name[..info_name.len() - INFO_EXTENSION.len()].to_vec());
+
+            // Info files are written by other applications as well, so unreadable or malformed
+            // ones are skipped rather than failing the entire listing.
+            let contents = match fs::read(info_entry.path()) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            if let Some((original_path, deletion_date)) = self.parse_info(&contents) {
+                entries.push(TrashedEntry {
+                    trash_dir: self.path.clone(),
+                    name,
+                    original_path,
+                    deletion_date,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the contents of a `.trashinfo` file, returning the absolute original path and
+    /// deletion date it records.
+    ///
+    /// Paths with `..` components are rejected, as are paths outside the top directory for trash
+    /// directories on other filesystems.
+    fn parse_info(&self, contents: &[u8]) -> Option<(PathBuf, Option<SystemTime>)> {
+        let mut in_group = false;
+        let mut path = None;
+        let mut deletion_date = None;
+        for line in contents.split(|&byte| byte == b'\n') {
+            let line = if line.ends_with(b"\r") {
+                &line[..line.len() - 1]
+            } else {
+                line
+            };
+            if line.starts_with(b"[") {
+                in_group = line == b"[Trash Info]";
+            } else if !in_group {
+                continue;
+            } else if line.starts_with(PATH_KEY) {
+                let value = decode_path(&line[PATH_KEY.len()..])?;
+                path = Some(PathBuf::from(OsString::from_vec(value)));
+            } else if line.starts_with(DELETION_DATE_KEY) {
+                deletion_date = parse_deletion_date(&line[DELETION_DATE_KEY.len()..]);
+            }
+        }
+
+        let path = path?;
+        if path.file_name().is_none()
+            || path
+                .components()
+                .any(|component| component == Component::ParentDir)
+        {
+            return None;
+        }
+
+        let original_path = match self.topdir {
+            Some(ref topdir) if path.is_absolute() && !path.starts_with(topdir) => return None,
+            _ if path.is_absolute() => path,
+            Some(ref topdir) => topdir.join(path),
+            None => return None,
+        };
+
+        Some((original_path, deletion_date))
+    }
+}
+
+/// Removes the `.trashinfo` file of `entry`, ignoring it if it no longer exists.
+fn remove_info_file(entry: &TrashedEntry) -> io::Result<()> {
+    match fs::remove_file(entry.info_path()) {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Returns the first path that does not exist yet among `path` with `.2`, `.3`, and so on
+/// appended.
+fn unused_sibling(path: &Path) -> PathBuf {
+    let mut index = 2u32;
+    loop {
+        let mut candidate = path.as_os_str().to_os_string();
+        candidate.push(format!(".{}", index));
+        let candidate = PathBuf::from(candidate);
+        if fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        index += 1;
+    }
+}
+
+/// Returns the absolute path of `path`, resolving symbolic links in every component except the
+/// last.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+            Ok(env::current_dir()?.join(name))
+        }
+        (Some(parent), Some(name)) => Ok(fs::canonicalize(parent)?.join(name)),
+        _ => fs::canonicalize(path),
+    }
+}
+
+/// Returns the device ID of the filesystem containing `path`, or of its nearest existing ancestor
+/// if `path` does not exist yet.
+fn nearest_device(path: &Path) -> io::Result<u64> {
+    let mut last_error = None;
+    for ancestor in path.ancestors() {
+        match fs::metadata(ancestor) {
+            Ok(metadata) => return Ok(metadata.dev()),
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+}
+
+/// Returns the top directory of the filesystem with the given device ID containing the absolute
+/// path `original`, which is its outermost ancestor on the same device.
+fn find_topdir(original: &Path, device: u64) -> io::Result<PathBuf> {
+    let mut topdir = original.parent().unwrap_or(original);
+    while let Some(parent) = topdir.parent() {
+        if fs::metadata(parent)?.dev() != device {
+            break;
+        }
+        topdir = parent;
+    }
+
+    Ok(topdir.to_path_buf())
+}
+
+/// Escapes `path` for the `Path` key of a `.trashinfo` file, percent-encoding every byte other
+/// than unreserved URI characters and `/`.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+/// Decodes a percent-encoded `Path` value from a `.trashinfo` file, returning `None` if it contains
+/// an invalid escape.
+fn decode_path(value: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let high = (*bytes.next()? as char).to_digit(16)?;
+            let low = (*bytes.next()? as char).to_digit(16)?;
+            decoded.push((high * 16 + low) as u8);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    Some(decoded)
+}
+
+/// Formats `time` for the `DeletionDate` key of a `.trashinfo` file, which uses the
+/// `YYYY-MM-DDThh:mm:ss` format in local time.
+fn format_deletion_date(time: SystemTime) -> io::Result<String> {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as libc::time_t)
+        .unwrap_or(0);
+
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    if unsafe { libc::localtime_r(&seconds, &mut tm) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    ))
+}
+
+/// Parses a `DeletionDate` value from a `.trashinfo` file, in the `YYYY-MM-DDThh:mm:ss` format and
+/// local time.
+fn parse_deletion_date(value: &[u8]) -> Option<SystemTime> {
+    let value = std::str::from_utf8(value).ok()?;
+    let mut fields = value.splitn(2, 'T');
+    let (date, time) = (fields.next()?, fields.next()?);
+    let mut date = date.splitn(3, '-').map(str::parse::<i32>);
+    let mut time = time.splitn(3, ':').map(str::parse::<i32>);
+
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    tm.tm_year = date.next()?.ok()? - 1900;
+    tm.tm_mon = date.next()?.ok()? - 1;
+    tm.tm_mday = date.next()?.ok()?;
+    tm.tm_hour = time.next()?.ok()?;
+    tm.tm_min = time.next()?.ok()?;
+    tm.tm_sec = time.next()?.ok()?;
+    tm.tm_isdst = -1;
+
+    let seconds = unsafe { libc::mktime(&mut tm) };
+    if seconds < 0 {
+        return None;
+    }
+
+    Some(UNIX_EPOCH + Duration::from_secs(seconds as u64))
+}
@@ -0,0 +1,106 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reversible removal by moving directories to the trash.
+//!
+//! On Windows, the Shell and Property System backends move directories to the Recycle Bin when
+//! `FOF_ALLOWUNDO` is set. Elsewhere, the [freedesktop.org Trash specification] is implemented
+//! directly, as there is no system API for it.
+//!
+//! [freedesktop.org Trash specification]: https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod freedesktop;
+#[cfg(test)]
+mod tests;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub use freedesktop::{RestoreConflict, Trash, TrashedEntry};
+
+use crate::{engine::is_dir_symlink, safeguard::Safeguards};
+use std::{fs, io, path::Path};
+
+/// Moves a directory at this path, along with all its contents, to the trash so that the removal
+/// can be undone by the user.
+///
+/// Like [`remove_dir_all`](fn.remove_dir_all.html), this function does **not** follow symbolic
+/// links; a directory symlink is moved to the trash itself, leaving its target in place. Protected
+/// paths are refused in the same manner as well.
+///
+/// # Platform-specific behavior
+///
+/// On Windows, this moves the directory to the Recycle Bin using either the `IFileOperation` COM
+/// interface or the `SHFileOperation` function with `FOF_ALLOWUNDO`. Note that the Shell silently
+/// deletes items that cannot be recycled, such as those on network shares or those exceeding the
+/// Recycle Bin's size limit.
+///
+/// On Linux and other Unix platforms except macOS, this implements the [freedesktop.org Trash
+/// specification]. Directories on the same filesystem as the home trash (`$XDG_DATA_HOME/Trash`)
+/// are moved there, while directories on other filesystems are moved to a `.Trash/$uid` or
+/// `.Trash-$uid` directory at the top of their filesystem. A `.trashinfo` file recording the
+/// original path and deletion date is written alongside each trashed directory, which desktop
+/// environments use to restore it. Trashed entries can be listed, restored, and purged using
+/// [`Trash`](struct.Trash.html).
+///
+/// Moving to the trash is not supported on macOS, where an `io::ErrorKind::Unsupported` error is
+/// returned.
+///
+/// [freedesktop.org Trash specification]: https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+///
+/// # Errors
+///
+/// Returns an error if the target does not exist, is not a directory or directory symlink
+/// (`io::ErrorKind::InvalidData`), is protected (`io::ErrorKind::PermissionDenied`), or could not
+/// be moved to the trash. Nothing is removed if an error is returned, apart from any entries the
+/// Windows backends removed before failing.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::trash_dir_all;
+///
+/// fn main() -> std::io::Result<()> {
+///     trash_dir_all("/some/dir")?;
+///     Ok(())
+/// }
+/// ```
+pub fn trash_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    Safeguards::default().check(path)?;
+
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if !file_type.is_dir() && !is_dir_symlink(path, file_type) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Target is not a directory or directory symlink.",
+        ));
+    }
+
+    trash(path)
+}
+
+/// Moves the directory at `path` to the Recycle Bin.
+#[cfg(windows)]
+fn trash(path: &Path) -> io::Result<()> {
+    crate::windows::trash_dir_all(path)
+}
+
+/// Moves the directory at `path` to the freedesktop.org trash of the current user.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn trash(path: &Path) -> io::Result<()> {
+    Trash::new()?.trash_entry(path).map(|_| ())
+}
+
+/// Reports that moving to the trash is unsupported on this platform.
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+fn trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Moving to the trash is not supported on this platform.",
+    ))
+}
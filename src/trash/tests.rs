@@ -0,0 +1,344 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Trash tests.
+
+use crate::trash_dir_all;
+use std::{fs, io};
+use tempfile::TempDir;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use {
+    super::{RestoreConflict, Trash, TrashedEntry},
+    crate::engine::tests::populate_dir,
+    std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// Creates a directory at `path` populated with a few files and a subdirectory.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn create_tree(path: &Path) {
+    fs::create_dir_all(path).unwrap();
+    populate_dir(path).unwrap();
+}
+
+/// Reads the `.trashinfo` file of the trashed entry at `trashed`, returning its `Path` and
+/// `DeletionDate` values.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn read_info(trashed: &Path) -> (String, String) {
+    let mut info_name = trashed.file_name().unwrap().to_os_string();
+    info_name.push(".trashinfo");
+    let info_path = trashed
+        .parent()
+        .unwrap()
+        .with_file_name("info")
+        .join(info_name);
+    let contents = fs::read_to_string(info_path).unwrap();
+
+    let mut lines = contents.lines();
+    assert_eq!(lines.next(), Some("[Trash Info]"));
+    let path = lines.next().unwrap().trim_start_matches("Path=");
+    let date = lines.next().unwrap().trim_start_matches("DeletionDate=");
+    (path.to_string(), date.to_string())
+}
+
+/// Returns the entries of `trash` residing in the home trash within `data_home`, sorted by name.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn home_entries(trash: &Trash, data_home: &Path) -> Vec<TrashedEntry> {
+    let mut entries: Vec<_> = trash
+        .entries()
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.path().starts_with(data_home))
+        .collect();
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+    entries
+}
+
+/// Tests whether `trash_dir_all` rejects regular files and missing targets without touching them.
+#[test]
+fn non_directories_fail() {
+    let base_dir = TempDir::new().unwrap();
+    let file_path = base_dir.path().join("file");
+    fs::write(&file_path, b"").unwrap();
+
+    assert_eq!(
+        trash_dir_all(&file_path).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert!(file_path.is_file());
+    assert_eq!(
+        trash_dir_all(base_dir.path().join("missing"))
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::NotFound
+    );
+}
+
+/// Tests whether directories on the same filesystem as `XDG_DATA_HOME` are moved to the home trash
+/// along with an info file, with name conflicts resolved using numeric suffixes.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn home_trash_works() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let data_home = TempDir::new().unwrap();
+    let base_dir = TempDir::new().unwrap();
+    let trash = Trash::with_data_home(data_home.path());
+    let target = base_dir.path().join("my dir");
+    let files_dir = data_home.path().join("Trash").join("files");
+
+    create_tree(&target);
+    let trashed = trash.trash(&target).unwrap().path();
+    assert_eq!(trashed, files_dir.join("my dir"));
+    assert!(!target.exists());
+    assert!(trashed.join("baz/qux").is_file());
+
+    let (path, date) = read_info(&trashed);
+    let expected = fs::canonicalize(base_dir.path()).unwrap().join("my%20dir");
+    assert_eq!(Path::new(&path), expected);
+    assert_eq!(date.len(), "YYYY-MM-DDThh:mm:ss".len());
+    assert_eq!(&date[10..11], "T");
+
+    let mode = fs::metadata(data_home.path().join("Trash"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o700);
+
+    create_tree(&target);
+    let trashed = trash.trash(&target).unwrap().path();
+    assert_eq!(trashed, files_dir.join("my dir.2"));
+    assert_eq!(Path::new(&read_info(&trashed).0), expected);
+}
+
+/// Tests whether directory symlinks are moved to the trash without their targets.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn symlinks_are_not_followed() {
+    let data_home = TempDir::new().unwrap();
+    let base_dir = TempDir::new().unwrap();
+    let target = base_dir.path().join("target");
+    let link = base_dir.path().join("link");
+    create_tree(&target);
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    let trashed = Trash::with_data_home(data_home.path())
+        .trash(&link)
+        .unwrap()
+        .path();
+    assert!(fs::symlink_metadata(&trashed)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert!(fs::symlink_metadata(&link).is_err());
+    assert!(target.join("baz/qux").is_file());
+}
+
+/// Tests whether directories on other filesystems are moved to a `.Trash-$uid` directory at the
+/// top of their filesystem, or to an administrator-created `.Trash` directory with the sticky bit
+/// set, with paths recorded relative to the top directory, and whether recorded paths outside the
+/// top directory are not listed.
+#[test]
+#[cfg(target_os = "linux")]
+fn topdir_trash_works() {
+    use std::{
+        os::unix::fs::PermissionsExt,
+        process::{Command, Stdio},
+    };
+
+    let data_home = TempDir::new().unwrap();
+    let mount_dir = TempDir::new().unwrap();
+    let mount_path = mount_dir.path();
+
+    let mounted = Command::new("mount")
+        .args(&["-t", "tmpfs", "none"])
+        .arg(mount_path)
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+        .map_or(false, |status| status.success());
+    if !mounted {
+        return;
+    }
+
+    let uid = unsafe { libc::getuid() };
+    let trash = Trash::with_data_home(data_home.path());
+    let target = mount_path.join("sub").join("target");
+    let run = || -> io::Result<_> {
+        create_tree(&target);
+        let user_trashed = trash.trash(&target)?.path();
+
+        let admin_dir = mount_path.join(".Trash");
+        fs::create_dir(&admin_dir)?;
+        fs::set_permissions(&admin_dir, fs::Permissions::from_mode(0o1777))?;
+        create_tree(&target);
+        let admin_trashed = trash.trash(&target)?.path();
+
+        let info_dir = mount_path.join(format!(".Trash-{}", uid)).join("info");
+        for (name, path) in [("outside", "/etc/passwd"), ("parent", "sub/../../x")].iter() {
+            let contents = format!("[Trash Info]\nPath={}\n", path);
+            fs::write(info_dir.join(format!("{}.trashinfo", name)), contents)?;
+        }
+        let mut listed: Vec<_> = trash
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.path().starts_with(mount_path))
+            .map(|entry| entry.original_path().to_path_buf())
+            .collect();
+        listed.sort();
+
+        Ok((user_trashed, admin_trashed, listed))
+    };
+    let result = run();
+    let user_info = result
+        .as_ref()
+        .ok()
+        .map(|(trashed, _, _)| read_info(trashed));
+    Command::new("umount").arg(mount_path).status().unwrap();
+
+    let (user_trashed, admin_trashed, listed) = result.unwrap();
+    assert_eq!(
+        user_trashed,
+        mount_path
+            .join(format!(".Trash-{}", uid))
+            .join("files")
+            .join("target")
+    );
+    assert_eq!(
+        admin_trashed,
+        mount_path
+            .join(".Trash")
+            .join(uid.to_string())
+            .join("files")
+            .join("target")
+    );
+    assert_eq!(user_info.unwrap().0, "sub/target");
+    assert_eq!(listed, vec![target.clone(), target]);
+}
+
+/// Tests whether trashed entries are listed with their original paths and deletion dates, and
+/// whether malformed info files are skipped.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn listing_works() {
+    let data_home = TempDir::new().unwrap();
+    let base_dir = TempDir::new().unwrap();
+    let base_path = fs::canonicalize(base_dir.path()).unwrap();
+    let trash = Trash::with_data_home(data_home.path());
+    assert!(home_entries(&trash, data_home.path()).is_empty());
+
+    let before = SystemTime::now() - Duration::from_secs(1);
+    create_tree(&base_path.join("first"));
+    create_tree(&base_path.join("second %"));
+    let trashed = trash.trash(base_path.join("first")).unwrap();
+    trash.trash(base_path.join("second %")).unwrap();
+
+    let info_dir = data_home.path().join("Trash").join("info");
+    fs::write(info_dir.join("bogus.trashinfo"), b"[Other]\nPath=/x\n").unwrap();
+    fs::write(
+        info_dir.join("escaping.trashinfo"),
+        format!("[Trash Info]\nPath={}/../x\n", base_path.display()),
+    )
+    .unwrap();
+    fs::write(info_dir.join("stray"), b"").unwrap();
+
+    let entries = home_entries(&trash, data_home.path());
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0], trashed);
+    assert_eq!(entries[0].original_path(), base_path.join("first"));
+    assert_eq!(entries[1].original_path(), base_path.join("second %"));
+    assert!(entries[1].path().join("baz/qux").is_file());
+
+    let deletion_date = entries[1].deletion_date().unwrap();
+    assert!(deletion_date >= before && deletion_date <= SystemTime::now());
+}
+
+/// Tests whether entries are restored to their original paths, recreating missing parents and
+/// handling conflicts according to the requested policy.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn restoring_works() {
+    let data_home = TempDir::new().unwrap();
+    let base_dir = TempDir::new().unwrap();
+    let trash = Trash::with_data_home(data_home.path());
+    let target = base_dir.path().join("parent").join("target");
+
+    create_tree(&target);
+    let entry = trash.trash(&target).unwrap();
+    fs::remove_dir(base_dir.path().join("parent")).unwrap();
+    assert_eq!(
+        trash.restore(&entry, RestoreConflict::Fail).unwrap(),
+        entry.original_path()
+    );
+    assert!(target.join("baz/qux").is_file());
+    assert!(home_entries(&trash, data_home.path()).is_empty());
+
+    let entry = trash.trash(&target).unwrap();
+    fs::create_dir(&target).unwrap();
+    let error = trash.restore(&entry, RestoreConflict::Fail).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    assert!(entry.path().is_dir());
+
+    let restored = trash.restore(&entry, RestoreConflict::KeepBoth).unwrap();
+    assert_eq!(restored, entry.original_path().with_file_name("target.2"));
+    assert!(restored.join("baz/qux").is_file());
+    assert!(target.is_dir());
+
+    let entry = trash.trash(&restored).unwrap();
+    fs::create_dir(&restored).unwrap();
+    fs::write(restored.join("occupant"), b"").unwrap();
+    trash.restore(&entry, RestoreConflict::Replace).unwrap();
+    assert!(restored.join("baz/qux").is_file());
+    assert!(!restored.join("occupant").exists());
+
+    let entries = home_entries(&trash, data_home.path());
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].path().join("occupant").is_file());
+}
+
+/// Tests whether purging removes entries and their info files, optionally limited to entries older
+/// than a given age.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn purging_works() {
+    let data_home = TempDir::new().unwrap();
+    let base_dir = TempDir::new().unwrap();
+    let trash = Trash::with_data_home(data_home.path());
+
+    create_tree(&base_dir.path().join("recent"));
+    let recent = trash.trash(base_dir.path().join("recent")).unwrap();
+
+    // Trash an entry "written" by another application long ago.
+    let trash_dir = data_home.path().join("Trash");
+    create_tree(&trash_dir.join("files").join("old"));
+    fs::write(
+        trash_dir.join("info").join("old.trashinfo"),
+        "[Trash Info]\nPath=/somewhere/old\nDeletionDate=2000-01-01T00:00:00\n",
+    )
+    .unwrap();
+
+    let purged = trash
+        .purge_older_than(Duration::from_secs(30 * 24 * 60 * 60))
+        .unwrap();
+    let purged: Vec<_> = purged
+        .into_iter()
+        .filter(|entry| entry.path().starts_with(data_home.path()))
+        .collect();
+    assert_eq!(purged.len(), 1);
+    assert_eq!(purged[0].original_path(), Path::new("/somewhere/old"));
+    assert!(!trash_dir.join("files").join("old").exists());
+    assert!(!trash_dir.join("info").join("old.trashinfo").exists());
+    assert_eq!(home_entries(&trash, data_home.path()), vec![recent.clone()]);
+
+    trash.purge(&recent).unwrap();
+    assert!(!recent.path().exists());
+    assert!(home_entries(&trash, data_home.path()).is_empty());
+}
@@ -0,0 +1,163 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Waiting for removed paths to disappear from the filesystem.
+//!
+//! Windows only removes a file or directory once the last handle to it is closed, so a removal
+//! that succeeds while another process (such as an antivirus scanner or search indexer) holds the
+//! entry open leaves it pending deletion, and creating a new entry at the same path fails in the
+//! meantime. The path is polled with exponential backoff until it is gone or a timeout elapses.
+//! Polling goes through a [`Probe`] so that it can be tested without a real filesystem or clock.
+
+#[cfg(test)]
+mod tests;
+
+use crate::Error;
+use std::{
+    fs, io,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Delay before the second attempt when polling.
+const INITIAL_DELAY: Duration = Duration::from_millis(1);
+
+/// Longest delay between consecutive attempts when polling.
+const MAX_DELAY: Duration = Duration::from_millis(50);
+
+/// Source of time used while polling.
+pub(crate) trait Clock {
+    /// Returns the time elapsed since polling began.
+    fn elapsed(&self) -> Duration;
+
+    /// Blocks for the given duration.
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// Source of filesystem state and time used while waiting.
+pub(crate) trait Probe: Clock {
+    /// Returns whether an entry is still present at `path`.
+    fn exists(&mut self, path: &Path) -> io::Result<bool>;
+}
+
+/// [`Clock`] and [`Probe`] backed by the real clock and filesystem.
+pub(crate) struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Creates a clock measuring time elapsed from now.
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+impl Probe for SystemClock {
+    fn exists(&mut self, path: &Path) -> io::Result<bool> {
+        match fs::symlink_metadata(path) {
+            Ok(_) => Ok(true),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+            // Entries pending deletion cannot be opened, even to read their attributes.
+            #[cfg(windows)]
+            Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied => Ok(true),
+            #[cfg(unix)]
+            Err(ref error) if error.raw_os_error() == Some(libc::ENOTDIR) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Calls `attempt` until it returns `true`, or until `timeout` elapses according to `clock`, and
+/// returns whether an attempt succeeded.
+///
+/// The first attempt is made immediately, followed by attempts with delays doubling from
+/// [`INITIAL_DELAY`] up to [`MAX_DELAY`] between them.
+pub(crate) fn poll<C, F>(timeout: Duration, clock: &mut C, mut attempt: F) -> io::Result<bool>
+where
+    C: Clock,
+    F: FnMut(&mut C) -> io::Result<bool>,
+{
+    let mut delay = INITIAL_DELAY;
+    loop {
+        if attempt(clock)? {
+            return Ok(true);
+        }
+
+        let elapsed = clock.elapsed();
+        if elapsed >= timeout {
+            return Ok(false);
+        }
+
+        clock.sleep(delay.min(timeout - elapsed));
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
+
+/// Blocks until nothing exists at `path`, or until `timeout` elapses.
+///
+/// The path is checked immediately, then repeatedly with delays doubling from 1 millisecond up to
+/// 50 milliseconds between checks. This is useful after removing a path on Windows, where an entry
+/// held open by another process lingers in a "delete pending" state until every handle to it is
+/// closed, causing attempts to create a new entry at the same path to fail. On other platforms,
+/// removals take effect immediately, so this only waits if another process recreates the path.
+///
+/// [`RemoveOptions::wait_until_gone`](struct.RemoveOptions.html#method.wait_until_gone) waits
+/// for the target of a removal in the same way before returning.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::TimedOut` error carrying an
+/// [`Error::StillExists`](enum.Error.html#variant.StillExists) if the path is still present once
+/// `timeout` has elapsed, or any error encountered while checking for the path.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::{fs, time::Duration};
+/// use win32_remove_dir_all::{remove_dir_all, wait_until_gone};
+///
+/// fn main() -> std::io::Result<()> {
+///     remove_dir_all("cache")?;
+///     wait_until_gone("cache", Duration::from_secs(5))?;
+///     fs::create_dir("cache")
+/// }
+/// ```
+pub fn wait_until_gone<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<()> {
+    wait_with_probe(path.as_ref(), timeout, &mut SystemClock::start())
+}
+
+/// Blocks until `probe` reports that nothing exists at `path`, or until `timeout` elapses
+/// according to `probe`.
+pub(crate) fn wait_with_probe<P: Probe>(
+    path: &Path,
+    timeout: Duration,
+    probe: &mut P,
+) -> io::Result<()> {
+    if poll(timeout, probe, |probe| Ok(!probe.exists(path)?))? {
+        Ok(())
+    } else {
+        Err(Error::StillExists {
+            path: path.to_path_buf(),
+            timeout,
+        }
+        .into())
+    }
+}
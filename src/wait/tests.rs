@@ -0,0 +1,152 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Removal completion tests.
+
+use super::{wait_until_gone, wait_with_probe, Clock, Probe};
+use crate::{engine::tests::populate_dir, remove_dir_all_with, Error, RemoveOptions};
+use std::{fs, io, path::Path, time::Duration};
+use tempfile::TempDir;
+
+/// Simulated filesystem and clock, reporting a path as present for a fixed number of checks.
+#[derive(Default)]
+struct MockProbe {
+    /// Number of checks remaining for which the path is reported as present.
+    present_for: usize,
+
+    /// Error to return from the next check, if any.
+    error: Option<io::Error>,
+
+    /// Simulated time elapsed.
+    clock: Duration,
+
+    /// Every sleep requested, in order.
+    sleeps: Vec<Duration>,
+}
+
+impl Probe for MockProbe {
+    fn exists(&mut self, _path: &Path) -> io::Result<bool> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        // Checks take time too.
+        self.clock += Duration::from_micros(100);
+        if self.present_for == 0 {
+            return Ok(false);
+        }
+        self.present_for -= 1;
+        Ok(true)
+    }
+}
+
+impl Clock for MockProbe {
+    fn elapsed(&self) -> Duration {
+        self.clock
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.clock += duration;
+        self.sleeps.push(duration);
+    }
+}
+
+/// Returns the given numbers of milliseconds as durations.
+fn millis(values: &[u64]) -> Vec<Duration> {
+    values.iter().copied().map(Duration::from_millis).collect()
+}
+
+/// Tests whether waiting for an absent path returns without sleeping.
+#[test]
+fn absent_path_returns_immediately() {
+    let mut probe = MockProbe::default();
+    wait_with_probe(Path::new("gone"), Duration::from_secs(1), &mut probe).unwrap();
+    assert!(probe.sleeps.is_empty());
+}
+
+/// Tests whether the delay between checks doubles until reaching its limit.
+#[test]
+fn polling_backs_off() {
+    let mut probe = MockProbe {
+        present_for: 9,
+        ..MockProbe::default()
+    };
+    wait_with_probe(Path::new("pending"), Duration::from_secs(10), &mut probe).unwrap();
+    assert_eq!(probe.sleeps, millis(&[1, 2, 4, 8, 16, 32, 50, 50, 50]));
+}
+
+/// Tests whether a path that remains present results in a distinct timeout error, without
+/// sleeping past the timeout.
+#[test]
+fn lingering_path_times_out() {
+    let mut probe = MockProbe {
+        present_for: std::usize::MAX,
+        ..MockProbe::default()
+    };
+    let timeout = Duration::from_millis(10);
+    let error = wait_with_probe(Path::new("pending"), timeout, &mut probe).unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    match Error::from_io_error(&error) {
+        Some(Error::StillExists {
+            path,
+            timeout: waited,
+        }) => {
+            assert_eq!(path, Path::new("pending"));
+            assert_eq!(*waited, timeout);
+        }
+        other => panic!("unexpected error details: {:?}", other),
+    }
+    assert!(probe.clock >= timeout);
+    assert!(probe.clock < timeout + Duration::from_millis(1));
+    assert_eq!(&probe.sleeps[..3], &millis(&[1, 2, 4])[..]);
+
+    let mut probe = MockProbe {
+        present_for: 1,
+        ..MockProbe::default()
+    };
+    let error =
+        wait_with_probe(Path::new("pending"), Duration::from_secs(0), &mut probe).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert!(probe.sleeps.is_empty());
+}
+
+/// Tests whether errors checking for the path are returned.
+#[test]
+fn probe_errors_are_returned() {
+    let mut probe = MockProbe {
+        error: Some(io::Error::from(io::ErrorKind::PermissionDenied)),
+        ..MockProbe::default()
+    };
+    let error =
+        wait_with_probe(Path::new("hidden"), Duration::from_secs(1), &mut probe).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+}
+
+/// Tests whether waiting works against the real filesystem, both standalone and as a removal
+/// option.
+#[test]
+fn wait_until_gone_works() {
+    let base_dir = TempDir::new().unwrap();
+    let dir_path = base_dir.path().join("dir");
+    fs::create_dir(&dir_path).unwrap();
+    populate_dir(&dir_path).unwrap();
+
+    let error = wait_until_gone(&dir_path, Duration::from_millis(5)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+    let report = remove_dir_all_with(
+        &dir_path,
+        RemoveOptions::new().wait_until_gone(Duration::from_secs(1)),
+    )
+    .unwrap();
+    assert!(report.root_removed());
+    assert!(!dir_path.exists());
+    wait_until_gone(&dir_path, Duration::from_secs(0)).unwrap();
+    wait_until_gone(dir_path.join("child"), Duration::from_secs(0)).unwrap();
+}
@@ -6,8 +6,10 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Windows-specific `remove_dir_all` implementation.
+//! Windows-specific `remove_dir_all` and `copy_dir_all` implementations, along with Restart
+//! Manager holder identification.
 
+mod restart;
 mod shell;
 
 #[cfg(feature = "property_system_api")]
@@ -16,8 +18,23 @@ mod property;
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "property_system_api")]
+pub(crate) use property::ComApartment;
+
+pub(crate) use restart::find_holders;
+
 use std::{io, iter, os::windows::ffi::OsStrExt, path::Path, ptr};
-use winapi::um::fileapi::GetFullPathNameW;
+use winapi::um::{
+    fileapi::{
+        GetFileAttributesW, GetFullPathNameW, GetVolumeNameForVolumeMountPointW,
+        SetFileAttributesW, INVALID_FILE_ATTRIBUTES,
+    },
+    handleapi::CloseHandle,
+    minwinbase::STILL_ACTIVE,
+    processthreadsapi::{GetExitCodeProcess, OpenProcess},
+    shellapi::{FILEOP_FLAGS, FOF_ALLOWUNDO, FOF_NO_UI},
+    winnt::{FILE_ATTRIBUTE_HIDDEN, PROCESS_QUERY_LIMITED_INFORMATION},
+};
 
 const EXTENDED_PATH_PREFIX: [u16; 4] = [b'\\' as _, b'\\' as _, b'?' as _, b'\\' as _];
 
@@ -92,47 +109,125 @@ fn strip_extended_length_path_prefix(path: &[u16]) -> &[u16] {
     }
 }
 
-/// Removes a directory at this path, after removing all its contents. Use
-/// carefully!
-///
-/// This function does **not** follow symbolic links and it will simply remove the
-/// symbolic link itself.
-///
-/// # Platform-specific behavior
-///
-/// This function currently corresponds to `opendir`, `lstat`, `rm` and `rmdir` functions on Unix,
-/// and either the `SHFileOperation` function or the `IFileOperation` COM interface on Windows
-/// depending on the Windows version used at runtime.
-/// Note that, this [may change in the future][changes].
+/// Returns whether the directory reparse point at `path` is a volume mount point (a mounted folder)
+/// rather than a junction or symbolic link.
 ///
-/// [changes]: ../io/index.html#platform-specific-behavior
-///
-/// # Errors
+/// `GetVolumeNameForVolumeMountPointW` only succeeds for volume mount points and drive roots.
+pub(crate) fn is_volume_mount_point(path: &Path) -> bool {
+    let mut path = match resolve_absolute_path_utf16(path) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+
+    // The path must include a trailing backslash.
+    path.pop();
+    if path.last() != Some(&(b'\\' as _)) {
+        path.push(b'\\' as _);
+    }
+    path.push(0);
+
+    let mut volume_name = [0u16; 50];
+    unsafe {
+        GetVolumeNameForVolumeMountPointW(
+            strip_extended_length_path_prefix(&path).as_ptr(),
+            volume_name.as_mut_ptr(),
+            volume_name.len() as _,
+        ) != 0
+    }
+}
+
+/// Sets the hidden attribute of the file at `path`, ignoring any errors.
+pub(crate) fn hide(path: &Path) {
+    let path = match resolve_absolute_path_utf16(path) {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+
+    unsafe {
+        let attributes = GetFileAttributesW(path.as_ptr());
+        if attributes != INVALID_FILE_ATTRIBUTES {
+            SetFileAttributesW(path.as_ptr(), attributes | FILE_ATTRIBUTE_HIDDEN);
+        }
+    }
+}
+
+/// Returns whether a process with the given ID is running.
 ///
-/// See [`fs::remove_file`] and [`fs::remove_dir`].
+/// Processes that cannot be opened due to insufficient access are assumed to be running.
+pub(crate) fn is_process_running(pid: u32) -> bool {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return io::Error::last_os_error().kind() == io::ErrorKind::PermissionDenied;
+        }
+
+        let mut exit_code = 0;
+        let running = GetExitCodeProcess(process, &mut exit_code) == 0 || exit_code == STILL_ACTIVE;
+        CloseHandle(process);
+        running
+    }
+}
+
+/// Removes a directory and all of its contents using `IFileOperation` if supported, falling back
+/// to `SHFileOperationW` otherwise.
 ///
-/// [`fs::remove_file`]:  fn.remove_file.html
-/// [`fs::remove_dir`]: fn.remove_dir.html
+/// Directory symlinks are removed without following them, while any other type of file is
+/// rejected with `io::ErrorKind::InvalidData`.
+pub(crate) fn remove_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    delete_dir_all(path.as_ref(), FOF_NO_UI)
+}
+
+/// Moves a directory and all of its contents to the Recycle Bin, with the same semantics as
+/// [`remove_dir_all`].
 ///
-/// # Examples
+/// The Shell permanently deletes items that cannot be recycled, such as those on network shares or
+/// exceeding the Recycle Bin's size limit, without reporting an error.
+pub(crate) fn trash_dir_all(path: &Path) -> io::Result<()> {
+    delete_dir_all(path, FOF_NO_UI | FOF_ALLOWUNDO)
+}
+
+/// Removes several directories and all of their contents in a single operation, using
+/// `IFileOperation` if supported and falling back to `SHFileOperationW` otherwise.
 ///
-/// ```no_run
-/// use std::fs;
+/// Nothing is removed if any of the paths is not a directory or directory symlink, but if the
+/// operation fails partway through, some of the directories may have been removed.
+pub(crate) fn remove_dirs_all(paths: &[&Path]) -> io::Result<()> {
+    #[cfg(feature = "property_system_api")]
+    {
+        if property::remove_dirs_all(paths, FOF_NO_UI)?.is_some() {
+            return Ok(());
+        }
+    }
+
+    shell::remove_dirs_all(paths, FOF_NO_UI)
+}
+
+/// Copies the items at `sources`, along with all their contents, into the existing directory at
+/// `destination` in a single operation, using `IFileOperation` if supported and falling back to
+/// `SHFileOperationW` otherwise.
 ///
-/// fn main() -> std::io::Result<()> {
-///     fs::remove_dir_all("/some/dir")?;
-///     Ok(())
-/// }
-/// ```
-pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
-    let path = path.as_ref();
+/// Existing files are replaced and existing directories are merged. The Shell copies the targets
+/// of symbolic links and junctions rather than the links themselves.
+pub(crate) fn copy_items(sources: &[&Path], destination: &Path) -> io::Result<()> {
+    #[cfg(feature = "property_system_api")]
+    {
+        if property::copy_items(sources, destination, FOF_NO_UI)?.is_some() {
+            return Ok(());
+        }
+    }
+
+    shell::copy_items(sources, destination, FOF_NO_UI)
+}
 
+/// Deletes a directory using `IFileOperation` if supported, falling back to `SHFileOperationW`
+/// otherwise, with the given operation flags.
+fn delete_dir_all(path: &Path, flags: FILEOP_FLAGS) -> io::Result<()> {
     #[cfg(feature = "property_system_api")]
     {
-        if property::remove_dir_all(path)?.is_some() {
+        if property::remove_dir_all(path, flags)?.is_some() {
             return Ok(());
         }
     }
 
-    shell::remove_dir_all(path)
+    shell::remove_dir_all(path, flags)
 }
@@ -6,12 +6,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! `remove_dir_all` implementation using `IFileOperation` from the Property System API.
+//! Directory removal and copying using `IFileOperation` from the Property System API.
 
 use super::{resolve_absolute_path_utf16, strip_extended_length_path_prefix};
 use std::{
     cell::Cell,
-    io, mem,
+    io,
+    marker::PhantomData,
+    mem,
     ops::Deref,
     os::raw::{c_char, c_void},
     path::Path,
@@ -32,7 +34,7 @@ use winapi::{
         libloaderapi::{GetModuleHandleW, GetProcAddress},
         objbase::COINIT_APARTMENTTHREADED,
         objidl::IBindCtx,
-        shellapi::FOF_NO_UI,
+        shellapi::FILEOP_FLAGS,
         shobjidl_core::{FileOperation, IShellItem},
         unknwnbase::{IUnknown, IUnknownVtbl},
         winnt::{HRESULT, LPCWSTR, PCWSTR},
@@ -320,6 +322,44 @@ fn hresult_to_result(result: HRESULT, context: &str) -> io::Result<HRESULT> {
     }
 }
 
+thread_local! {
+    /// Whether the current thread has been initialized for apartment-threaded COM by a
+    /// `ComApartment`.
+    static COM_APARTMENT_ACTIVE: Cell<bool> = Cell::new(false);
+}
+
+/// Apartment-threaded COM initialization of the current thread, uninitialized when dropped.
+pub(crate) struct ComApartment {
+    /// `CoUninitialize` must be called on the same thread as `CoInitializeEx`.
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl ComApartment {
+    /// Initializes the current thread for apartment-threaded COM.
+    pub fn initialize() -> io::Result<Self> {
+        unsafe {
+            hresult_to_result(
+                CoInitializeEx(ptr::null_mut(), COINIT_APARTMENTTHREADED),
+                "`CoInitializeEx`",
+            )?;
+        }
+        COM_APARTMENT_ACTIVE.with(|active| active.set(true));
+
+        Ok(Self {
+            _not_send: PhantomData,
+        })
+    }
+}
+
+impl Drop for ComApartment {
+    fn drop(&mut self) {
+        COM_APARTMENT_ACTIVE.with(|active| active.set(false));
+        unsafe {
+            CoUninitialize();
+        }
+    }
+}
+
 /// `SHCreateItemFromParsingName` function pointer.
 ///
 /// Presence of this function is used to determine whether the Windows Property System is present,
@@ -330,13 +370,114 @@ static mut SH_CREATE_ITEM_FROM_PARSING_NAME_OPT: Option<SHCreateItemFromParsingN
 /// Single-initialization for `SH_CREATE_ITEM_FROM_PARSING_NAME`.
 static SH_CREATE_ITEM_FROM_PARSING_NAME_INIT: Once = Once::new();
 
-/// Deletes a directory and all of its contents using `IFileOperation` if supported.
+/// Deletes a directory and all of its contents using `IFileOperation` with the given operation
+/// flags if supported.
 ///
 /// Returns one of the following:
 /// - `Ok(Some(()))`: Directory deletion succeeded.
 /// - `Err(error)`: Directory deletion failed.
 /// - `Ok(None)`: `IFileOperation` is not supported.
-pub fn remove_dir_all(path: &Path) -> io::Result<Option<()>> {
+pub fn remove_dir_all(path: &Path, flags: FILEOP_FLAGS) -> io::Result<Option<()>> {
+    remove_dirs_all(&[path], flags)
+}
+
+/// Deletes several directories and all of their contents in a single `IFileOperation` with the
+/// given operation flags if supported, with the same return values as [`remove_dir_all`].
+///
+/// Nothing is deleted if any of the paths is not a directory or directory symlink.
+pub fn remove_dirs_all(paths: &[&Path], flags: FILEOP_FLAGS) -> io::Result<Option<()>> {
+    let paths = paths
+        .iter()
+        .map(|path| resolve_absolute_path_utf16(path))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    with_file_operation(
+        flags,
+        move |sh_create_item_from_parsing_name, file_op| unsafe {
+            let bind_ctx = create_bind_ctx()?;
+            let mut items = Vec::with_capacity(paths.len());
+            for path in &paths {
+                let item = create_item(sh_create_item_from_parsing_name, &bind_ctx, path)?;
+
+                // Double-check the target is a valid filesystem directory. Since Windows distinguishes
+                // between file and folder symbolic links, `SFGAO_FOLDER` should be set regardless of
+                // whether the target is a symbolic link. `GetAttributes` will return `S_OK` if and only
+                // if the attributes match exactly.
+                let mut attributes = 0;
+                if item.GetAttributes(SFGAO_FOLDER | SFGAO_FILESYSTEM, &mut attributes) != S_OK {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Target is not a directory or directory symlink.",
+                    ));
+                }
+
+                items.push(item);
+            }
+
+            for item in &items {
+                hresult_to_result(
+                    file_op.DeleteItem(item.as_ptr(), ptr::null_mut()),
+                    "`IFileOperation::DeleteItem()`",
+                )?;
+            }
+
+            Ok(())
+        },
+    )
+}
+
+/// Copies the items at `sources` (along with all their contents) into the existing directory at
+/// `destination` in a single `IFileOperation` with the given operation flags if supported, with the
+/// same return values as [`remove_dir_all`].
+pub fn copy_items(
+    sources: &[&Path],
+    destination: &Path,
+    flags: FILEOP_FLAGS,
+) -> io::Result<Option<()>> {
+    let sources = sources
+        .iter()
+        .map(|path| resolve_absolute_path_utf16(path))
+        .collect::<io::Result<Vec<_>>>()?;
+    let destination = resolve_absolute_path_utf16(destination)?;
+
+    with_file_operation(
+        flags,
+        move |sh_create_item_from_parsing_name, file_op| unsafe {
+            let bind_ctx = create_bind_ctx()?;
+            let destination =
+                create_item(sh_create_item_from_parsing_name, &bind_ctx, &destination)?;
+            let items = sources
+                .iter()
+                .map(|path| create_item(sh_create_item_from_parsing_name, &bind_ctx, path))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            for item in &items {
+                hresult_to_result(
+                    file_op.CopyItem(
+                        item.as_ptr(),
+                        destination.as_ptr(),
+                        ptr::null(),
+                        ptr::null_mut(),
+                    ),
+                    "`IFileOperation::CopyItem()`",
+                )?;
+            }
+
+            Ok(())
+        },
+    )
+}
+
+/// Creates an `IFileOperation` with the given operation flags, queues operations on it using
+/// `queue`, and performs them if `IFileOperation` is supported.
+///
+/// Returns `Ok(None)` if `IFileOperation` is not supported, without calling `queue`. The operation
+/// is run on the current thread if it was set up by `ComApartment`, or on a separate
+/// apartment-threaded COM thread otherwise.
+fn with_file_operation<F>(flags: FILEOP_FLAGS, queue: F) -> io::Result<Option<()>>
+where
+    F: FnOnce(SHCreateItemFromParsingNameFn, &IFileOperation) -> io::Result<()> + Send + 'static,
+{
     SH_CREATE_ITEM_FROM_PARSING_NAME_INIT.call_once(|| unsafe {
         // Attempt to dynamically load `SHCreateItemFromParsingName` from `shell32.dll` (which
         // should be linked with the program and already loaded, otherwise `SHFileOperationW` would
@@ -356,130 +497,143 @@ pub fn remove_dir_all(path: &Path) -> io::Result<Option<()>> {
         None => return Ok(None),
     };
 
-    let path = resolve_absolute_path_utf16(path)?;
+    let perform = move || -> io::Result<()> {
+        let file_op = create_file_operation(flags)?;
+        queue(sh_create_item_from_parsing_name, &file_op)?;
+        perform_operations(&file_op)
+    };
+
+    // Threads set up by `ComApartment` are already apartment-threaded, so the operation can be
+    // performed directly on them.
+    if COM_APARTMENT_ACTIVE.with(Cell::get) {
+        return perform().map(Some);
+    }
 
     // `IFileOperation` only supports use in an apartment-threaded COM thread, so spawn a separate
     // thread for the operation to avoid any potential conflicts with the application's COM
     // apartment configuration.
-    let thread_work = move || -> io::Result<_> {
-        unsafe {
-            // Create an `IBindCtx` to restrict searches to filesystem paths.
-            let mut p_bind_ctx = ptr::null_mut::<IBindCtx>();
-            let result = CreateBindCtx(0, &mut p_bind_ctx);
-            let bind_ctx_opt = ComRef::new(p_bind_ctx);
-            let bind_ctx = hresult_to_result(result, "`CreateBindCtx`").and_then(|_| {
-                bind_ctx_opt.ok_or_else(|| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        "`CreateBindCtx` succeeded but did not create an `IBindCtx`.",
-                    )
-                })
-            })?;
-
-            // The "Parsing With Parameters" sample uses `const_cast` to cast away `const`-ness on
-            // object parameter strings, so I can only assume it is safe for us to do the same.
-            let true_obj = Unknown::allocate();
-            hresult_to_result(
-                bind_ctx.RegisterObjectParam(
-                    STR_PARSE_PREFER_FOLDER_BROWSING.as_ptr() as *mut _,
-                    true_obj.as_ptr(),
-                ),
-                "`IBindCtx::RegisterObjectParam`",
-            )?;
+    let handle = thread::spawn(move || {
+        let _apartment = ComApartment::initialize()?;
+        perform()
+    });
 
-            let mut p_item = ptr::null_mut::<IShellItem>();
-            let result = (sh_create_item_from_parsing_name)(
-                strip_extended_length_path_prefix(&path).as_ptr(),
-                bind_ctx.as_ptr(),
-                &IShellItem::uuidof(),
-                &mut p_item as *mut _ as *mut _,
-            );
-            let item_opt = ComRef::new(p_item);
-            let item =
-                hresult_to_result(result, "`SHCreateItemFromParsingName`").and_then(|_| {
-                    item_opt.ok_or_else(|| {
-                        io::Error::new(
-                            io::ErrorKind::Other,
-                            concat!(
-                                "`SHCreateItemFromParsingName` succeeded but did not create an ",
-                                "`IShellItem`."
-                            ),
-                        )
-                    })
-                })?;
-
-            // Double-check the target is a valid filesystem directory. Since Windows distinguishes
-            // between file and folder symbolic links, `SFGAO_FOLDER` should be set regardless of
-            // whether the target is a symbolic link. `GetAttributes` will return `S_OK` if and only
-            // if the attributes match exactly.
-            let mut attributes = 0;
-            if item.GetAttributes(SFGAO_FOLDER | SFGAO_FILESYSTEM, &mut attributes) != S_OK {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Target is not a directory or directory symlink.",
-                ));
-            }
+    // Propagate panics within the worker thread by unwrapping.
+    handle.join().unwrap().map(Some)
+}
 
-            let mut p_file_op = ptr::null_mut::<IFileOperation>();
-            let result = CoCreateInstance(
-                &FileOperation::uuidof(),
-                ptr::null_mut(),
-                CLSCTX_INPROC_SERVER,
-                &IFileOperation::uuidof(),
-                &mut p_file_op as *mut _ as *mut _,
-            );
-            let file_op_opt = ComRef::new(p_file_op);
-            let file_op =
-                hresult_to_result(result, "`IFileOperation` creation").and_then(|_| {
-                    file_op_opt.ok_or_else(|| {
-                        io::Error::new(
-                            io::ErrorKind::Other,
-                            "`CoCreateInstance` succeded but did not create an `IFileOperation`.",
-                        )
-                    })
-                })?;
+/// Creates an `IBindCtx` restricting `SHCreateItemFromParsingName` to filesystem paths.
+///
+/// This must be called from an apartment-threaded COM thread.
+unsafe fn create_bind_ctx() -> io::Result<ComRef<IBindCtx>> {
+    let mut p_bind_ctx = ptr::null_mut::<IBindCtx>();
+    let result = CreateBindCtx(0, &mut p_bind_ctx);
+    let bind_ctx_opt = ComRef::new(p_bind_ctx);
+    let bind_ctx = hresult_to_result(result, "`CreateBindCtx`").and_then(|_| {
+        bind_ctx_opt.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "`CreateBindCtx` succeeded but did not create an `IBindCtx`.",
+            )
+        })
+    })?;
+
+    // The "Parsing With Parameters" sample uses `const_cast` to cast away `const`-ness on object
+    // parameter strings, so I can only assume it is safe for us to do the same.
+    let true_obj = Unknown::allocate();
+    hresult_to_result(
+        bind_ctx.RegisterObjectParam(
+            STR_PARSE_PREFER_FOLDER_BROWSING.as_ptr() as *mut _,
+            true_obj.as_ptr(),
+        ),
+        "`IBindCtx::RegisterObjectParam`",
+    )?;
+
+    Ok(bind_ctx)
+}
 
-            hresult_to_result(
-                file_op.SetOperationFlags(FOF_NO_UI.into()),
-                "`IFileOperation::SetOperationFlags()`",
-            )?;
-            hresult_to_result(
-                file_op.DeleteItem(item.as_ptr(), ptr::null_mut()),
-                "`IFileOperation::DeleteItem()`",
-            )?;
+/// Creates an `IShellItem` for the absolute, nul-terminated UTF-16 `path`.
+///
+/// This must be called from an apartment-threaded COM thread.
+unsafe fn create_item(
+    sh_create_item_from_parsing_name: SHCreateItemFromParsingNameFn,
+    bind_ctx: &ComRef<IBindCtx>,
+    path: &[u16],
+) -> io::Result<ComRef<IShellItem>> {
+    let mut p_item = ptr::null_mut::<IShellItem>();
+    let result = (sh_create_item_from_parsing_name)(
+        strip_extended_length_path_prefix(path).as_ptr(),
+        bind_ctx.as_ptr(),
+        &IShellItem::uuidof(),
+        &mut p_item as *mut _ as *mut _,
+    );
+    let item_opt = ComRef::new(p_item);
+    hresult_to_result(result, "`SHCreateItemFromParsingName`").and_then(|_| {
+        item_opt.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                concat!(
+                    "`SHCreateItemFromParsingName` succeeded but did not create an ",
+                    "`IShellItem`."
+                ),
+            )
+        })
+    })
+}
 
-            hresult_to_result(
-                file_op.PerformOperations(),
-                "`IFileOperation::PerformOperations`",
-            )?;
+/// Creates an `IFileOperation` with the given operation flags.
+///
+/// This must be called from an apartment-threaded COM thread.
+fn create_file_operation(flags: FILEOP_FLAGS) -> io::Result<ComRef<IFileOperation>> {
+    unsafe {
+        let mut p_file_op = ptr::null_mut::<IFileOperation>();
+        let result = CoCreateInstance(
+            &FileOperation::uuidof(),
+            ptr::null_mut(),
+            CLSCTX_INPROC_SERVER,
+            &IFileOperation::uuidof(),
+            &mut p_file_op as *mut _ as *mut _,
+        );
+        let file_op_opt = ComRef::new(p_file_op);
+        let file_op = hresult_to_result(result, "`IFileOperation` creation").and_then(|_| {
+            file_op_opt.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    "`CoCreateInstance` succeded but did not create an `IFileOperation`.",
+                )
+            })
+        })?;
 
-            let mut aborted = FALSE;
-            hresult_to_result(
-                file_op.GetAnyOperationsAborted(&mut aborted),
-                "`IFileOperation::GetAnyOperationsAborted`",
-            )?;
-            if aborted != FALSE {
-                return Err(io::Error::new(
-                    io::ErrorKind::Interrupted,
-                    "Operation aborted before completion.",
-                ));
-            }
+        hresult_to_result(
+            file_op.SetOperationFlags(flags.into()),
+            "`IFileOperation::SetOperationFlags()`",
+        )?;
 
-            Ok(())
-        }
-    };
+        Ok(file_op)
+    }
+}
 
-    let handle = thread::spawn(move || unsafe {
+/// Performs the operations queued on `file_op`, failing if any were aborted.
+///
+/// This must be called from an apartment-threaded COM thread.
+fn perform_operations(file_op: &IFileOperation) -> io::Result<()> {
+    unsafe {
         hresult_to_result(
-            CoInitializeEx(ptr::null_mut(), COINIT_APARTMENTTHREADED),
-            "`CoInitializeEx`",
+            file_op.PerformOperations(),
+            "`IFileOperation::PerformOperations`",
         )?;
-        let result = thread_work();
-        CoUninitialize();
 
-        result
-    });
+        let mut aborted = FALSE;
+        hresult_to_result(
+            file_op.GetAnyOperationsAborted(&mut aborted),
+            "`IFileOperation::GetAnyOperationsAborted`",
+        )?;
+        if aborted != FALSE {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Operation aborted before completion.",
+            ));
+        }
 
-    // Propagate panics within the worker thread by unwrapping.
-    handle.join().unwrap().map(Some)
+        Ok(())
+    }
 }
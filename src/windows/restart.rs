@@ -0,0 +1,134 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Holder identification using Restart Manager.
+
+use super::{resolve_absolute_path_utf16, strip_extended_length_path_prefix};
+use crate::{HoldKind, Holder};
+use std::{fs, io, path::Path, ptr};
+use winapi::{
+    shared::{
+        minwindef::{DWORD, UINT},
+        winerror::{ERROR_MORE_DATA, ERROR_SUCCESS},
+    },
+    um::{
+        restartmanager::{
+            RmEndSession, RmGetList, RmRegisterResources, RmStartSession, CCH_RM_SESSION_KEY,
+            RM_PROCESS_INFO,
+        },
+        winnt::LPCWSTR,
+    },
+};
+
+/// Returns the processes using any file within the directory tree at `path`.
+///
+/// Restart Manager only tracks files, so processes whose working directory is within the tree are
+/// not reported unless they also hold a file open.
+pub(crate) fn find_holders(path: &Path) -> io::Result<Vec<Holder>> {
+    let mut files = Vec::new();
+    collect_files(path, &mut files)?;
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut session = 0;
+    let mut session_key = [0u16; CCH_RM_SESSION_KEY + 1];
+    check(unsafe { RmStartSession(&mut session, 0, session_key.as_mut_ptr()) })?;
+    let holders = list_holders(session, &files);
+    unsafe {
+        RmEndSession(session);
+    }
+
+    holders
+}
+
+/// Registers `files` (nul-terminated UTF-16 paths) with the Restart Manager session, and returns
+/// the processes using any of them.
+fn list_holders(session: DWORD, files: &[Vec<u16>]) -> io::Result<Vec<Holder>> {
+    let mut file_names: Vec<LPCWSTR> = files
+        .iter()
+        .map(|file| strip_extended_length_path_prefix(file).as_ptr())
+        .collect();
+    check(unsafe {
+        RmRegisterResources(
+            session,
+            file_names.len() as UINT,
+            file_names.as_mut_ptr(),
+            0,
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+        )
+    })?;
+
+    // The list may grow between calls, so keep retrying until it fits.
+    let mut processes: Vec<RM_PROCESS_INFO> = Vec::new();
+    loop {
+        let mut needed = 0;
+        let mut count = processes.capacity() as UINT;
+        let mut reboot_reasons = 0;
+        let result = unsafe {
+            RmGetList(
+                session,
+                &mut needed,
+                &mut count,
+                processes.as_mut_ptr(),
+                &mut reboot_reasons,
+            )
+        };
+        match result {
+            ERROR_SUCCESS => {
+                unsafe {
+                    processes.set_len(count as usize);
+                }
+                break;
+            }
+            ERROR_MORE_DATA => processes.reserve_exact(needed as usize),
+            error => return Err(io::Error::from_raw_os_error(error as i32)),
+        }
+    }
+
+    Ok(processes
+        .iter()
+        .map(|process| {
+            let name = &process.strAppName;
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            Holder {
+                pid: process.Process.dwProcessId,
+                name: Some(String::from_utf16_lossy(&name[..len])).filter(|name| !name.is_empty()),
+                kind: HoldKind::OpenFile,
+                path: None,
+            }
+        })
+        .collect())
+}
+
+/// Appends the absolute path of every file within the directory at `path` to `files`, without
+/// following links.
+fn collect_files(path: &Path, files: &mut Vec<Vec<u16>>) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(resolve_absolute_path_utf16(&entry.path())?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts a Restart Manager result code into an `io::Result`.
+fn check(result: DWORD) -> io::Result<()> {
+    if result == ERROR_SUCCESS {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(result as i32))
+    }
+}
@@ -6,16 +6,17 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! `remove_dir_all` implementation using `SHFileOperationW` from the Shell API.
+//! Directory removal and copying using `SHFileOperationW` from the Shell API.
 
 use super::{resolve_absolute_path_utf16, strip_extended_length_path_prefix};
+use crate::batch::MultiStringBuilder;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use std::{convert::TryFrom, io, path::Path, ptr};
 use winapi::{
-    shared::minwindef::FALSE,
+    shared::minwindef::{FALSE, WORD},
     um::{
         fileapi::{GetFileAttributesW, INVALID_FILE_ATTRIBUTES},
-        shellapi::{SHFileOperationW, FOF_NO_UI, FO_DELETE, SHFILEOPSTRUCTW},
+        shellapi::{SHFileOperationW, FILEOP_FLAGS, FO_COPY, FO_DELETE, SHFILEOPSTRUCTW},
         winnt::FILE_ATTRIBUTE_DIRECTORY,
     },
 };
@@ -123,34 +124,80 @@ impl ErrorCode {
     }
 }
 
-/// Deletes a directory and all of its contenst using `SHFileOperationW`.
-pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+/// Deletes a directory and all of its contenst using `SHFileOperationW` with the given operation
+/// flags.
+pub fn remove_dir_all(path: &Path, flags: FILEOP_FLAGS) -> io::Result<()> {
+    remove_dirs_all(&[path], flags)
+}
+
+/// Deletes several directories and all of their contents in a single `SHFileOperationW` call with
+/// the given operation flags.
+///
+/// Nothing is deleted if any of the paths is not a directory or directory symlink.
+pub fn remove_dirs_all(paths: &[&Path], flags: FILEOP_FLAGS) -> io::Result<()> {
     // `SHFileOperationW` requires the input string to be double nul-terminated, as single nul
     // characters are used to delimit multiple path input.
-    let mut path = resolve_absolute_path_utf16(path)?;
-    path.push(0);
-
-    // Make sure the target is a directory or a directory symlink. Since Windows distinguishes
-    // between file and folder symbolic links, `FILE_ATTRIBUTE_DIRECTORY` should be set regardless
-    // of whether the target is a symbolic link.
-    let attributes = unsafe { GetFileAttributesW(path.as_ptr()) };
-    if attributes == INVALID_FILE_ATTRIBUTES {
-        return Err(io::Error::last_os_error());
+    let mut sources = MultiStringBuilder::new();
+    for path in paths {
+        let mut path = resolve_absolute_path_utf16(path)?;
+
+        // Make sure the target is a directory or a directory symlink. Since Windows distinguishes
+        // between file and folder symbolic links, `FILE_ATTRIBUTE_DIRECTORY` should be set
+        // regardless of whether the target is a symbolic link.
+        let attributes = unsafe { GetFileAttributesW(path.as_ptr()) };
+        if attributes == INVALID_FILE_ATTRIBUTES {
+            return Err(io::Error::last_os_error());
+        }
+
+        if (attributes & FILE_ATTRIBUTE_DIRECTORY) == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Target is not a directory or directory symlink.",
+            ));
+        }
+
+        path.pop();
+        sources.push(strip_extended_length_path_prefix(&path))?;
     }
+    let sources = sources.build();
 
-    if (attributes & FILE_ATTRIBUTE_DIRECTORY) == 0 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Target is not a directory or directory symlink.",
-        ));
+    perform_operation(FO_DELETE, &sources, None, flags)
+}
+
+/// Copies the items at `sources` (along with all their contents) into the existing directory at
+/// `destination` in a single `SHFileOperationW` call with the given operation flags.
+pub fn copy_items(sources: &[&Path], destination: &Path, flags: FILEOP_FLAGS) -> io::Result<()> {
+    let mut source_list = MultiStringBuilder::new();
+    for path in sources {
+        let mut path = resolve_absolute_path_utf16(path)?;
+        path.pop();
+        source_list.push(strip_extended_length_path_prefix(&path))?;
     }
+    let sources = source_list.build();
+
+    let mut destination = resolve_absolute_path_utf16(destination)?;
+    destination.pop();
+    let mut destination_list = MultiStringBuilder::new();
+    destination_list.push(strip_extended_length_path_prefix(&destination))?;
+    let destination = destination_list.build();
+
+    perform_operation(FO_COPY, &sources, Some(&destination), flags)
+}
 
+/// Performs a single `SHFileOperationW` call of the given type on the double-nul-terminated
+/// `sources` and `destination` lists.
+fn perform_operation(
+    function: WORD,
+    sources: &[u16],
+    destination: Option<&[u16]>,
+    flags: FILEOP_FLAGS,
+) -> io::Result<()> {
     let mut file_op = SHFILEOPSTRUCTW {
         hwnd: ptr::null_mut(),
-        wFunc: FO_DELETE.into(),
-        pFrom: strip_extended_length_path_prefix(&path).as_ptr(),
-        pTo: ptr::null(),
-        fFlags: FOF_NO_UI,
+        wFunc: function.into(),
+        pFrom: sources.as_ptr(),
+        pTo: destination.map_or(ptr::null(), <[u16]>::as_ptr),
+        fFlags: flags,
         fAnyOperationsAborted: FALSE,
         hNameMappings: ptr::null_mut(),
         lpszProgressTitle: ptr::null(),
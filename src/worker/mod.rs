@@ -0,0 +1,212 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reusable removal sessions backed by a long-lived worker thread.
+//!
+//! A [`Worker`] owns a single thread that is set up once by an initializer (which, for
+//! `Remover` on Windows, initializes an apartment-threaded COM apartment) and then runs queued
+//! jobs one at a time until it is dropped. The worker itself is platform-independent.
+
+#[cfg(test)]
+mod tests;
+
+use crate::{remove, RemovalReport, RemoveOptions};
+use std::{
+    any::Any,
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{mpsc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+/// Job queued for a [`Worker`].
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Result of a job, with any panic caught so that it can be resumed on the submitting thread.
+type JobResult<T> = Result<T, Box<dyn Any + Send>>;
+
+/// Dedicated thread running queued jobs in order.
+#[derive(Debug)]
+pub(crate) struct Worker {
+    jobs: Mutex<Option<mpsc::Sender<Job>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Spawns a worker thread, running `init` on it before any jobs.
+    ///
+    /// The value returned by `init` is kept alive on the worker thread until the worker shuts
+    /// down, so it can be used to tear down any per-thread state. If `init` fails, its error is
+    /// returned here and the thread exits.
+    pub fn spawn<I, G>(name: &str, init: I) -> io::Result<Self>
+    where
+        I: FnOnce() -> io::Result<G> + Send + 'static,
+        G: 'static,
+    {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready, init_result) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || {
+                let _guard = match init() {
+                    Ok(guard) => {
+                        let _ = ready.send(Ok(()));
+                        guard
+                    }
+                    Err(error) => {
+                        let _ = ready.send(Err(error));
+                        return;
+                    }
+                };
+
+                for job in queue {
+                    job();
+                }
+            })?;
+
+        let worker = Self {
+            jobs: Mutex::new(Some(jobs)),
+            thread: Some(thread),
+        };
+        match init_result.recv() {
+            Ok(Ok(())) => Ok(worker),
+            Ok(Err(error)) => Err(error),
+            // The initializer panicked, so resume the panic here.
+            Err(_) => {
+                let mut worker = worker;
+                match worker.thread.take().map(JoinHandle::join) {
+                    Some(Err(payload)) => panic::resume_unwind(payload),
+                    _ => unreachable!("worker exited without reporting initialization"),
+                }
+            }
+        }
+    }
+
+    /// Runs `job` on the worker thread, blocking until it completes and returning its result.
+    ///
+    /// Jobs submitted from multiple threads are run one at a time in submission order. A panic
+    /// within `job` is resumed on the calling thread, leaving the worker available for other jobs.
+    pub fn run<F, T>(&self, job: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_sender, result) = mpsc::channel::<JobResult<T>>();
+        let job: Job = Box::new(move || {
+            let _ = result_sender.send(panic::catch_unwind(AssertUnwindSafe(job)));
+        });
+
+        let sent = self
+            .jobs
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .as_ref()
+            .map_or(false, |jobs| jobs.send(job).is_ok());
+        assert!(sent, "worker thread is no longer running");
+
+        match result.recv() {
+            Ok(Ok(value)) => value,
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => panic!("worker thread exited before completing a job"),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the queue lets the thread finish any pending jobs and exit.
+        self.jobs
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Reusable removal session, running every removal on a single long-lived worker thread.
+///
+/// On Windows, the worker thread is initialized for apartment-threaded COM once, when the session
+/// is created, rather than a new thread being spawned and initialized for each `IFileOperation`
+/// removal. This makes a `Remover` considerably cheaper than repeated calls to
+/// [`remove_dir_all`](fn.remove_dir_all.html) when removing many directories, such as temporary
+/// directories in a test harness. On other platforms, removals simply run on the worker thread.
+///
+/// A `Remover` can be shared between threads, in which case removals are queued and run one at a
+/// time. Dropping it waits for any queued removals to finish before shutting the worker down.
+///
+/// # Examples
+///
+/// ```no_run
+/// use win32_remove_dir_all::Remover;
+///
+/// fn main() -> std::io::Result<()> {
+///     let remover = Remover::new()?;
+///     for i in 0..1000 {
+///         remover.remove_dir_all(format!("scratch/{}", i))?;
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Remover {
+    worker: Worker,
+}
+
+impl Remover {
+    /// Starts a new removal session, spawning its worker thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread could not be spawned or, on Windows, if COM could not be
+    /// initialized on it.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            worker: Worker::spawn("remove-dir-all", initialize_thread)?,
+        })
+    }
+
+    /// Removes a directory at this path, after removing all its contents, on the worker thread.
+    ///
+    /// See [`remove_dir_all`](fn.remove_dir_all.html).
+    pub fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.remove_dir_all_with(path, &RemoveOptions::new())
+            .map(|_| ())
+    }
+
+    /// Removes a directory at this path, after removing all its contents, on the worker thread
+    /// using the specified options.
+    ///
+    /// See [`remove_dir_all_with`](fn.remove_dir_all_with.html).
+    pub fn remove_dir_all_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &RemoveOptions,
+    ) -> io::Result<RemovalReport> {
+        let path = path.as_ref().to_path_buf();
+        let options = options.clone();
+        self.worker
+            .run(move || remove::remove(&path, &options, None))
+    }
+}
+
+/// Initializes a `Remover` worker thread for apartment-threaded COM, so that `IFileOperation` can
+/// be used on it directly.
+#[cfg(all(windows, feature = "property_system_api"))]
+fn initialize_thread() -> io::Result<crate::windows::ComApartment> {
+    crate::windows::ComApartment::initialize()
+}
+
+/// Initializes a `Remover` worker thread, which requires no setup on this platform.
+#[cfg(not(all(windows, feature = "property_system_api")))]
+fn initialize_thread() -> io::Result<()> {
+    Ok(())
+}
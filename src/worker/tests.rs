@@ -0,0 +1,132 @@
+// Copyright 2020 Theodore Cipicchio
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Worker and removal session tests.
+
+use super::{Remover, Worker};
+use crate::engine::tests::populate_dir;
+use std::{
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tempfile::TempDir;
+
+/// Guard recording when it is dropped.
+struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Tests whether the initializer runs once on the worker thread, jobs run on that same thread, and
+/// the initializer's guard is dropped when the worker shuts down.
+#[test]
+fn worker_reuses_initialized_thread() {
+    let inits = Arc::new(AtomicUsize::new(0));
+    let drops = Arc::new(AtomicUsize::new(0));
+
+    let worker = {
+        let inits = Arc::clone(&inits);
+        let drops = Arc::clone(&drops);
+        Worker::spawn("test-worker", move || {
+            inits.fetch_add(1, Ordering::SeqCst);
+            Ok((thread::current().id(), DropCounter(drops)))
+        })
+        .unwrap()
+    };
+
+    let first = worker.run(|| thread::current().id());
+    for _ in 0..100 {
+        assert_eq!(worker.run(|| thread::current().id()), first);
+    }
+    assert_ne!(first, thread::current().id());
+    assert_eq!(
+        worker
+            .run(|| thread::current().name().map(str::to_owned))
+            .as_ref()
+            .map(String::as_str),
+        Some("test-worker")
+    );
+    assert_eq!(inits.load(Ordering::SeqCst), 1);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+    drop(worker);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+}
+
+/// Tests whether initialization errors are returned when spawning the worker.
+#[test]
+fn worker_init_errors_are_returned() {
+    let error = Worker::spawn("test-worker", || -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "init failed"))
+    })
+    .unwrap_err();
+    assert_eq!(error.to_string(), "init failed");
+}
+
+/// Tests whether a panicking job is resumed on the caller without taking down the worker.
+#[test]
+fn worker_survives_job_panics() {
+    let worker = Worker::spawn("test-worker", || Ok(())).unwrap();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| worker.run(|| panic!("job failed"))));
+    assert!(result.is_err());
+    assert_eq!(worker.run(|| 42), 42);
+}
+
+/// Tests whether jobs submitted from several threads are all run.
+#[test]
+fn worker_accepts_concurrent_jobs() {
+    let worker = Arc::new(Worker::spawn("test-worker", || Ok(())).unwrap());
+    let total = Arc::new(AtomicUsize::new(0));
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let worker = Arc::clone(&worker);
+            let total = Arc::clone(&total);
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    let total = Arc::clone(&total);
+                    worker.run(move || total.fetch_add(1, Ordering::SeqCst));
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(total.load(Ordering::SeqCst), 400);
+}
+
+/// Tests whether a removal session removes many directories and reports errors for each.
+#[test]
+fn remover_works() {
+    let base_dir = TempDir::new().unwrap();
+    let remover = Remover::new().unwrap();
+
+    for i in 0..20 {
+        let dir_path = base_dir.path().join(i.to_string());
+        fs::create_dir(&dir_path).unwrap();
+        populate_dir(&dir_path).unwrap();
+
+        remover.remove_dir_all(&dir_path).unwrap();
+        assert!(!dir_path.exists());
+    }
+
+    let error = remover
+        .remove_dir_all(base_dir.path().join("missing"))
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}